@@ -1,17 +1,18 @@
+use ndarray::Array;
 use ort::{
-    environment::Environment,
-    execution_providers::{ExecutionProvider, CPUExecutionProviderOptions},
-    session::SessionBuilder,
-    GraphOptimizationLevel, Value,
+    execution_providers::CPUExecutionProvider,
+    session::{builder::GraphOptimizationLevel, Session},
+    value::Tensor,
 };
-use ndarray::{Array, CowArray};
-use std::{path::Path, sync::Arc};
 use std::thread;
+use std::{path::Path, sync::Arc};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Use CARGO_MANIFEST_DIR to determine the model path dynamically
     let manifest_dir = env!("CARGO_MANIFEST_DIR");
-    let model_path = Path::new(manifest_dir).join("data").join("decision_tree_with_pipeline.onnx");
+    let model_path = Path::new(manifest_dir)
+        .join("data")
+        .join("decision_tree_with_pipeline.onnx");
 
     // Ensure the model path is valid
     if !model_path.exists() {
@@ -22,18 +23,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Loading model from {:?}", model_path);
 
     // Create a single ONNX Runtime environment for the application
-    let environment = Arc::new(
-        Environment::builder()
-            .with_name("InferenceServer")
-            .with_execution_providers([ExecutionProvider::CPU(CPUExecutionProviderOptions::default())])
-            .build()?,
-    );
+    ort::init()
+        .with_name("InferenceServer")
+        .with_execution_providers([CPUExecutionProvider::default().build()])
+        .commit()?;
 
     // Create a session builder for the model
     let session = Arc::new(
-        SessionBuilder::new(&environment)?
+        Session::builder()?
             .with_optimization_level(GraphOptimizationLevel::Level1)?
-            .with_model_from_file(model_path)?,
+            .commit_from_file(model_path)?,
     );
 
     // Start threads for parallel inference
@@ -61,22 +60,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn perform_inference(
-    session: &ort::Session,
+    session: &Session,
     input_tensor: Array<f32, ndarray::Ix2>, // Input tensor as f32
-) -> Result<Vec<i64>, Box<dyn std::error::Error>> { // Output as i64
-    // Convert to CowArray and then to dynamic dimensions
-    let cow_tensor: CowArray<f32, _> = input_tensor.into();
-    let dyn_tensor = cow_tensor.into_dyn();
-
+) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    // Output as i64
     // Create the input Value and run inference
-    let input_value = Value::from_array(session.allocator(), &dyn_tensor)?;
-    let outputs = session.run(vec![input_value])?;
+    let input_value = Tensor::from_array(input_tensor)?;
+    let outputs = session.run(ort::inputs![input_value]?)?;
 
     // Extract the output tensor
-    if let Some(output) = outputs.get(0) {
-        let output_tensor: ort::tensor::OrtOwnedTensor<i64, _> = output.try_extract()?; // Extract as i64
-        Ok(output_tensor.view().as_slice().unwrap().to_vec())
+    if outputs.len() > 0 {
+        let (_, output_tensor) = outputs[0].try_extract_raw_tensor::<i64>()?; // Extract as i64
+        Ok(output_tensor.to_vec())
     } else {
         Err("No output tensor produced".into())
     }
-}
\ No newline at end of file
+}
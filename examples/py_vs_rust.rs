@@ -90,7 +90,10 @@ fn main() {
         "/opt/homebrew/Cellar/python@3.12/3.12.6/Frameworks/Python.framework/Versions/3.12/lib/python3.12/site-packages",
     );
 
-    println!("PYTHON_SYS_EXECUTABLE: {:?}", env::var("PYTHON_SYS_EXECUTABLE"));
+    println!(
+        "PYTHON_SYS_EXECUTABLE: {:?}",
+        env::var("PYTHON_SYS_EXECUTABLE")
+    );
     println!("PYTHONHOME: {:?}", env::var("PYTHONHOME"));
     println!("PYTHONPATH: {:?}", env::var("PYTHONPATH"));
 
@@ -125,7 +128,10 @@ fn main() {
         rust_total_duration += start.elapsed();
     }
     let rust_avg_duration = rust_total_duration / iterations as u32;
-    println!("Rust average duration ({} iterations): {:?}", iterations, rust_avg_duration);
+    println!(
+        "Rust average duration ({} iterations): {:?}",
+        iterations, rust_avg_duration
+    );
 
     // Benchmark Python implementation over 10,000 iterations
     let mut python_total_duration = Duration::ZERO;
@@ -139,4 +145,4 @@ fn main() {
         "Python average duration ({} iterations): {:?}",
         iterations, python_avg_duration
     );
-}
\ No newline at end of file
+}
@@ -0,0 +1,57 @@
+import argparse
+import onnx
+from onnx import checker, helper, TensorProto
+
+# Parse command-line arguments
+parser = argparse.ArgumentParser(description="Derive the ONNX models the tests use from data/decision_tree.onnx.")
+parser.add_argument("--data", default="../data", help="Directory holding decision_tree.onnx, where the models are written.")
+parser.add_argument("--f", help="(vs code interactive argument set, ignore)")
+args = parser.parse_args()
+
+CLASSES = ["no", "yes"]
+
+base = onnx.load(f"{args.data}/decision_tree.onnx")
+
+# The same tree with string class labels, so `label` is a string tensor
+strings = onnx.ModelProto()
+strings.CopyFrom(base)
+tree = strings.graph.node[0]
+int_labels = next(a for a in tree.attribute if a.name == "classlabels_int64s")
+tree.attribute.remove(int_labels)
+tree.attribute.append(helper.make_attribute("classlabels_strings", CLASSES))
+strings.graph.output[0].type.tensor_type.elem_type = TensorProto.STRING
+checker.check_model(strings)
+onnx.save(strings, f"{args.data}/decision_tree_strings.onnx")
+print("Saved decision_tree_strings.onnx")
+
+
+# The probabilities again as one {class: probability} map per row, as skl2onnx's ZipMap outputs them
+def map_sequence(key_type):
+    value = helper.make_tensor_type_proto(TensorProto.FLOAT, None)
+    return helper.make_sequence_type_proto(helper.make_map_type_proto(key_type, value))
+
+
+zipmap = onnx.ModelProto()
+zipmap.CopyFrom(base)
+zipmap.graph.node.extend([
+    helper.make_node("ZipMap", ["probabilities"], ["probability_map"], domain="ai.onnx.ml", classlabels_int64s=[0, 1]),
+    helper.make_node("ZipMap", ["probabilities"], ["class_map"], domain="ai.onnx.ml", classlabels_strings=CLASSES),
+])
+zipmap.graph.output.extend([
+    helper.make_value_info("probability_map", map_sequence(TensorProto.INT64)),
+    helper.make_value_info("class_map", map_sequence(TensorProto.STRING)),
+])
+checker.check_model(zipmap)
+onnx.save(zipmap, f"{args.data}/decision_tree_zipmap.onnx")
+print("Saved decision_tree_zipmap.onnx")
+
+
+# The same tree taking an input of any width, so the width comes from `feature_width` or `indices`
+dynamic = onnx.ModelProto()
+dynamic.CopyFrom(base)
+width = dynamic.graph.input[0].type.tensor_type.shape.dim[1]
+width.ClearField("dim_value")
+width.dim_param = "width"
+checker.check_model(dynamic)
+onnx.save(dynamic, f"{args.data}/decision_tree_dynamic.onnx")
+print("Saved decision_tree_dynamic.onnx")
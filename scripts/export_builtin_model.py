@@ -0,0 +1,54 @@
+import argparse
+import json
+from sklearn.datasets import make_classification, make_regression
+from sklearn.ensemble import RandomForestClassifier
+from sklearn.linear_model import LinearRegression, LogisticRegression
+from sklearn.tree import DecisionTreeClassifier
+
+# Parse command-line arguments
+parser = argparse.ArgumentParser(description="Export a scikit-learn model as a built-in MLModel JSON file.")
+parser.add_argument("--kind", choices=["linear", "logistic", "tree", "forest"], default="logistic")
+parser.add_argument("--out", default="../data/builtin_model.json", help="Where to write the model file.")
+parser.add_argument("--f", help="(vs code interactive argument set, ignore)")
+args = parser.parse_args()
+
+
+def dump_tree(tree):
+    # sklearn keeps value as (nodes, outputs, classes); the built-in model wants one list per node
+    return {
+        "children_left": tree.children_left.tolist(),
+        "children_right": tree.children_right.tolist(),
+        "feature": tree.feature.tolist(),
+        "threshold": tree.threshold.tolist(),
+        "value": tree.value.reshape(tree.node_count, -1).tolist(),
+    }
+
+
+if args.kind == "linear":
+    X, y = make_regression(n_samples=100, n_features=4, random_state=42)
+    model = LinearRegression().fit(X, y)
+    dumped = {"type": "linear", "coefficients": model.coef_.tolist(), "intercept": float(model.intercept_)}
+else:
+    X, y = make_classification(n_samples=100, n_features=4, n_informative=3, n_redundant=1, random_state=42)
+    if args.kind == "logistic":
+        model = LogisticRegression().fit(X, y)
+        dumped = {
+            "type": "logistic",
+            "coefficients": model.coef_.tolist(),
+            "intercept": model.intercept_.tolist(),
+            "activation": "sigmoid" if model.coef_.shape[0] == 1 else "softmax",
+        }
+    elif args.kind == "tree":
+        model = DecisionTreeClassifier(max_depth=3, random_state=42).fit(X, y)
+        dumped = {"type": "tree", "tree": dump_tree(model.tree_), "normalize": True}
+    else:
+        model = RandomForestClassifier(n_estimators=10, max_depth=3, random_state=42).fit(X, y)
+        dumped = {
+            "type": "forest",
+            "trees": [dump_tree(estimator.tree_) for estimator in model.estimators_],
+            "normalize": True,
+        }
+
+with open(args.out, "w") as f:
+    json.dump(dumped, f)
+print(f"{args.kind} model saved as {args.out}")
@@ -1,26 +1,11 @@
-import sys
-import io
+# Imported once by a PyFeatureProcessor with "numpy": true, which then calls `process` with
+# each message's features as a read-only NumPy array (2-D for a pooled batch).
 
-# SETUP - DO NOT REMOVE
+# this must be available in site-packages path you defined
+import numpy as np
 
-# You need to leave this here.
-# You can set your own default dummy values, but this is how we load from Rust.
-input_data = locals().get("input_data", [])
-
-# Redirect stdout to capture prints
-old_stdout = sys.stdout
-sys.stdout = io.StringIO()
-
-# YOU EDIT
-
-
-def process_features(input_data):
-    # this must be available in site-packages path you defined
-    import numpy as np
-
-    # Ensure input is a NumPy array
-    input_data = np.array(input_data)
 
+def process(input_data):
     # Calculate mean and standard deviation for each feature
     mean = np.mean(input_data, axis=0)
     std = np.std(input_data, axis=0)
@@ -33,13 +18,3 @@ def process_features(input_data):
     normalized_data = (input_data - mean) / std
 
     return normalized_data
-
-
-# OUTPUTS - DO NOT REMOVE
-
-# Assuming 'input_data' is passed as a variable in the locals
-output_data = process_features(input_data)
-
-# Capture printed output, which allows Rust to print it after.
-# Unfortunately this means if we errored out we probably didin't see.
-captured_output = sys.stdout.getvalue()
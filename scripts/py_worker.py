@@ -0,0 +1,137 @@
+# Runs a PyFeatureProcessor script in a worker process. The step embeds this file and starts it
+# as `python -c <this file> <script> <function> <numpy> <memory limit in bytes, 0 for none>`; it
+# is not meant to be run by hand.
+#
+# It talks to the step over stdin/stdout in frames: a big-endian u32 length and a JSON header,
+# then a big-endian u32 count and that many little-endian float64 values. After importing the
+# script it sends {"ready": true} (or {"error": ...}), then answers each {"rows": n} request
+# with {"output": "..."} and the function's result, or {"error": {...}}.
+import array
+import contextlib
+import importlib.util
+import io
+import json
+import os
+import resource
+import struct
+import sys
+import traceback
+
+
+def read_exact(stream, size):
+    data = stream.read(size)
+    if len(data) < size:
+        sys.exit(0)  # The step closed the pipe
+    return data
+
+
+def read_frame(stream):
+    (header_len,) = struct.unpack(">I", read_exact(stream, 4))
+    header = json.loads(read_exact(stream, header_len))
+    (count,) = struct.unpack(">I", read_exact(stream, 4))
+    values = array.array("d")
+    values.frombytes(read_exact(stream, 8 * count))
+    if sys.byteorder == "big":
+        values.byteswap()
+    return header, values
+
+
+def write_frame(stream, header, values=()):
+    header = json.dumps(header).encode()
+    values = array.array("d", values)
+    if sys.byteorder == "big":
+        values.byteswap()
+    stream.write(struct.pack(">I", len(header)) + header)
+    stream.write(struct.pack(">I", len(values)) + values.tobytes())
+    stream.flush()
+
+
+def failure(message, output):
+    exception = sys.exc_info()[1]
+    error = {"message": f"{message}: {type(exception).__name__}: {exception}"}
+    error["traceback"] = traceback.format_exc().rstrip()
+    if output:
+        error["output"] = output
+    if isinstance(exception, MemoryError):
+        error["limit"] = "memory"
+    return {"error": error}
+
+
+class BadOutput(Exception):
+    pass
+
+
+def to_floats(result, rows):
+    # NumPy arrays (or anything array-like) are checked for dtype and shape, like in-process
+    dtype = getattr(result, "dtype", None)
+    if dtype is not None:
+        if dtype.kind not in "biuf":
+            raise BadOutput(f"Python function must return numbers, got an array of dtype {dtype}")
+        if result.ndim == 2 and result.shape[0] != max(rows, 1):
+            raise BadOutput(f"Python function returned {result.shape[0]} rows for {max(rows, 1)} input rows")
+        if result.ndim not in (1, 2):
+            raise BadOutput(f"Python function must return a 1-D or 2-D array, got {result.ndim} dimensions")
+        return result.astype("float64").ravel().tolist()
+    try:
+        return [float(value) for value in result]
+    except (TypeError, ValueError) as e:
+        raise BadOutput(f"Python function must return an array or list of numbers: {e}")
+
+
+def main():
+    script, function, numpy = sys.argv[1], sys.argv[2], sys.argv[3] == "1"
+    memory_limit = int(sys.argv[4])
+
+    # Keep the protocol on a private copy of stdout; anything else written to fd 1 goes to stderr
+    requests = sys.stdin.buffer
+    responses = os.fdopen(os.dup(1), "wb")
+    os.dup2(2, 1)
+
+    captured = io.StringIO()
+    try:
+        with contextlib.redirect_stdout(captured), contextlib.redirect_stderr(captured):
+            spec = importlib.util.spec_from_file_location(os.path.splitext(os.path.basename(script))[0], script)
+            module = importlib.util.module_from_spec(spec)
+            spec.loader.exec_module(module)
+            process = getattr(module, function)
+            if numpy:
+                import numpy as np
+    except Exception:
+        write_frame(responses, failure(f"Failed to import Python script '{script}'", captured.getvalue()))
+        return
+    # Capped after the import so the import itself can't fail on it. RLIMIT_AS caps the whole
+    # address space, including the interpreter and the script's imports, so the limit must leave
+    # room above what the worker uses by now; allocations past it raise MemoryError
+    if memory_limit:
+        resource.setrlimit(resource.RLIMIT_AS, (memory_limit, memory_limit))
+    write_frame(responses, {"ready": True, "output": captured.getvalue()})
+
+    while True:
+        header, values = read_frame(requests)
+        rows = header.get("rows", 0)
+        if numpy:
+            features = np.frombuffer(values, dtype=np.float64)
+            if rows > 1:
+                features = features.reshape(rows, -1)
+        else:
+            features = values.tolist()
+
+        captured = io.StringIO()
+        try:
+            with contextlib.redirect_stdout(captured), contextlib.redirect_stderr(captured):
+                result = process(features)
+        except Exception:
+            write_frame(responses, failure("Python function failed", captured.getvalue()))
+            continue
+        try:
+            result = to_floats(result, rows)
+        except BadOutput as e:
+            error = {"message": str(e)}
+            if captured.getvalue():
+                error["output"] = captured.getvalue()
+            write_frame(responses, {"error": error})
+            continue
+        write_frame(responses, {"output": captured.getvalue()}, result)
+
+
+main()
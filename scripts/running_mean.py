@@ -0,0 +1,25 @@
+# A step type written in Python, declared under `python_steps` in a config. Each step of this
+# type gets its own instance, built with the step's params.
+
+
+class RunningMean:
+    """Centres each feature on the mean of everything the step has seen so far."""
+
+    def __init__(self, params):
+        self.decay = params.get("decay", 0.9)
+        self.means = None
+
+    def start(self):
+        print("RunningMean starting")
+
+    def process(self, features):
+        if self.means is None:
+            self.means = list(features)
+        self.means = [self.decay * m + (1 - self.decay) * x for m, x in zip(self.means, features)]
+        return [x - m for x, m in zip(features, self.means)]
+
+    def process_batch(self, rows):
+        return [self.process(row) for row in rows]
+
+    def stop(self):
+        print(f"RunningMean stopping with means {self.means}")
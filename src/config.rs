@@ -1,15 +1,26 @@
 // src/config.rs
 
+use log::info;
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 
 /// Represents the entire DAG configuration, consisting of multiple steps.
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub steps: Vec<StepConfig>,
+    // Optional JSONL file that failed messages are appended to
+    #[serde(default)]
+    pub dead_letter_path: Option<String>,
+    // Step types written in Python, by the type name steps use
+    #[serde(default)]
+    pub python_steps: HashMap<String, PythonStepType>,
     // This is set by the way we run the graph
     #[serde(skip_deserializing)] // This will skip deserializing the field
     pub http_mode: bool, // Add this to represent whether we're in HTTP mode
+    // Also set by the way we run the graph: source steps are not spawned when replaying
+    #[serde(skip_deserializing)]
+    pub replay_mode: bool,
 }
 
 /// Represents a single step in the DAG.
@@ -23,3 +34,80 @@ pub struct StepConfig {
     #[serde(default)]
     pub params: Value,
 }
+
+/// A step type implemented by a Python class, as declared under `python_steps`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PythonStepType {
+    pub python_script: String, // Path to the script defining the class
+    pub class: String,         // Name of the class in it
+    #[serde(default)]
+    pub numpy: bool, // Pass NumPy arrays instead of lists
+}
+
+/// Rewrites a config to answer requests instead of running its own sources, as in http mode:
+/// `DataGenerator`s and `CsvReader`s are removed and the steps they fed read from `http_input`,
+/// the final step becomes an `HttpOutput` named `http_output`, and a `BatchPooler` feeding it gets
+/// a window of 1.
+pub fn adapt_for_requests(config_json: &mut Value) {
+    let mut data_generator_output: Option<String> = None;
+
+    // Remove DataGenerator and store its output name
+    if let Some(steps) = config_json.get_mut("steps").and_then(|v| v.as_array_mut()) {
+        steps.retain(|step| {
+            if step["type"] == "DataGenerator" || step["type"] == "CsvReader" {
+                info!("Removing DataGenerator.");
+                // Safely extract the first output as a string
+                if let Some(output) = step["outputs"]
+                    .as_array()
+                    .and_then(|arr| arr.first().and_then(|v| v.as_str()))
+                {
+                    data_generator_output = Some(output.to_string());
+                }
+                false // Remove the DataGenerator step
+            } else {
+                true // Keep all other steps
+            }
+        });
+
+        // Update the next step's input from DataGenerator's output to "http_input"
+        if let Some(output_name) = data_generator_output {
+            for step in steps.iter_mut() {
+                if let Some(inputs) = step["inputs"].as_array_mut() {
+                    // Find the output name in the inputs and replace it with "http_input"
+                    for input in inputs.iter_mut() {
+                        if input == &json!(output_name) {
+                            *input = json!("http_input");
+                        }
+                    }
+                }
+            }
+        }
+
+        // Find and update the final step
+        if let Some(final_step) = steps.iter_mut().rev().find(|step| {
+            step["outputs"]
+                .as_array()
+                .map(|o| o.is_empty())
+                .unwrap_or(false)
+        }) {
+            info!("Replacing final step with HttpOutput.");
+            let input_name = final_step["inputs"][0].clone();
+            final_step["type"] = json!("HttpOutput");
+            final_step["name"] = json!("http_output");
+            final_step["outputs"] = json!([]); // HttpOutput has no outputs
+            final_step["inputs"] = json!([input_name]);
+
+            // Adjust the BatchPooler preceding the final step
+            if let Some(batch_pooler_step) = steps.iter_mut().find(|step| {
+                step["type"] == "BatchPooler"
+                    && step["outputs"].as_array().unwrap().contains(&input_name)
+            }) {
+                info!(
+                    "Adjusting BatchPooler window size to 1 for '{}'",
+                    batch_pooler_step["name"].as_str().unwrap()
+                );
+                batch_pooler_step["params"]["window_size"] = json!(1);
+            }
+        }
+    }
+}
@@ -1,26 +1,52 @@
-use crate::config::{Config, StepConfig};
-use crate::messages::{Initialize, ProcessMessage};
-use crate::step::Step;
+use crate::config::{Config, PythonStepType, StepConfig};
+use crate::dead_letter::{DeadLetterRecord, DeadLetterWriter};
+use crate::messages::{
+    DeadLetter, GetHealth, Health, Initialize, ProcessMessage, Replay, StepFailing,
+};
+use crate::step::{Step, StepError, TraceStep};
 use crate::steps::batch_pooler::BatchPooler;
 use crate::steps::csv_reader::CsvReader;
 use crate::steps::data_generator::DataGenerator;
+use crate::steps::entity_aggregator::EntityAggregator;
+use crate::steps::feature_lookup::FeatureLookup;
 use crate::steps::feature_processor::FeatureProcessor;
 use crate::steps::http_output::HttpOutput;
 use crate::steps::ml_model::MLModel;
 use crate::steps::printer::Printer;
 use crate::steps::py_feature_processor::PyFeatureProcessor;
+use crate::steps::py_step::PyStep;
 use crate::steps::step_join_point::StepJoinPoint;
 use actix::prelude::*;
 use dashmap::DashMap;
 use log::{error, info, warn};
-use std::collections::{HashMap, HashSet};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::oneshot;
 
+/// The step types `create_step_actor` builds itself.
+const BUILTIN_STEP_TYPES: &[&str] = &[
+    "FeatureProcessor",
+    "EntityAggregator",
+    "FeatureLookup",
+    "PyFeatureProcessor",
+    "MLModel",
+    "DataGenerator",
+    "CsvReader",
+    "StepJoinPoint",
+    "BatchPooler",
+    "Printer",
+    "HttpOutput",
+];
+
 /// Enum to encapsulate different step actors
 enum StepActor {
     FeatureProcessor(Addr<FeatureProcessor>),
+    EntityAggregator(Addr<EntityAggregator>),
+    FeatureLookup(Addr<FeatureLookup>),
     PyFeatureProcessor(Addr<PyFeatureProcessor>),
+    PyStep(Addr<PyStep>),
     MLModel(Addr<MLModel>),
     DataGenerator(Addr<DataGenerator>),
     CsvReader(Addr<CsvReader>),
@@ -34,7 +60,10 @@ impl StepActor {
     fn send_process_message(&self, msg: ProcessMessage) {
         match self {
             StepActor::FeatureProcessor(actor) => actor.do_send(msg),
+            StepActor::EntityAggregator(actor) => actor.do_send(msg),
+            StepActor::FeatureLookup(actor) => actor.do_send(msg),
             StepActor::PyFeatureProcessor(actor) => actor.do_send(msg),
+            StepActor::PyStep(actor) => actor.do_send(msg),
             StepActor::MLModel(actor) => actor.do_send(msg),
             StepActor::DataGenerator(actor) => actor.do_send(msg),
             StepActor::CsvReader(actor) => actor.do_send(msg),
@@ -55,14 +84,22 @@ pub struct Coordinator {
     actors: HashMap<String, StepActor>,
     // Mapping from input name to its downstream step names
     adjacency: HashMap<String, Vec<String>>,
-    sender_map: Option<Arc<DashMap<u64, oneshot::Sender<Vec<f64>>>>>, // Optional sender_map
+    sender_map: Option<Arc<DashMap<u64, oneshot::Sender<ProcessMessage>>>>, // Optional sender_map
+    // Where failed messages are recorded, if the config asks for it
+    dead_letters: Option<DeadLetterWriter>,
+    // Step types written in Python, by type name
+    python_steps: HashMap<String, PythonStepType>,
+    // Steps that have stopped processing messages, and why
+    failing: BTreeMap<String, String>,
+    // Steps that failed to load while handling `Initialize`
+    startup_errors: Vec<String>,
 }
 
 impl Coordinator {
     /// Creates a new Coordinator with the given configuration.
     pub fn new(
         config: Config,
-        sender_map: Option<Arc<DashMap<u64, oneshot::Sender<Vec<f64>>>>>,
+        sender_map: Option<Arc<DashMap<u64, oneshot::Sender<ProcessMessage>>>>,
     ) -> Self {
         // Build adjacency list based on step inputs to downstream steps
         let mut adjacency: HashMap<String, Vec<String>> = HashMap::new();
@@ -75,62 +112,116 @@ impl Coordinator {
             }
         }
 
+        let dead_letters = config.dead_letter_path.clone().map(DeadLetterWriter::new);
+
         Coordinator {
             actors: HashMap::new(),
             adjacency,
             sender_map,
+            dead_letters,
+            python_steps: config.python_steps,
+            failing: BTreeMap::new(),
+            startup_errors: Vec::new(),
         }
     }
 
-    fn create_step_actor(&self, step: &StepConfig, ctx: &mut Context<Self>) -> Option<StepActor> {
+    fn create_step_actor(
+        &mut self,
+        step: &StepConfig,
+        ctx: &mut Context<Self>,
+    ) -> Option<StepActor> {
         match step.node_type.as_str() {
             "FeatureProcessor" => {
                 let output_name = step.outputs.get(0)?;
-                let processor = FeatureProcessor::new_from_params(
+                let mut processor = FeatureProcessor::new_from_params(
                     step.name.clone(),
                     output_name.to_string(),
                     ctx.address(),
                     step.params.clone(),
                 );
+                if let Err(e) = processor.load_transforms() {
+                    return self.configuration_error("FeatureProcessor", step, e);
+                }
                 Some(StepActor::FeatureProcessor(processor.start()))
             }
+            "EntityAggregator" => {
+                let output_name = step.outputs.first()?;
+                let mut aggregator = EntityAggregator::new_from_params(
+                    step.name.clone(),
+                    output_name.to_string(),
+                    ctx.address(),
+                    step.params.clone(),
+                );
+                if let Err(e) = aggregator.load_aggregates() {
+                    return self.configuration_error("EntityAggregator", step, e);
+                }
+                Some(StepActor::EntityAggregator(aggregator.start()))
+            }
+            "FeatureLookup" => {
+                let output_name = step.outputs.first()?;
+                let mut lookup = FeatureLookup::new_from_params(
+                    step.name.clone(),
+                    output_name.to_string(),
+                    ctx.address(),
+                    step.params.clone(),
+                );
+                if let Err(e) = lookup.load_table() {
+                    return self.configuration_error("FeatureLookup", step, e);
+                }
+                Some(StepActor::FeatureLookup(lookup.start()))
+            }
             "PyFeatureProcessor" => {
                 let output_name = step.outputs.get(0)?;
-                let processor = PyFeatureProcessor::new_from_params(
+                let mut processor = PyFeatureProcessor::new_from_params(
                     step.name.clone(),
                     output_name.to_string(),
                     ctx.address(),
                     step.params.clone(),
                 );
+                if let Err(e) = processor.load_script() {
+                    return self.configuration_error("PyFeatureProcessor", step, e);
+                }
                 Some(StepActor::PyFeatureProcessor(processor.start()))
             }
             "MLModel" => {
                 let output_name = step.outputs.get(0)?;
-                let model = MLModel::new_from_params(
+                let mut model = MLModel::new_from_params(
                     step.name.clone(),
                     output_name.to_string(),
                     ctx.address(),
                     step.params.clone(),
                 );
+                // Load the model now so a bad model fails the DAG at startup, not per message
+                if let Err(e) = model.load_model() {
+                    return self.configuration_error("MLModel", step, e);
+                }
                 Some(StepActor::MLModel(model.start()))
             }
             "DataGenerator" => {
                 let output_name = step.outputs.get(0)?;
-                let data_generator = DataGenerator::new_from_params(
+                let mut data_generator = DataGenerator::new_from_params(
                     step.name.clone(),
                     output_name.to_string(),
                     ctx.address(),
                     step.params.clone(),
                 );
+                if let Err(e) = data_generator.load_schema() {
+                    return self.configuration_error("DataGenerator", step, e);
+                }
                 Some(StepActor::DataGenerator(data_generator.start()))
             }
             "CsvReader" => {
-                let csv_reader = CsvReader::new_from_params(
+                let output_name = step.outputs.first()?;
+                let mut csv_reader = CsvReader::new_from_params(
                     step.name.clone(),
-                    step.outputs[0].clone(),
+                    output_name.to_string(),
                     ctx.address(),
                     step.params.clone(),
                 );
+                csv_reader.error_output = step.outputs.get(1).cloned();
+                if let Err(e) = csv_reader.load_file() {
+                    return self.configuration_error("CsvReader", step, e);
+                }
                 Some(StepActor::CsvReader(csv_reader.start()))
             }
             "StepJoinPoint" => {
@@ -151,12 +242,15 @@ impl Coordinator {
                             .collect(),
                     ),
                 );
-                let step_join_point = StepJoinPoint::new_from_params(
+                let mut step_join_point = StepJoinPoint::new_from_params(
                     step.name.clone(),
                     output_name.to_string(),
                     ctx.address(),
                     serde_json::Value::Object(params),
                 );
+                if let Err(e) = step_join_point.load_columns() {
+                    return self.configuration_error("StepJoinPoint", step, e);
+                }
                 Some(StepActor::StepJoinPoint(step_join_point.start()))
             }
             "BatchPooler" => {
@@ -186,6 +280,20 @@ impl Coordinator {
                 );
                 Some(StepActor::HttpOutput(http_output.start()))
             }
+            type_name if self.python_steps.contains_key(type_name) => {
+                let output_name = step.outputs.first()?;
+                let mut py_step = PyStep::new_from_params(
+                    step.name.clone(),
+                    output_name.to_string(),
+                    ctx.address(),
+                    step.params.clone(),
+                );
+                let step_type = self.python_steps[type_name].clone();
+                if let Err(e) = py_step.load_class(type_name, &step_type) {
+                    return self.configuration_error(type_name, step, e);
+                }
+                Some(StepActor::PyStep(py_step.start()))
+            }
             _ => {
                 warn!("Unknown step type: '{}'", step.node_type);
                 None
@@ -193,9 +301,29 @@ impl Coordinator {
         }
     }
 
+    /// Logs a step that failed to load and stops the system, so the DAG fails at startup. The
+    /// error is also returned from `Initialize`.
+    fn configuration_error(
+        &mut self,
+        step_type: &str,
+        step: &StepConfig,
+        error: String,
+    ) -> Option<StepActor> {
+        let error = format!("{} '{}': {}", step_type, step.name, error);
+        error!("Configuration error: {}", error);
+        self.startup_errors.push(error);
+        System::current().stop();
+        None
+    }
+
     /// Spawns actors based on the configuration and registers their addresses.
     fn spawn_actors(&mut self, ctx: &mut Context<Self>, config: &Config) {
         for step in &config.steps {
+            // When replaying, messages come from the dead-letter file instead of the sources
+            if config.replay_mode && step.inputs.is_empty() {
+                info!("Skipping source step '{}' in replay mode", step.name);
+                continue;
+            }
             if let Some(actor) = self.create_step_actor(step, ctx) {
                 self.actors.insert(step.name.clone(), actor);
                 info!("Spawned actor for step '{}'", step.name);
@@ -303,6 +431,16 @@ impl Coordinator {
             }
         }
 
+        // Python step types can't take over a built-in type's name
+        for type_name in config.python_steps.keys() {
+            if BUILTIN_STEP_TYPES.contains(&type_name.as_str()) {
+                errors.push(format!(
+                    "Python step type '{}' has the name of a built-in step type.",
+                    type_name
+                ));
+            }
+        }
+
         let mut names = HashSet::new();
         for step in &config.steps {
             if !names.insert(&step.name) {
@@ -373,21 +511,37 @@ impl Coordinator {
         false
     }
 
-    /// Handles the `Initialize` message, validates the configuration, and spawns actors.
-    fn handle_initialize(&mut self, msg: Initialize, ctx: &mut Context<Self>) {
+    /// Handles the `Initialize` message, validates the configuration, and spawns actors. Returns
+    /// the configuration errors, if any, after logging them and stopping the system.
+    fn handle_initialize(
+        &mut self,
+        msg: Initialize,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Vec<String>> {
         match self.validate_config(&msg.config) {
             Ok(()) => {
                 info!("Configuration validated successfully.");
                 self.spawn_actors(ctx, &msg.config);
+                if self.startup_errors.is_empty() {
+                    Ok(())
+                } else {
+                    Err(std::mem::take(&mut self.startup_errors))
+                }
             }
             Err(errors) => {
-                for error in errors {
+                for error in &errors {
                     error!("Configuration error: {}", error);
                 }
                 System::current().stop();
+                Err(errors)
             }
         }
     }
+
+    /// Checks a configuration without starting anything, returning every problem found.
+    pub fn validate(config: &Config) -> Result<(), Vec<String>> {
+        Coordinator::new(config.clone(), None).validate_config(config)
+    }
 }
 
 impl Actor for Coordinator {
@@ -399,10 +553,10 @@ impl Actor for Coordinator {
 }
 
 impl Handler<Initialize> for Coordinator {
-    type Result = ();
+    type Result = Result<(), Vec<String>>;
 
     fn handle(&mut self, msg: Initialize, ctx: &mut Context<Self>) -> Self::Result {
-        self.handle_initialize(msg, ctx);
+        self.handle_initialize(msg, ctx)
     }
 }
 
@@ -430,3 +584,80 @@ impl Handler<ProcessMessage> for Coordinator {
         }
     }
 }
+
+impl Handler<DeadLetter> for Coordinator {
+    type Result = ();
+
+    fn handle(&mut self, msg: DeadLetter, _ctx: &mut Context<Self>) -> Self::Result {
+        error!(
+            "Step '{}' failed to process message ID={}: {}",
+            msg.step_name, msg.message.id, msg.error
+        );
+
+        if let Some(dead_letters) = self.dead_letters.as_mut() {
+            let record = DeadLetterRecord::new(&msg.step_name, &msg.error, msg.message.clone());
+            dead_letters.append(&record);
+        }
+
+        // Answer the pending HTTP request with the failure right away instead of leaving it hanging
+        if let Some(sender_map) = &self.sender_map {
+            if let Some((_, sender)) = sender_map.remove(&msg.message.id) {
+                let mut failed = msg.message;
+                // Steps that don't record their own failure in the trace get it recorded here
+                if failed.trace.failure().is_none() {
+                    failed.trace.add_step(
+                        TraceStep::new(&msg.step_name, Duration::ZERO, Value::Null)
+                            .with_error(StepError::new(msg.error)),
+                    );
+                }
+                info!(
+                    "Answered pending HTTP request ID={} with the failure in '{}'",
+                    failed.id, msg.step_name
+                );
+                // The request may have timed out already
+                let _ = sender.send(failed);
+            }
+        }
+    }
+}
+
+impl Handler<StepFailing> for Coordinator {
+    type Result = ();
+
+    fn handle(&mut self, msg: StepFailing, _ctx: &mut Context<Self>) -> Self::Result {
+        error!("Step '{}' is failing: {}", msg.step_name, msg.reason);
+        self.failing.insert(msg.step_name, msg.reason);
+    }
+}
+
+impl Handler<GetHealth> for Coordinator {
+    type Result = Health;
+
+    fn handle(&mut self, _msg: GetHealth, _ctx: &mut Context<Self>) -> Self::Result {
+        Health {
+            status: if self.failing.is_empty() {
+                "ok"
+            } else {
+                "failing"
+            }
+            .to_string(),
+            failing: self.failing.clone(),
+        }
+    }
+}
+
+impl Handler<Replay> for Coordinator {
+    type Result = ();
+
+    fn handle(&mut self, msg: Replay, _ctx: &mut Context<Self>) -> Self::Result {
+        if let Some(actor) = self.actors.get(&msg.step_name) {
+            info!(
+                "Replaying message ID={} into step '{}'",
+                msg.message.id, msg.step_name
+            );
+            actor.send_process_message(msg.message);
+        } else {
+            error!("Replay target step '{}' not found", msg.step_name);
+        }
+    }
+}
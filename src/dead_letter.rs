@@ -0,0 +1,112 @@
+// src/dead_letter.rs
+
+use crate::messages::ProcessMessage;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// DeadLetterRecord Struct
+///
+/// A single failed message as written to the dead-letter file (one JSON object per line).
+/// The wrapped `ProcessMessage` carries the payload and its trace up to the point of failure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterRecord {
+    pub step_name: String,       // Name of the step that failed
+    pub error: String,           // Human readable error
+    pub timestamp_ms: u64,       // Milliseconds since the unix epoch when the failure was recorded
+    pub message: ProcessMessage, // The message as it arrived at the failing step
+}
+
+impl DeadLetterRecord {
+    /// Creates a new record stamped with the current time.
+    pub fn new(step_name: &str, error: &str, message: ProcessMessage) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        DeadLetterRecord {
+            step_name: step_name.to_string(),
+            error: error.to_string(),
+            timestamp_ms,
+            message,
+        }
+    }
+}
+
+/// DeadLetterWriter Struct
+///
+/// Appends `DeadLetterRecord`s to a JSONL file. The file is opened lazily on the first write.
+pub struct DeadLetterWriter {
+    pub path: String,
+    file: Option<File>,
+}
+
+impl DeadLetterWriter {
+    pub fn new(path: String) -> Self {
+        DeadLetterWriter { path, file: None }
+    }
+
+    /// Appends a record to the dead-letter file, logging (but not propagating) IO failures.
+    pub fn append(&mut self, record: &DeadLetterRecord) {
+        if self.file.is_none() {
+            match OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+            {
+                Ok(file) => {
+                    info!("Opened dead-letter file '{}'", self.path);
+                    self.file = Some(file);
+                }
+                Err(e) => {
+                    error!("Failed to open dead-letter file '{}': {:?}", self.path, e);
+                    return;
+                }
+            }
+        }
+
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize dead-letter record: {:?}", e);
+                return;
+            }
+        };
+
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = writeln!(file, "{}", line) {
+                error!(
+                    "Failed to write to dead-letter file '{}': {:?}",
+                    self.path, e
+                );
+            }
+        }
+    }
+}
+
+/// Reads every record from a dead-letter file, skipping (and logging) lines that fail to parse.
+pub fn read_dead_letters(path: &str) -> Result<Vec<DeadLetterRecord>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<DeadLetterRecord>(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => error!(
+                "Skipping malformed dead-letter record on line {}: {:?}",
+                line_number + 1,
+                e
+            ),
+        }
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,62 @@
+// src/fit.rs
+
+use crate::config::Config;
+use crate::steps::csv_reader::read_rows;
+use crate::steps::transforms::{fit_transforms, parse_transforms, TransformArtifact};
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+
+/// Fits a `FeatureProcessor` step's transforms on the rows of a CSV file and writes the fitted
+/// transforms to `artifact_path`, or to the step's own `artifact_path` param if none is given.
+/// The CSV is read with the `schema`/`header` params of the config's `CsvReader`, if it has one.
+/// Returns the artifact and where it was written.
+pub fn fit_step(
+    config: &Config,
+    step_name: &str,
+    csv_path: &str,
+    artifact_path: Option<&str>,
+) -> Result<(TransformArtifact, String), Box<dyn Error>> {
+    let step = config
+        .steps
+        .iter()
+        .find(|step| step.name == step_name)
+        .ok_or_else(|| format!("Step '{}' not found in config", step_name))?;
+    if step.node_type != "FeatureProcessor" {
+        return Err(format!(
+            "Step '{}' is a {}, only FeatureProcessor steps can be fitted",
+            step.name, step.node_type
+        )
+        .into());
+    }
+    let artifact_path = artifact_path
+        .or_else(|| step.params.get("artifact_path").and_then(Value::as_str))
+        .ok_or_else(|| {
+            format!(
+                "Step '{}' has no 'artifact_path'; pass --artifact",
+                step.name
+            )
+        })?
+        .to_string();
+
+    let transforms = parse_transforms(&step.params)?;
+    let reader_params = config
+        .steps
+        .iter()
+        .find(|step| step.node_type == "CsvReader")
+        .map(|step| step.params.clone())
+        .unwrap_or_default();
+    let (schema, rows) = read_rows(csv_path, &reader_params)?;
+    if rows.is_empty() {
+        return Err(format!("No rows to fit on in '{}'", csv_path).into());
+    }
+
+    let artifact = TransformArtifact {
+        step: step.name.clone(),
+        source: csv_path.to_string(),
+        rows: rows.len(),
+        transforms: fit_transforms(&transforms, rows, schema.as_ref())?,
+    };
+    fs::write(&artifact_path, serde_json::to_string_pretty(&artifact)?)?;
+    Ok((artifact, artifact_path))
+}
@@ -3,15 +3,18 @@ use std::sync::Arc;
 use actix::Addr;
 use actix_web::{web, HttpResponse};
 use dashmap::DashMap;
-use serde_json::Value;
+use serde_json::{json, Value};
 use tokio::sync::oneshot;
 
-use crate::{coordinator::Coordinator, messages::ProcessMessage};
+use crate::{
+    coordinator::Coordinator,
+    messages::{GetHealth, ProcessMessage},
+};
 
 pub async fn handle_http_request(
     coordinator: web::Data<Addr<Coordinator>>,
     json_payload: web::Json<Value>,
-    sender_map: Option<Arc<DashMap<u64, oneshot::Sender<Vec<f64>>>>>, // Optional sender_map
+    sender_map: Option<Arc<DashMap<u64, oneshot::Sender<ProcessMessage>>>>, // Optional sender_map
 ) -> actix_web::HttpResponse {
     let request_id = rand::random::<u64>(); // Generate a unique ID for this request
 
@@ -25,7 +28,7 @@ pub async fn handle_http_request(
 
     if let Some(sender_map) = sender_map {
         // Access the sender_map's data and process it
-        let (tx, rx) = tokio::sync::oneshot::channel::<Vec<f64>>();
+        let (tx, rx) = tokio::sync::oneshot::channel::<ProcessMessage>();
         sender_map.insert(request_id, tx);
 
         coordinator.do_send(ProcessMessage {
@@ -36,15 +39,37 @@ pub async fn handle_http_request(
             // in http mode we only have batch of 1
             batch_total: Some(1),
             trace: Default::default(),
+            member_ids: vec![],
+            schema: None,
         });
 
         // Wait for the response or return an error if timeout
         match rx.await {
-            Ok(response_data) => {
+            Ok(response) => {
                 sender_map.remove(&request_id); // Clean up the map entry
-                HttpResponse::Ok()
-                    .insert_header(("dag-request-id", request_id.to_string())) // Add the request_id as a header
-                    .json(response_data) // Return the JSON response
+
+                // A step failed: return its error (with any Python traceback and output)
+                if let Some((step, error)) = response.trace.failure() {
+                    return HttpResponse::InternalServerError()
+                        .insert_header(("dag-request-id", request_id.to_string()))
+                        .json(json!({ "step": step, "error": error }));
+                }
+
+                let mut builder = HttpResponse::Ok();
+                builder.insert_header(("dag-request-id", request_id.to_string())); // Add the request_id as a header
+
+                // Name the model variant(s) an A/B split MLModel chose for this request
+                let variants: Vec<&str> = response
+                    .trace
+                    .steps
+                    .iter()
+                    .filter_map(|step| step.variant.as_deref())
+                    .collect();
+                if !variants.is_empty() {
+                    builder.insert_header(("dag-model-variant", variants.join(",")));
+                }
+
+                builder.json(response.data) // Return the JSON response
             }
             Err(_) => {
                 sender_map.remove(&request_id); // Clean up on error
@@ -59,3 +84,13 @@ pub async fn handle_http_request(
             .body("No sender_map available to process the request")
     }
 }
+
+/// Reports the DAG's health: 200 when every step is processing messages, 503 with the failing
+/// steps and their reasons otherwise.
+pub async fn handle_health(coordinator: web::Data<Addr<Coordinator>>) -> actix_web::HttpResponse {
+    match coordinator.send(GetHealth).await {
+        Ok(health) if health.failing.is_empty() => HttpResponse::Ok().json(health),
+        Ok(health) => HttpResponse::ServiceUnavailable().json(health),
+        Err(_) => HttpResponse::ServiceUnavailable().body("Coordinator is not running"),
+    }
+}
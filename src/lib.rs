@@ -2,8 +2,15 @@
 
 pub mod config;
 pub mod coordinator;
+pub mod dead_letter;
+pub mod fit;
 pub mod graph_visualizer;
 pub mod http_input_handler;
 pub mod messages;
+pub mod model_inspector;
+pub mod pipeline;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod schema;
 pub mod step;
 pub mod steps;
@@ -1,16 +1,22 @@
 use actix::prelude::*;
 use actix_web::{web, App, HttpServer};
+use actor_poc::dead_letter::read_dead_letters;
+use actor_poc::fit::fit_step;
 use actor_poc::graph_visualizer::{convert_to_step_nodes, create_graph, export_graph_to_image};
-use actor_poc::{config::Config, http_input_handler::handle_http_request};
+use actor_poc::model_inspector::{check_step, print_model_summary};
+use actor_poc::{
+    config::{adapt_for_requests, Config},
+    http_input_handler::{handle_health, handle_http_request},
+};
 use clap::{Arg, Command};
 use env_logger::Env;
-use serde_json::{json, Value};
+use serde_json::Value;
 use std::error::Error;
 use std::fs;
 use std::sync::Arc;
 
 use actor_poc::coordinator::Coordinator;
-use actor_poc::messages::Initialize;
+use actor_poc::messages::{Initialize, ProcessMessage, Replay};
 use dashmap::DashMap;
 use log::{debug, info};
 use tokio::sync::oneshot;
@@ -27,7 +33,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .long("config")
                 .value_name("FILE")
                 .help("Sets a custom configuration file in JSON format")
-                .required(true)
+                .required_unless_present("inspect_model")
                 .value_parser(clap::value_parser!(String)),
         )
         .arg(
@@ -67,6 +73,71 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .help("Sets the port for the Actix Web server (default is 8080)")
                 .value_parser(clap::value_parser!(u16)),
         )
+        .arg(
+            Arg::new("dead_letter")
+                .long("dead-letter")
+                .value_name("FILE")
+                .help("Appends messages that fail in a step to this JSONL file (overrides `dead_letter_path` in the config)")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("DEAD_LETTER_FILE")
+                .help("Replays the records of a dead-letter file into the step given by --replay-step instead of running the sources")
+                .requires("replay_step")
+                .conflicts_with("http")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("replay_step")
+                .long("replay-step")
+                .value_name("STEP")
+                .help("Name of the step that replayed messages are delivered to")
+                .requires("replay")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("inspect_model")
+                .long("inspect-model")
+                .value_name("MODEL")
+                .help("Prints the input/output names, dtypes and shapes, opsets and metadata of an ONNX model, then exits")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("check_step")
+                .long("check-step")
+                .value_name("STEP")
+                .help("With --inspect-model, checks the params of this MLModel step in --config against the model")
+                .requires("inspect_model")
+                .requires("config")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("fit")
+                .long("fit")
+                .value_name("CSV")
+                .help("Fits the transforms of the FeatureProcessor given by --fit-step on this CSV and writes the statistics to its artifact, then exits")
+                .requires("fit_step")
+                .requires("config")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("fit_step")
+                .long("fit-step")
+                .value_name("STEP")
+                .help("Name of the FeatureProcessor step to fit")
+                .requires("fit")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("artifact")
+                .long("artifact")
+                .value_name("FILE")
+                .help("With --fit, writes the artifact here (overrides `artifact_path` in the step's params)")
+                .requires("fit")
+                .value_parser(clap::value_parser!(String)),
+        )
         .get_matches();
 
     // Initialize the logger based on the verbose flag
@@ -77,6 +148,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
     };
     env_logger::Builder::from_env(Env::default().default_filter_or(log_level)).init();
 
+    // Inspect an ONNX model (and optionally check a step's params against it) without running the DAG
+    if let Some(model_path) = matches.get_one::<String>("inspect_model") {
+        print_model_summary(model_path)?;
+
+        if let Some(step_name) = matches.get_one::<String>("check_step") {
+            let config_file = matches
+                .get_one::<String>("config")
+                .ok_or("--check-step requires --config")?;
+            let config: Config = serde_json::from_str(&fs::read_to_string(config_file)?)?;
+            let step = config
+                .steps
+                .iter()
+                .find(|step| &step.name == step_name)
+                .ok_or_else(|| format!("Step '{}' not found in config", step_name))?;
+
+            check_step(model_path, step)?;
+            println!("Step '{}' matches the model", step_name);
+        }
+        return Ok(());
+    }
+
     // Get the configuration file path and load the JSON
     let config_file = matches
         .get_one::<String>("config")
@@ -86,6 +178,28 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Parse the configuration file as JSON
     let mut config_json: Value = serde_json::from_str(&config_content)?;
 
+    // Fit a FeatureProcessor's transforms on a CSV and write the artifact it serves from
+    if let Some(csv_path) = matches.get_one::<String>("fit") {
+        let step_name = matches
+            .get_one::<String>("fit_step")
+            .ok_or("--fit requires --fit-step")?;
+        let config: Config = serde_json::from_value(config_json)?;
+        let artifact_path = matches.get_one::<String>("artifact").map(String::as_str);
+        let (artifact, artifact_path) = fit_step(&config, step_name, csv_path, artifact_path)?;
+        println!(
+            "Fitted {} transforms of step '{}' on {} rows of '{}'",
+            artifact.transforms.len(),
+            step_name,
+            artifact.rows,
+            csv_path
+        );
+        for transform in &artifact.transforms {
+            println!("  {}", serde_json::to_string(transform)?);
+        }
+        println!("Wrote {}", artifact_path);
+        return Ok(());
+    }
+
     if matches.contains_id("graph") {
         // If the graph argument was provided, use it, otherwise create a default path
         let graph_output_path = if let Some(path) = matches.get_one::<String>("graph") {
@@ -121,68 +235,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     info!("Starting the Actor-based DAG system.");
     // Modify the JSON config based on HTTP mode
     if http_mode {
-        let mut data_generator_output: Option<String> = None;
-
-        // Remove DataGenerator and store its output name
-        if let Some(steps) = config_json.get_mut("steps").and_then(|v| v.as_array_mut()) {
-            steps.retain(|step| {
-                if step["type"] == "DataGenerator" || step["type"] == "CsvReader" {
-                    info!("Removing DataGenerator.");
-                    // Safely extract the first output as a string
-                    if let Some(output) = step["outputs"]
-                        .as_array()
-                        .and_then(|arr| arr.get(0).and_then(|v| v.as_str()))
-                    {
-                        data_generator_output = Some(output.to_string());
-                    }
-                    false // Remove the DataGenerator step
-                } else {
-                    true // Keep all other steps
-                }
-            });
-
-            // Update the next step's input from DataGenerator's output to "http_input"
-            if let Some(output_name) = data_generator_output {
-                for step in steps.iter_mut() {
-                    if let Some(inputs) = step["inputs"].as_array_mut() {
-                        // Find the output name in the inputs and replace it with "http_input"
-                        for input in inputs.iter_mut() {
-                            if input == &json!(output_name) {
-                                *input = json!("http_input");
-                            }
-                        }
-                    }
-                }
-            }
-
-            // Find and update the final step
-            if let Some(final_step) = steps.iter_mut().rev().find(|step| {
-                step["outputs"]
-                    .as_array()
-                    .map(|o| o.is_empty())
-                    .unwrap_or(false)
-            }) {
-                info!("Replacing final step with HttpOutput.");
-                let input_name = final_step["inputs"][0].clone();
-                final_step["type"] = json!("HttpOutput");
-                final_step["name"] = json!("http_output");
-                final_step["outputs"] = json!([]); // HttpOutput has no outputs
-                final_step["inputs"] = json!([input_name]);
-
-                // Adjust the BatchPooler preceding the final step
-                if let Some(batch_pooler_step) = steps.iter_mut().find(|step| {
-                    step["type"] == "BatchPooler"
-                        && step["outputs"].as_array().unwrap().contains(&input_name)
-                }) {
-                    info!(
-                        "Adjusting BatchPooler window size to 1 for '{}'",
-                        batch_pooler_step["name"].as_str().unwrap()
-                    );
-                    batch_pooler_step["params"]["window_size"] = json!(1);
-                }
-            }
-        }
-
+        adapt_for_requests(&mut config_json);
         debug!(
             "http mode on, changed config_json to: {}",
             serde_json::to_string_pretty(&config_json).unwrap()
@@ -192,10 +245,49 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Deserialize the modified JSON into the Config struct
     let mut config: Config = serde_json::from_value(config_json)?;
     config.http_mode = http_mode;
+    if let Some(dead_letter_path) = matches.get_one::<String>("dead_letter") {
+        config.dead_letter_path = Some(dead_letter_path.clone());
+    }
+
+    // Replay mode: feed dead-letter records into a chosen step instead of running the sources
+    if let Some(replay_file) = matches.get_one::<String>("replay") {
+        let replay_step = matches
+            .get_one::<String>("replay_step")
+            .ok_or("--replay requires --replay-step")?;
+        if !config.steps.iter().any(|step| &step.name == replay_step) {
+            return Err(format!("Replay step '{}' not found in config", replay_step).into());
+        }
+
+        let records = read_dead_letters(replay_file)?;
+        info!(
+            "Replaying {} dead-letter records from '{}' into step '{}'",
+            records.len(),
+            replay_file,
+            replay_step
+        );
+
+        config.replay_mode = true;
+        let coordinator = Coordinator::new(config.clone(), None).start();
+        coordinator.do_send(Initialize { config });
+        for record in records {
+            coordinator.do_send(Replay {
+                step_name: replay_step.clone(),
+                message: record.message,
+            });
+        }
+
+        let timeout: u64 = *matches.get_one::<u64>("timeout").unwrap_or(&30);
+        info!("Using timeout of {} seconds", timeout);
+        actix::clock::sleep(std::time::Duration::from_secs(timeout)).await;
+        System::current().stop();
+        return Ok(());
+    }
 
     // If http_mode is enabled, create a sender_map, otherwise set it to None
     let sender_map = if http_mode {
-        Some(Arc::new(DashMap::<u64, oneshot::Sender<Vec<f64>>>::new()))
+        Some(Arc::new(
+            DashMap::<u64, oneshot::Sender<ProcessMessage>>::new(),
+        ))
     } else {
         None
     };
@@ -224,6 +316,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                         // Clone the Arc inside Option
                     }),
                 )
+                .route("/health", web::get().to(handle_health))
         })
         .bind(("0.0.0.0", port))?
         .run();
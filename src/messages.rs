@@ -1,9 +1,11 @@
 // src/messages.rs
 
-use crate::{config::Config, step::Trace};
+use crate::{config::Config, schema::Schema, step::Trace};
 use actix::prelude::*;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 /// ProcessMessage
 ///
@@ -18,11 +20,66 @@ pub struct ProcessMessage {
     pub batch_id: Option<u64>,    // Optional batch identifier (if applicable)
     pub batch_total: Option<u32>, // Optional total number of messages in the batch (if applicable)
     pub trace: Trace,             // Trace data for tracking the message through the pipeline
+    #[serde(default)]
+    pub member_ids: Vec<u64>, // IDs of the messages pooled into this one, in row order (set by BatchPooler)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Arc<Schema>>, // Names and types of the features in `data` (per row), if known
 }
 
-/// Message to initialize the Coordinator with the configuration
+/// Message to initialize the Coordinator with the configuration. Answered with the configuration
+/// errors, if any.
 #[derive(Message)]
-#[rtype(result = "()")]
+#[rtype(result = "Result<(), Vec<String>>")]
 pub struct Initialize {
     pub config: Config,
 }
+
+/// DeadLetter
+///
+/// Sent by a step to the Coordinator when it fails to process a message.
+/// The Coordinator records it in the dead-letter file (if configured) instead of dropping it silently.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct DeadLetter {
+    pub step_name: String,       // Name of the step that failed
+    pub error: String,           // What went wrong
+    pub message: ProcessMessage, // The message that could not be processed
+}
+
+/// Replay
+///
+/// Asks the Coordinator to deliver a message directly to a named step, bypassing the adjacency lookup.
+/// Used to feed dead-letter records back into the DAG.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct Replay {
+    pub step_name: String,       // Step that should receive the message
+    pub message: ProcessMessage, // Message to deliver
+}
+
+/// StepFailing
+///
+/// Sent by a step to the Coordinator when it stops processing messages, like a Python step that
+/// keeps exceeding its resource limits. The health endpoint reports it.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct StepFailing {
+    pub step_name: String, // Name of the step that is failing
+    pub reason: String,    // Why it stopped processing
+}
+
+/// GetHealth
+///
+/// Asks the Coordinator which steps are failing, for the health endpoint.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "Health")]
+pub struct GetHealth;
+
+/// Health
+///
+/// The DAG's health: "ok", or "failing" with the reason for each failing step.
+#[derive(MessageResponse, Debug, Clone, Serialize)]
+pub struct Health {
+    pub status: String,
+    pub failing: BTreeMap<String, String>, // Step name to reason
+}
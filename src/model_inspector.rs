@@ -0,0 +1,223 @@
+// src/model_inspector.rs
+
+use crate::config::StepConfig;
+#[cfg(all(feature = "onnxruntime", not(feature = "tract")))]
+use crate::steps::onnx::load_session;
+use crate::steps::OnnxModel;
+use std::error::Error;
+use std::fs;
+#[cfg(feature = "tract")]
+use tract_onnx::prelude::*;
+
+/// An operator set the model imports, e.g. `ai.onnx` version 17.
+#[derive(Debug, Clone)]
+pub struct OpsetImport {
+    pub domain: String,
+    pub version: i64,
+}
+
+/// Loads the model through onnxruntime, the runtime `MLModel` uses in this build, and prints
+/// its inputs, outputs, opsets and metadata properties.
+#[cfg(all(feature = "onnxruntime", not(feature = "tract")))]
+pub fn print_model_summary(model_path: &str) -> Result<(), Box<dyn Error>> {
+    let session = load_session(model_path)?;
+
+    println!("Model: {}", model_path);
+    println!("Runtime: onnxruntime");
+
+    println!("Inputs:");
+    for input in &session.inputs {
+        println!("  {}: {}", input.name, input.input_type);
+    }
+
+    println!("Outputs:");
+    for output in &session.outputs {
+        println!("  {}: {}", output.name, output.output_type);
+    }
+
+    print_opsets(model_path)?;
+
+    let metadata = session.metadata()?;
+    println!("Metadata:");
+    println!("  name: {}", metadata.name()?);
+    println!("  producer: {}", metadata.producer()?);
+    println!("  version: {}", metadata.version()?);
+    println!("  description: {}", metadata.description()?);
+    for key in metadata.custom_keys()? {
+        let value = metadata.custom(&key)?.unwrap_or_default();
+        println!("  {}: {}", key, value);
+    }
+
+    Ok(())
+}
+
+/// Loads the model through tract, the runtime `MLModel` uses in this build, and prints its
+/// inputs, outputs, opsets and metadata properties. Types and shapes are as the file declares
+/// them, before `MLModel` fixes the batch axis.
+#[cfg(feature = "tract")]
+pub fn print_model_summary(model_path: &str) -> Result<(), Box<dyn Error>> {
+    let onnx = tract_onnx::onnx();
+    let proto = onnx
+        .proto_model_for_path(model_path)
+        .map_err(|e| format!("Failed to read ONNX model '{}': {:?}", model_path, e))?;
+    let model = onnx
+        .model_for_proto_model(&proto)
+        .and_then(|mut model| model.analyse(false).map(|_| model))
+        .map_err(|e| format!("Failed to load ONNX model '{}': {:?}", model_path, e))?;
+
+    println!("Model: {}", model_path);
+    println!("Runtime: tract");
+
+    println!("Inputs:");
+    for (index, outlet) in model.input_outlets()?.iter().enumerate() {
+        let fact = model.input_fact(index)?;
+        println!("  {}: {:?}", model.node(outlet.node).name, fact);
+    }
+
+    println!("Outputs:");
+    for (index, outlet) in model.output_outlets()?.iter().enumerate() {
+        let fact = model.output_fact(index)?;
+        let name = model
+            .outlet_label(*outlet)
+            .unwrap_or(&model.node(outlet.node).name);
+        println!("  {}: {:?}", name, fact);
+    }
+
+    print_opsets(model_path)?;
+
+    println!("Metadata:");
+    let graph_name = proto.graph.as_ref().map(|graph| graph.name.as_str());
+    println!("  name: {}", graph_name.unwrap_or_default());
+    println!("  producer: {}", proto.producer_name);
+    println!("  version: {}", proto.model_version);
+    println!("  description: {}", proto.doc_string);
+    for property in &proto.metadata_props {
+        println!("  {}: {}", property.key, property.value);
+    }
+
+    Ok(())
+}
+
+/// Without an ONNX runtime only the opsets can be read from the file.
+#[cfg(not(any(feature = "onnxruntime", feature = "tract")))]
+pub fn print_model_summary(model_path: &str) -> Result<(), Box<dyn Error>> {
+    println!("Model: {}", model_path);
+
+    print_opsets(model_path)?;
+
+    Err("inputs, outputs and metadata need the `onnxruntime` or `tract` feature".into())
+}
+
+fn print_opsets(model_path: &str) -> Result<(), Box<dyn Error>> {
+    println!("Opsets:");
+    for opset in read_opset_imports(model_path)? {
+        println!("  {}: {}", opset.domain, opset.version);
+    }
+    Ok(())
+}
+
+/// Checks that an `MLModel` step's params (`inputs`, `outputs`, `feature_width`) fit the model,
+/// using the same validation the step runs at startup.
+pub fn check_step(model_path: &str, step: &StepConfig) -> Result<(), String> {
+    if step.node_type != "MLModel" {
+        return Err(format!(
+            "Step '{}' is a {}, not an MLModel",
+            step.name, step.node_type
+        ));
+    }
+
+    let feature_width = step
+        .params
+        .get("feature_width")
+        .and_then(|w| w.as_u64())
+        .map(|w| w as usize);
+
+    OnnxModel::load(model_path, &step.params, feature_width)?;
+    Ok(())
+}
+
+/// Reads the `opset_import` entries of an ONNX file. The runtime doesn't expose them, so this
+/// walks the top level of the `ModelProto` protobuf (field 8) directly.
+pub fn read_opset_imports(model_path: &str) -> Result<Vec<OpsetImport>, Box<dyn Error>> {
+    let bytes = fs::read(model_path)?;
+    let mut opsets = Vec::new();
+
+    for (field, value) in protobuf_fields(&bytes)? {
+        if let (8, FieldValue::Bytes(opset)) = (field, value) {
+            let mut domain = String::new();
+            let mut version = 0;
+            for (field, value) in protobuf_fields(opset)? {
+                match (field, value) {
+                    (1, FieldValue::Bytes(bytes)) => {
+                        domain = String::from_utf8_lossy(bytes).to_string()
+                    }
+                    (2, FieldValue::Varint(value)) => version = value as i64,
+                    _ => {}
+                }
+            }
+            // An empty domain is the default operator set
+            if domain.is_empty() {
+                domain = "ai.onnx".to_string();
+            }
+            opsets.push(OpsetImport { domain, version });
+        }
+    }
+
+    Ok(opsets)
+}
+
+enum FieldValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Splits one protobuf message into its (field number, value) pairs, without descending into them.
+fn protobuf_fields(mut bytes: &[u8]) -> Result<Vec<(u64, FieldValue<'_>)>, Box<dyn Error>> {
+    let mut fields = Vec::new();
+
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        let value = match key & 0x7 {
+            0 => FieldValue::Varint(read_varint(&mut bytes)?),
+            1 | 5 => {
+                let width = if key & 0x7 == 1 { 8 } else { 4 };
+                if bytes.len() < width {
+                    return Err("Truncated ONNX file".into());
+                }
+                bytes = &bytes[width..];
+                FieldValue::Fixed
+            }
+            2 => {
+                let length = read_varint(&mut bytes)? as usize;
+                if bytes.len() < length {
+                    return Err("Truncated ONNX file".into());
+                }
+                let (value, rest) = bytes.split_at(length);
+                bytes = rest;
+                FieldValue::Bytes(value)
+            }
+            wire_type => {
+                return Err(
+                    format!("Unexpected protobuf wire type {} in ONNX file", wire_type).into(),
+                )
+            }
+        };
+        fields.push((key >> 3, value));
+    }
+
+    Ok(fields)
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, Box<dyn Error>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or("Truncated ONNX file")?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("Invalid varint in ONNX file".into())
+}
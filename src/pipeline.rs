@@ -0,0 +1,151 @@
+// src/pipeline.rs
+
+use crate::config::{adapt_for_requests, Config};
+use crate::coordinator::Coordinator;
+use crate::messages::{Initialize, ProcessMessage};
+use actix::prelude::*;
+use dashmap::DashMap;
+use log::info;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Pipeline Struct
+///
+/// Runs a DAG in-process without the HTTP server, for callers that aren't actors themselves, like
+/// the Python package. The config is adapted as in http mode, the actors run on a thread of their
+/// own, and each feature vector fed in comes back as the message leaving the final step, trace
+/// included, or as the message a step failed on.
+pub struct Pipeline {
+    coordinator: Addr<Coordinator>,
+    sender_map: Arc<DashMap<u64, oneshot::Sender<ProcessMessage>>>, // Shared with the HttpOutput
+    system: System,
+    thread: Mutex<Option<JoinHandle<()>>>, // Taken by `stop`
+    next_id: AtomicU64,
+}
+
+/// A feature vector fed into a `Pipeline`, to `wait` for.
+pub struct Submitted {
+    id: u64,
+    receiver: oneshot::Receiver<ProcessMessage>,
+}
+
+impl Pipeline {
+    /// Validates the config and starts its steps, returning every configuration error if the
+    /// config is invalid or a step fails to load.
+    pub fn start(mut config_json: Value) -> Result<Pipeline, Vec<String>> {
+        adapt_for_requests(&mut config_json);
+        let mut config: Config = serde_json::from_value(config_json)
+            .map_err(|e| vec![format!("Failed to parse config: {}", e)])?;
+        config.http_mode = true;
+
+        let sender_map = Arc::new(DashMap::new());
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread_sender_map = sender_map.clone();
+        let thread = thread::spawn(move || {
+            let system = System::new();
+            let (coordinator, initialized) = system.block_on(async move {
+                let coordinator = Coordinator::new(config.clone(), Some(thread_sender_map)).start();
+                let initialized = coordinator.send(Initialize { config }).await;
+                (coordinator, initialized)
+            });
+            let started = match initialized {
+                Ok(Ok(())) => Ok((coordinator, System::current())),
+                Ok(Err(errors)) => Err(errors),
+                Err(e) => Err(vec![format!("Coordinator stopped while starting: {}", e)]),
+            };
+            let failed = started.is_err();
+            if ready_tx.send(started).is_ok() && !failed {
+                let _ = system.run();
+            }
+        });
+
+        let started = ready_rx
+            .recv()
+            .unwrap_or_else(|_| Err(vec!["Pipeline thread panicked while starting".to_string()]));
+        match started {
+            Ok((coordinator, system)) => {
+                info!("Pipeline started.");
+                Ok(Pipeline {
+                    coordinator,
+                    sender_map,
+                    system,
+                    thread: Mutex::new(Some(thread)),
+                    next_id: AtomicU64::new(0),
+                })
+            }
+            Err(errors) => {
+                let _ = thread.join();
+                Err(errors)
+            }
+        }
+    }
+
+    /// Feeds one feature vector in, returning what to `wait` on for the message that comes out.
+    pub fn submit(&self, features: Vec<f64>) -> Submitted {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.sender_map.insert(id, tx);
+        self.coordinator.do_send(ProcessMessage {
+            id,
+            node_id: "http_input".to_string(),
+            data: features,
+            batch_id: Some(id),
+            batch_total: Some(1),
+            trace: Default::default(),
+            member_ids: vec![],
+            schema: None,
+        });
+        Submitted { id, receiver: rx }
+    }
+
+    /// Blocks until a submitted message comes out, or `timeout` passes. A message a step failed on
+    /// comes out too, with the failure in its trace.
+    pub fn wait(&self, submitted: Submitted, timeout: Duration) -> Result<ProcessMessage, String> {
+        let Submitted { id, receiver } = submitted;
+        // The timer needs the actors' runtime, so the wait happens there
+        let (tx, rx) = mpsc::channel();
+        self.system.arbiter().spawn(async move {
+            let _ = tx.send(tokio::time::timeout(timeout, receiver).await);
+        });
+        let result = match rx.recv() {
+            Ok(Ok(Ok(message))) => return Ok(message),
+            Ok(Err(_)) => Err(format!("No output after {:?}", timeout)),
+            Ok(Ok(Err(_))) | Err(_) => Err("Pipeline stopped before answering".to_string()),
+        };
+        // Nothing will wait for the message any more if it still comes out
+        self.sender_map.remove(&id);
+        result
+    }
+
+    /// Number of submitted messages whose output hasn't come out or been given up on.
+    pub fn in_flight(&self) -> usize {
+        self.sender_map.len()
+    }
+
+    /// Feeds one feature vector in and waits for what comes out.
+    pub fn process(&self, features: Vec<f64>, timeout: Duration) -> Result<ProcessMessage, String> {
+        let submitted = self.submit(features);
+        self.wait(submitted, timeout)
+    }
+
+    /// Stops the actors and waits for their thread to finish. Messages still in flight are
+    /// dropped.
+    pub fn stop(&self) {
+        let thread = self.thread.lock().ok().and_then(|mut thread| thread.take());
+        if let Some(thread) = thread {
+            self.system.stop();
+            let _ = thread.join();
+            info!("Pipeline stopped.");
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
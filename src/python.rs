@@ -0,0 +1,250 @@
+// src/python.rs
+
+// The `actor_poc` Python extension module, built by maturin with the `python` feature. It runs
+// DAGs through `Pipeline`, without the HTTP server.
+
+// pyo3 0.16's macros predate these lints
+#![allow(unexpected_cfgs, non_local_definitions)]
+
+use crate::config::{adapt_for_requests, Config};
+use crate::coordinator::Coordinator;
+use crate::messages::ProcessMessage;
+use crate::pipeline::{Pipeline as DagPipeline, Submitted};
+use env_logger::Env;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+create_exception!(actor_poc, PipelineError, PyException);
+
+/// Reads a config given as a path to a JSON file or as a dict.
+fn config_json(py: Python, config: &PyAny) -> PyResult<Value> {
+    let json = if let Ok(path) = config.downcast::<PyString>() {
+        let path = path.to_str()?;
+        fs::read_to_string(path).map_err(|e| {
+            PipelineError::new_err(format!("Failed to read config '{}': {}", path, e))
+        })?
+    } else {
+        py.import("json")?
+            .call_method1("dumps", (config,))?
+            .extract()?
+    };
+    serde_json::from_str(&json)
+        .map_err(|e| PipelineError::new_err(format!("Failed to parse config: {}", e)))
+}
+
+/// Converts JSON to the matching Python objects.
+fn to_python(py: Python, value: &impl serde::Serialize) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| PipelineError::new_err(format!("Failed to convert to Python: {}", e)))?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.into())
+}
+
+fn configuration_errors(errors: Vec<String>) -> PyErr {
+    PipelineError::new_err(format!("Invalid config:\n  {}", errors.join("\n  ")))
+}
+
+/// Checks a config, as a pipeline would run it, returning the problems found (empty when valid).
+#[pyfunction]
+fn validate_config(py: Python, config: &PyAny) -> PyResult<Vec<String>> {
+    let mut config_json = config_json(py, config)?;
+    adapt_for_requests(&mut config_json);
+    let mut config: Config = match serde_json::from_value(config_json) {
+        Ok(config) => config,
+        Err(e) => return Ok(vec![format!("Failed to parse config: {}", e)]),
+    };
+    config.http_mode = true;
+    Ok(Coordinator::validate(&config).err().unwrap_or_default())
+}
+
+/// Output Class
+///
+/// What came out of the pipeline for one feature vector: the final step's `data` and the
+/// message's `trace`, or the `step` that failed on it and its `error`.
+#[pyclass]
+pub struct Output {
+    #[pyo3(get)]
+    id: u64,
+    #[pyo3(get)]
+    data: Vec<f64>,
+    #[pyo3(get)]
+    trace: PyObject, // The trace as a dict
+    #[pyo3(get)]
+    step: Option<String>, // The step that failed, if one did
+    #[pyo3(get)]
+    error: Option<PyObject>, // Its error as a dict: message, and traceback and output for Python
+}
+
+impl Output {
+    fn new(py: Python, message: ProcessMessage) -> PyResult<Output> {
+        let (step, error) = match message.trace.failure() {
+            Some((step, error)) => (Some(step.to_string()), Some(to_python(py, error)?)),
+            None => (None, None),
+        };
+        Ok(Output {
+            id: message.id,
+            trace: to_python(py, &message.trace)?,
+            data: message.data,
+            step,
+            error,
+        })
+    }
+}
+
+#[pymethods]
+impl Output {
+    #[getter]
+    fn ok(&self) -> bool {
+        self.step.is_none()
+    }
+
+    fn __repr__(&self) -> String {
+        match &self.step {
+            Some(step) => format!("Output(id={}, failed in '{}')", self.id, step),
+            None => format!("Output(id={}, data={:?})", self.id, self.data),
+        }
+    }
+}
+
+/// Pipeline Class
+///
+/// A DAG running in this process, built from a config path or dict. `process` feeds one feature
+/// vector in and waits for its `Output`; `process_many` streams an iterable of them through.
+/// The GIL is released while waiting, so Python steps in the DAG can run.
+#[pyclass]
+pub struct Pipeline {
+    inner: Arc<DagPipeline>,
+}
+
+#[pymethods]
+impl Pipeline {
+    #[new]
+    fn new(py: Python, config: &PyAny) -> PyResult<Pipeline> {
+        let config_json = config_json(py, config)?;
+        let inner = py
+            .allow_threads(|| DagPipeline::start(config_json))
+            .map_err(configuration_errors)?;
+        Ok(Pipeline {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Feeds one feature vector in and returns its `Output`, raising `PipelineError` if a step
+    /// fails on it or nothing comes out within `timeout` seconds.
+    #[args(timeout = "30.0")]
+    fn process(&self, py: Python, features: Vec<f64>, timeout: f64) -> PyResult<Output> {
+        let inner = self.inner.clone();
+        let message = py
+            .allow_threads(|| inner.process(features, Duration::from_secs_f64(timeout)))
+            .map_err(PipelineError::new_err)?;
+        if let Some((step, error)) = message.trace.failure() {
+            return Err(PipelineError::new_err(format!(
+                "Step '{}' failed: {}",
+                step, error
+            )));
+        }
+        Output::new(py, message)
+    }
+
+    /// Streams feature vectors through, keeping up to `max_in_flight` in the DAG at once, and
+    /// returns an iterator of their `Output`s in the same order. Failed ones are yielded too,
+    /// with `ok` false.
+    #[args(max_in_flight = "64", timeout = "30.0")]
+    fn process_many(&self, rows: &PyAny, max_in_flight: usize, timeout: f64) -> PyResult<Outputs> {
+        Ok(Outputs {
+            pipeline: self.inner.clone(),
+            rows: rows.iter()?.into(),
+            pending: VecDeque::new(),
+            max_in_flight: max_in_flight.max(1),
+            timeout: Duration::from_secs_f64(timeout),
+        })
+    }
+
+    /// Stops the DAG. Called on leaving a `with` block too.
+    fn close(&self, py: Python) {
+        let inner = self.inner.clone();
+        py.allow_threads(|| inner.stop());
+    }
+
+    fn __enter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        py: Python,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> bool {
+        self.close(py);
+        false
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        // Steps release Python objects while stopping, so they need the GIL back
+        let inner = self.inner.clone();
+        Python::with_gil(|py| py.allow_threads(|| inner.stop()));
+    }
+}
+
+/// Outputs Class
+///
+/// The iterator `Pipeline.process_many` returns. It keeps the DAG fed from the input iterable
+/// while waiting for the oldest output.
+#[pyclass]
+pub struct Outputs {
+    pipeline: Arc<DagPipeline>,
+    rows: PyObject, // Iterator over the feature vectors
+    pending: VecDeque<Submitted>,
+    max_in_flight: usize,
+    timeout: Duration,
+}
+
+#[pymethods]
+impl Outputs {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<Output>> {
+        while self.pending.len() < self.max_in_flight {
+            let row = match self.rows.as_ref(py).iter()?.next() {
+                Some(row) => row?,
+                None => break,
+            };
+            let features: Vec<f64> = row.extract()?;
+            self.pending.push_back(self.pipeline.submit(features));
+        }
+        let submitted = match self.pending.pop_front() {
+            Some(submitted) => submitted,
+            None => return Ok(None),
+        };
+        let (pipeline, timeout) = (self.pipeline.clone(), self.timeout);
+        let message = py
+            .allow_threads(|| pipeline.wait(submitted, timeout))
+            .map_err(PipelineError::new_err)?;
+        Output::new(py, message).map(Some)
+    }
+}
+
+#[pymodule]
+fn actor_poc(py: Python, m: &PyModule) -> PyResult<()> {
+    // Logs go to stderr; RUST_LOG raises the level
+    let _ = env_logger::Builder::from_env(Env::default().default_filter_or("warn")).try_init();
+
+    m.add("PipelineError", py.get_type::<PipelineError>())?;
+    m.add_class::<Pipeline>()?;
+    m.add_class::<Output>()?;
+    m.add_class::<Outputs>()?;
+    m.add_function(wrap_pyfunction!(validate_config, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,239 @@
+// src/schema.rs
+
+use crate::messages::ProcessMessage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// The type a feature is parsed as. Every feature is carried as an `f64` either way.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureType {
+    #[default]
+    Float,
+    Int,
+    Bool,
+}
+
+/// Field Struct
+///
+/// One named feature. In params it is either just the name (a float) or `{"name", "type"}`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(from = "FieldSpec")]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub dtype: FeatureType,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldSpec {
+    Name(String),
+    Typed {
+        name: String,
+        #[serde(rename = "type", default)]
+        dtype: FeatureType,
+    },
+}
+
+impl From<FieldSpec> for Field {
+    fn from(spec: FieldSpec) -> Self {
+        match spec {
+            FieldSpec::Name(name) => Field {
+                name,
+                dtype: FeatureType::Float,
+            },
+            FieldSpec::Typed { name, dtype } => Field { name, dtype },
+        }
+    }
+}
+
+/// A feature addressed by its position or by its name in the message's schema.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+/// Schema Struct
+///
+/// The ordered names and types of the features in a message's `data`. Sources declare it (or take
+/// it from a CSV header) and steps pass it on, so later steps can address features by name.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "Vec<Field>", into = "Vec<Field>")]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl TryFrom<Vec<Field>> for Schema {
+    type Error = String;
+
+    fn try_from(fields: Vec<Field>) -> Result<Self, Self::Error> {
+        Schema::new(fields)
+    }
+}
+
+impl From<Schema> for Vec<Field> {
+    fn from(schema: Schema) -> Self {
+        schema.fields
+    }
+}
+
+impl Schema {
+    /// Creates a schema, checking that every name is non-empty and unique.
+    pub fn new(fields: Vec<Field>) -> Result<Schema, String> {
+        let mut names = HashSet::new();
+        for field in &fields {
+            if field.name.is_empty() {
+                return Err("schema has a feature with an empty name".to_string());
+            }
+            if !names.insert(field.name.as_str()) {
+                return Err(format!("schema names feature '{}' twice", field.name));
+            }
+        }
+        Ok(Schema { fields })
+    }
+
+    /// Reads the optional `schema` param of a source step.
+    pub fn from_params(params: &Value) -> Result<Option<Schema>, String> {
+        match params.get("schema") {
+            None | Some(Value::Null) => Ok(None),
+            Some(schema) => serde_json::from_value(schema.clone())
+                .map(Some)
+                .map_err(|e| format!("Invalid schema: {}", e)),
+        }
+    }
+
+    /// Takes the feature names from the cells of a header record; every feature is a float.
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<Schema, String> {
+        Schema::new(
+            names
+                .into_iter()
+                .map(|name| Field {
+                    name: name.trim().to_string(),
+                    dtype: FeatureType::Float,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn width(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.fields.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name == name)
+    }
+
+    /// Resolves a column to its position. Names need a schema; positions are checked by the caller.
+    pub fn resolve(schema: Option<&Schema>, column: &Column) -> Result<usize, String> {
+        match (column, schema) {
+            (Column::Index(index), _) => Ok(*index),
+            (Column::Name(name), Some(schema)) => schema
+                .index_of(name)
+                .ok_or_else(|| format!("no feature named '{}' in {:?}", name, schema.names())),
+            (Column::Name(name), None) => Err(format!(
+                "feature '{}' is addressed by name but the message has no schema",
+                name
+            )),
+        }
+    }
+
+    /// Parses the cells of one record. Every feature must be present and parse as its type; an
+    /// empty cell is a missing value (NaN).
+    pub fn parse_row(&self, cells: &[&str]) -> Result<Vec<f64>, String> {
+        if cells.len() != self.width() {
+            return Err(format!(
+                "{} cells but the schema has {} features",
+                cells.len(),
+                self.width()
+            ));
+        }
+        self.fields
+            .iter()
+            .zip(cells)
+            .map(|(field, cell)| {
+                let cell = cell.trim();
+                if cell.is_empty() {
+                    return Ok(f64::NAN);
+                }
+                let value = match field.dtype {
+                    FeatureType::Float => cell.parse::<f64>().ok(),
+                    FeatureType::Int => cell.parse::<i64>().ok().map(|v| v as f64),
+                    FeatureType::Bool => match cell.to_ascii_lowercase().as_str() {
+                        "true" | "1" => Some(1.0),
+                        "false" | "0" => Some(0.0),
+                        _ => None,
+                    },
+                };
+                value.ok_or_else(|| {
+                    format!(
+                        "feature '{}' expects {:?}, got '{}'",
+                        field.name, field.dtype, cell
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// The schema of the given columns, in the given order. A column picked again is named with
+    /// the number of its pick, e.g. `amount#2`.
+    pub fn select(&self, indices: &[usize]) -> Result<Schema, String> {
+        let mut picks = vec![0; self.width()];
+        Schema::new(
+            indices
+                .iter()
+                .map(|&i| {
+                    picks[i] += 1;
+                    let mut field = self.fields[i].clone();
+                    if picks[i] > 1 {
+                        field.name = format!("{}#{}", field.name, picks[i]);
+                    }
+                    field
+                })
+                .collect(),
+        )
+    }
+
+    /// Appends features, e.g. ones a transform derives from the existing ones.
+    pub fn extend(&mut self, fields: impl IntoIterator<Item = Field>) -> Result<(), String> {
+        let mut extended = self.fields.clone();
+        extended.extend(fields);
+        *self = Schema::new(extended)?;
+        Ok(())
+    }
+
+    pub fn set_type(&mut self, index: usize, dtype: FeatureType) {
+        self.fields[index].dtype = dtype;
+    }
+}
+
+/// Checks that a message's data is as wide as its schema says (per row, for a pooled batch).
+pub fn check_width(msg: &ProcessMessage) -> Result<(), String> {
+    if let Some(schema) = &msg.schema {
+        let rows = msg.member_ids.len().max(1);
+        if msg.data.len() != schema.width() * rows {
+            return Err(format!(
+                "message has {} values but its schema has {} features{}",
+                msg.data.len(),
+                schema.width(),
+                if rows > 1 {
+                    format!(" in each of {} rows", rows)
+                } else {
+                    String::new()
+                }
+            ));
+        }
+    }
+    Ok(())
+}
@@ -3,6 +3,7 @@ use crate::messages::ProcessMessage;
 use actix::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::fmt;
 use std::time::Duration;
 
 /// Step Trait
@@ -35,6 +36,75 @@ pub struct TraceStep {
     pub node_id: String,    // Name of the step (node)
     pub duration: Duration, // Duration it took to process the step
     pub params: Value,      // Parameters used in the step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchInfo>, // Set when the step processed the message as part of a batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>, // Model variant that handled the message, for A/B split MLModels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>, // Backend that answered, for MLModels with a fallback chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<StepError>, // Set when the step failed on the message
+}
+
+/// StepError Struct
+///
+/// Why a step failed on a message, recorded in the message's trace and returned to HTTP callers.
+/// Steps that run user code attach what it printed and, for Python, the traceback.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepError {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceback: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>, // Captured stdout/stderr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<String>, // The resource limit the step exceeded: "timeout" or "memory"
+}
+
+impl StepError {
+    pub fn new(message: impl Into<String>) -> Self {
+        StepError {
+            message: message.into(),
+            traceback: None,
+            output: None,
+            limit: None,
+        }
+    }
+
+    /// Marks the error as the step exceeding a resource limit.
+    pub fn with_limit(mut self, limit: &str) -> Self {
+        self.limit = Some(limit.to_string());
+        self
+    }
+
+    /// Attaches captured output, ignoring it if empty.
+    pub fn with_output(mut self, output: String) -> Self {
+        self.output = Some(output).filter(|output| !output.is_empty());
+        self
+    }
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(traceback) = &self.traceback {
+            write!(f, "\n{}", traceback.trim_end())?;
+        }
+        if let Some(output) = &self.output {
+            write!(f, "\nCaptured output:\n{}", output.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// BatchInfo Struct
+///
+/// How a message was batched by the step: the number of messages sent together and how long
+/// this one waited for the batch to fill.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchInfo {
+    pub size: usize,
+    pub wait: Duration,
 }
 
 impl TraceStep {
@@ -44,8 +114,36 @@ impl TraceStep {
             node_id: node_id.to_string(),
             duration,
             params,
+            batch: None,
+            variant: None,
+            backend: None,
+            error: None,
         }
     }
+
+    /// Records that the message was processed in a batch of `size` after waiting `wait`.
+    pub fn with_batch(mut self, size: usize, wait: Duration) -> Self {
+        self.batch = Some(BatchInfo { size, wait });
+        self
+    }
+
+    /// Records the model variant that handled the message.
+    pub fn with_variant(mut self, variant: Option<String>) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Records which backend of a fallback chain produced the answer.
+    pub fn with_backend(mut self, backend: &str) -> Self {
+        self.backend = Some(backend.to_string());
+        self
+    }
+
+    /// Records that the step failed on the message.
+    pub fn with_error(mut self, error: StepError) -> Self {
+        self.error = Some(error);
+        self
+    }
 }
 
 /// Trace Struct
@@ -62,6 +160,13 @@ impl Trace {
         self.steps.push(step);
     }
 
+    /// The first step that failed on the message, if any.
+    pub fn failure(&self) -> Option<(&str, &StepError)> {
+        self.steps
+            .iter()
+            .find_map(|step| Some((step.node_id.as_str(), step.error.as_ref()?)))
+    }
+
     /// Combines another trace with this one.
     pub fn merge(&mut self, other: Trace) {
         self.steps.extend(other.steps);
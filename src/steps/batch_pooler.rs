@@ -36,6 +36,23 @@ pub struct BatchPooler {
     pub params: Value,
 }
 
+impl BatchPooler {
+    /// IDs of the original messages in a pooled batch, in row order. Messages that were
+    /// themselves pooled upstream contribute their own member IDs.
+    fn member_ids(batch: &[ProcessMessage]) -> Vec<u64> {
+        batch
+            .iter()
+            .flat_map(|m| {
+                if m.member_ids.is_empty() {
+                    vec![m.id]
+                } else {
+                    m.member_ids.clone()
+                }
+            })
+            .collect()
+    }
+}
+
 impl Step for BatchPooler {
     fn new_from_params(
         name: String,
@@ -128,6 +145,8 @@ impl Handler<ProcessMessage> for BatchPooler {
                         batch_id: msg.batch_id,
                         batch_total: msg.batch_total,
                         trace: msg.trace.clone(),
+                        member_ids: BatchPooler::member_ids(&batch),
+                        schema: msg.schema.clone(),
                     });
                 } else {
                     debug!(
@@ -162,6 +181,8 @@ impl Handler<ProcessMessage> for BatchPooler {
                             batch_id: Some(batch_id),
                             batch_total: Some(batch.len() as u32),
                             trace: msg.trace.clone(),
+                            member_ids: BatchPooler::member_ids(&batch),
+                            schema: msg.schema.clone(),
                         });
                     } else {
                         debug!(
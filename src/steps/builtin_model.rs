@@ -0,0 +1,320 @@
+// src/steps/builtin_model.rs
+
+use serde::Deserialize;
+use std::fs;
+
+/// Coefficients given either as one row (a single output) or one row per output.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum Rows {
+    Many(Vec<Vec<f64>>),
+    One(Vec<f64>),
+}
+
+impl Rows {
+    fn into_rows(self) -> Vec<Vec<f64>> {
+        match self {
+            Rows::Many(rows) => rows,
+            Rows::One(row) => vec![row],
+        }
+    }
+}
+
+/// An intercept given either as one number or one per output.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum Values {
+    Many(Vec<f64>),
+    One(f64),
+}
+
+/// A decision tree as scikit-learn stores it (`estimator.tree_`): parallel arrays indexed by
+/// node, where a node goes left when `x[feature] <= threshold` and leaves have no children (-1).
+#[derive(Deserialize, Debug, Clone)]
+pub struct Tree {
+    children_left: Vec<i64>,
+    children_right: Vec<i64>,
+    feature: Vec<i64>,
+    threshold: Vec<f64>,
+    value: Vec<Vec<f64>>, // Prediction at each node (used at leaves)
+}
+
+/// The JSON model file, as written by `scripts/export_builtin_model.py`.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ModelFile {
+    Linear {
+        coefficients: Rows,
+        #[serde(default)]
+        intercept: Option<Values>,
+    },
+    Logistic {
+        coefficients: Rows,
+        #[serde(default)]
+        intercept: Option<Values>,
+        #[serde(default)]
+        activation: Option<String>, // "sigmoid" or "softmax"; sigmoid for one output, else softmax
+    },
+    Tree {
+        tree: Tree,
+        #[serde(default)]
+        normalize: bool, // Divide leaf values by their sum (class counts to probabilities)
+    },
+    Forest {
+        trees: Vec<Tree>,
+        #[serde(default)]
+        normalize: bool,
+    },
+}
+
+/// A linear model: one row of coefficients and one intercept per output.
+#[derive(Debug, Clone)]
+pub struct Linear {
+    coefficients: Vec<Vec<f64>>,
+    intercepts: Vec<f64>,
+}
+
+impl Linear {
+    fn new(coefficients: Rows, intercept: Option<Values>) -> Result<Self, String> {
+        let coefficients = coefficients.into_rows();
+        let width = coefficients.first().map(|row| row.len()).unwrap_or(0);
+        if width == 0 || coefficients.iter().any(|row| row.len() != width) {
+            return Err("Coefficients must be non-empty rows of equal length".to_string());
+        }
+
+        let intercepts = match intercept {
+            None => vec![0.0; coefficients.len()],
+            Some(Values::One(intercept)) => vec![intercept; coefficients.len()],
+            Some(Values::Many(intercepts)) => intercepts,
+        };
+        if intercepts.len() != coefficients.len() {
+            return Err(format!(
+                "{} intercepts for {} rows of coefficients",
+                intercepts.len(),
+                coefficients.len()
+            ));
+        }
+
+        Ok(Linear {
+            coefficients,
+            intercepts,
+        })
+    }
+
+    fn width(&self) -> usize {
+        self.coefficients[0].len()
+    }
+
+    fn predict(&self, row: &[f64]) -> Vec<f64> {
+        self.coefficients
+            .iter()
+            .zip(&self.intercepts)
+            .map(|(coefficients, intercept)| {
+                coefficients
+                    .iter()
+                    .zip(row)
+                    .map(|(c, x)| c * x)
+                    .sum::<f64>()
+                    + intercept
+            })
+            .collect()
+    }
+}
+
+impl Tree {
+    /// Checks the arrays line up and every path ends at a leaf.
+    fn validate(&self) -> Result<(), String> {
+        let nodes = self.children_left.len();
+        if nodes == 0 {
+            return Err("Tree has no nodes".to_string());
+        }
+        if self.children_right.len() != nodes
+            || self.feature.len() != nodes
+            || self.threshold.len() != nodes
+            || self.value.len() != nodes
+        {
+            return Err("Tree arrays must all have one entry per node".to_string());
+        }
+
+        for node in 0..nodes {
+            let (left, right) = (self.children_left[node], self.children_right[node]);
+            if left == -1 && right == -1 {
+                continue;
+            }
+            // Children always come after their parent, which rules out cycles
+            for child in [left, right] {
+                if child <= node as i64 || child >= nodes as i64 {
+                    return Err(format!("Node {} has invalid child {}", node, child));
+                }
+            }
+            if self.feature[node] < 0 {
+                return Err(format!("Split node {} has no feature", node));
+            }
+        }
+        Ok(())
+    }
+
+    /// Highest feature index the tree splits on.
+    fn max_feature(&self) -> Option<usize> {
+        self.feature
+            .iter()
+            .zip(&self.children_left)
+            .filter(|(_, &left)| left != -1)
+            .map(|(&feature, _)| feature as usize)
+            .max()
+    }
+
+    fn predict(&self, row: &[f64]) -> Result<&[f64], String> {
+        let mut node = 0;
+        while self.children_left[node] != -1 {
+            let feature = self.feature[node] as usize;
+            let x = row.get(feature).ok_or_else(|| {
+                format!(
+                    "Tree splits on feature {} but rows have {} features",
+                    feature,
+                    row.len()
+                )
+            })?;
+            node = if *x <= self.threshold[node] {
+                self.children_left[node] as usize
+            } else {
+                self.children_right[node] as usize
+            };
+        }
+        Ok(&self.value[node])
+    }
+}
+
+/// BuiltinModel Enum
+///
+/// A model evaluated in pure Rust from a JSON parameter file, so simple deployments (and tests)
+/// need no onnxruntime.
+#[derive(Debug, Clone)]
+pub enum BuiltinModel {
+    Linear(Linear),
+    Logistic { linear: Linear, softmax: bool },
+    Forest { trees: Vec<Tree>, normalize: bool }, // A single tree is a forest of one
+}
+
+impl BuiltinModel {
+    /// Reads and validates the model file at `path`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read model file '{}': {}", path, e))?;
+        let file: ModelFile = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid model file '{}': {}", path, e))?;
+
+        let model = match file {
+            ModelFile::Linear {
+                coefficients,
+                intercept,
+            } => BuiltinModel::Linear(Linear::new(coefficients, intercept)?),
+            ModelFile::Logistic {
+                coefficients,
+                intercept,
+                activation,
+            } => {
+                let linear = Linear::new(coefficients, intercept)?;
+                let softmax = match activation.as_deref() {
+                    Some("softmax") => true,
+                    Some("sigmoid") => false,
+                    None => linear.coefficients.len() > 1,
+                    Some(other) => return Err(format!("Unknown activation '{}'", other)),
+                };
+                BuiltinModel::Logistic { linear, softmax }
+            }
+            ModelFile::Tree { tree, normalize } => BuiltinModel::Forest {
+                trees: vec![tree],
+                normalize,
+            },
+            ModelFile::Forest { trees, normalize } => BuiltinModel::Forest { trees, normalize },
+        };
+
+        if let BuiltinModel::Forest { trees, .. } = &model {
+            if trees.is_empty() {
+                return Err("Forest has no trees".to_string());
+            }
+            for (index, tree) in trees.iter().enumerate() {
+                tree.validate()
+                    .map_err(|e| format!("Tree {} in '{}': {}", index, path, e))?;
+            }
+        }
+
+        Ok(model)
+    }
+
+    /// Checks the model against the configured features per row, and returns the width the
+    /// model implies when none is configured (linear models know theirs exactly).
+    pub fn check_width(&self, feature_width: Option<usize>) -> Result<Option<usize>, String> {
+        match self {
+            BuiltinModel::Linear(linear) | BuiltinModel::Logistic { linear, .. } => {
+                match feature_width {
+                    Some(width) if width != linear.width() => Err(format!(
+                        "Model has {} coefficients per output but feature_width is {}",
+                        linear.width(),
+                        width
+                    )),
+                    _ => Ok(Some(linear.width())),
+                }
+            }
+            BuiltinModel::Forest { trees, .. } => {
+                let max_feature = trees.iter().filter_map(|tree| tree.max_feature()).max();
+                match (feature_width, max_feature) {
+                    (Some(width), Some(feature)) if feature >= width => Err(format!(
+                        "Model splits on feature {} but feature_width is {}",
+                        feature, width
+                    )),
+                    _ => Ok(feature_width),
+                }
+            }
+        }
+    }
+
+    /// Predicts one row.
+    pub fn predict_row(&self, row: &[f64]) -> Result<Vec<f64>, String> {
+        match self {
+            BuiltinModel::Linear(linear) => Ok(linear.predict(row)),
+            BuiltinModel::Logistic { linear, softmax } => {
+                let scores = linear.predict(row);
+                if *softmax {
+                    // Subtract the max so large scores don't overflow
+                    let max = scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                    let exps: Vec<f64> = scores.iter().map(|s| (s - max).exp()).collect();
+                    let total: f64 = exps.iter().sum();
+                    Ok(exps.iter().map(|e| e / total).collect())
+                } else {
+                    Ok(scores.iter().map(|s| 1.0 / (1.0 + (-s).exp())).collect())
+                }
+            }
+            BuiltinModel::Forest { trees, normalize } => {
+                let mut sum: Vec<f64> = Vec::new();
+                for tree in trees {
+                    let leaf = tree.predict(row)?;
+                    let total: f64 = leaf.iter().sum();
+                    let leaf = leaf.iter().map(|v| {
+                        if *normalize && total != 0.0 {
+                            v / total
+                        } else {
+                            *v
+                        }
+                    });
+                    if sum.is_empty() {
+                        sum = leaf.collect();
+                    } else {
+                        sum.iter_mut().zip(leaf).for_each(|(s, v)| *s += v);
+                    }
+                }
+                // The forest predicts the mean of its trees
+                Ok(sum.iter().map(|s| s / trees.len() as f64).collect())
+            }
+        }
+    }
+
+    /// Predicts every row of a batch.
+    pub fn predict(&self, data: &[f64], rows: usize) -> Result<Vec<Vec<f64>>, String> {
+        let width = data.len() / rows.max(1);
+        data.chunks(width.max(1))
+            .map(|row| self.predict_row(row))
+            .collect()
+    }
+}
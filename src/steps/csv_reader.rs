@@ -1,14 +1,24 @@
 use actix::{Actor, ActorContext, Addr, Context, Handler};
-use log::info;
+use csv::{ReaderBuilder, StringRecord};
+use log::{error, info, warn};
+use serde::Deserialize;
 use serde_json::Value;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::sync::Arc;
 
-use crate::{coordinator::Coordinator, messages::ProcessMessage, step::Step};
+use crate::{
+    coordinator::Coordinator,
+    messages::{ProcessMessage, StepFailing},
+    schema::{Column, Schema},
+    step::{Step, StepError, Trace, TraceStep},
+};
 
 /// CsvReader Actor
 ///
 /// Responsible for reading feature vectors from a CSV file and sending them to the Coordinator.
+/// With a `schema` param (or `header: true`) every row must have all of the named features, and
+/// the messages carry the schema. Rows that don't parse are skipped and counted, stop the
+/// reading, or go to the step's second output, as `on_error` says.
 pub struct CsvReader {
     pub name: String,
     pub output_name: String,
@@ -22,6 +32,10 @@ pub struct CsvReader {
     pub current_batch_id: u64,
     pub current_batch_count: u32,
     pub params: Value,
+    pub error_output: Option<String>, // Where `on_error: route` sends malformed rows
+    pub skipped_rows: u64,
+    csv_file: Option<CsvFile>, // Set by `load_file` before the actor starts
+    schema: Option<Arc<Schema>>, // Likewise
 }
 
 impl Step for CsvReader {
@@ -60,6 +74,10 @@ impl Step for CsvReader {
             current_batch_id: 1,
             current_batch_count: 0,
             params,
+            error_output: None,
+            skipped_rows: 0,
+            csv_file: None,
+            schema: None,
         }
     }
 
@@ -100,77 +118,455 @@ impl Handler<ProcessMessage> for CsvReader {
     }
 }
 
-impl CsvReader {
-    /// Reads data from the CSV file and sends it to the Coordinator.
-    fn read_and_send_data(&mut self, ctx: &mut Context<Self>) {
-        // Open the CSV file for reading
-        let file = File::open(&self.file_path).expect("Failed to open CSV file");
-        let reader = BufReader::new(file);
+/// What a CsvReader does with a row that doesn't parse, from its `on_error` param.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    #[default]
+    Skip, // Log and count it, and read on
+    Fail,  // Stop reading the file and report the step as failing
+    Route, // Send it to the step's second output, with the error in its trace
+}
 
-        // Read each line from the CSV
-        for line in reader.lines() {
-            let start_time = std::time::Instant::now();
+/// A row that doesn't parse: its line in the file, why, and its cells read as numbers where they
+/// can be (NaN where they can't).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedRow {
+    pub line: u64,
+    pub error: String,
+    pub data: Vec<f64>,
+}
+
+/// One record of the file, as a feature vector or as a row that doesn't parse.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    Features(Vec<f64>),
+    Malformed(MalformedRow),
+}
+
+/// CsvFile Struct
+///
+/// A CSV file as a CsvReader's params say to read it: cells split on `delimiter` (with quoting),
+/// an optional `header` line, an optional `schema` for the whole record and `columns` picking
+/// the features sent, by name or position. Every record must have as many cells as the schema or
+/// header has, or else as the first record.
+pub struct CsvFile {
+    path: String,
+    delimiter: u8,
+    header: bool,
+    on_error: OnError,
+    width: Option<usize>, // Cells per record, when a schema or header gives it
+    columns: Option<Vec<usize>>, // The cells sent, in order; all of them if unset
+    schema: Option<Schema>, // The schema of the features sent
+}
 
-            if let Ok(line) = line {
-                // Parse the CSV line into a vector of f64 values
-                let feature_vector: Vec<f64> = line
-                    .split(',')
-                    .filter_map(|s| s.trim().parse::<f64>().ok())
-                    .collect();
+impl CsvFile {
+    /// Reads the params and the header, checking that the file can be opened, the header agrees
+    /// with a declared schema and every column exists.
+    pub fn open(path: &str, params: &Value) -> Result<CsvFile, String> {
+        let delimiter = parse_delimiter(params)?;
+        let on_error: OnError = match params.get("on_error") {
+            None | Some(Value::Null) => OnError::default(),
+            Some(on_error) => serde_json::from_value(on_error.clone())
+                .map_err(|e| format!("Invalid 'on_error': {}", e))?,
+        };
+        let columns: Option<Vec<Column>> = match params.get("columns") {
+            None | Some(Value::Null) => None,
+            Some(columns) => Some(
+                serde_json::from_value(columns.clone())
+                    .map_err(|e| format!("Invalid 'columns': {}", e))?,
+            ),
+        };
 
-                if feature_vector.is_empty() {
-                    continue; // Skip empty lines or invalid rows
+        let mut csv_file = CsvFile {
+            path: path.to_string(),
+            delimiter,
+            header: params
+                .get("header")
+                .and_then(|v| v.as_bool()) // Whether the first line names the features
+                .unwrap_or(false),
+            on_error,
+            width: None,
+            columns: None,
+            schema: None,
+        };
+
+        let declared = Schema::from_params(params)?;
+        let schema = if csv_file.header {
+            let mut header = StringRecord::new();
+            let read = csv_file
+                .reader()?
+                .read_record(&mut header)
+                .map_err(|e| format!("Failed to read CSV file '{}': {}", path, e))?;
+            if !read {
+                return Err(format!("CSV file '{}' has no header line", path));
+            }
+            let from_header = Schema::from_names(header.iter())
+                .map_err(|e| format!("Invalid header in '{}': {}", path, e))?;
+            match declared {
+                Some(schema) if schema.names() != from_header.names() => {
+                    return Err(format!(
+                        "Header of '{}' names {:?} but the schema names {:?}",
+                        path,
+                        from_header.names(),
+                        schema.names()
+                    ))
                 }
+                Some(schema) => Some(schema),
+                None => Some(from_header),
+            }
+        } else {
+            // Fail now rather than when the actor starts reading
+            csv_file.reader()?;
+            declared
+        };
+        csv_file.width = schema.as_ref().map(Schema::width);
+
+        if let Some(columns) = columns {
+            let indices = columns
+                .iter()
+                .map(|column| {
+                    let index = Schema::resolve(schema.as_ref(), column)?;
+                    match csv_file.width {
+                        Some(width) if index >= width => Err(format!(
+                            "column {} is out of range for {} cells",
+                            index, width
+                        )),
+                        _ => Ok(index),
+                    }
+                })
+                .collect::<Result<Vec<_>, String>>()
+                .map_err(|e| format!("Invalid 'columns': {}", e))?;
+            csv_file.schema = schema.map(|schema| schema.select(&indices)).transpose()?;
+            csv_file.columns = Some(indices);
+        } else {
+            csv_file.schema = schema;
+        }
+        Ok(csv_file)
+    }
+
+    /// The schema of the features sent, if the params or header name them.
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    pub fn on_error(&self) -> OnError {
+        self.on_error
+    }
 
-                let id = self.next_id;
-                self.next_id += 1;
-                self.current_count += 1;
-
-                // Determine batch info
-                let (batch_id, batch_total) = if self.send_batch_info {
-                    if let Some(size) = self.batch_size {
-                        if self.current_batch_count >= size {
-                            self.current_batch_id += 1;
-                            self.current_batch_count = 0;
-                        }
-
-                        let current_id = self.current_batch_id;
-                        let total = size;
-                        self.current_batch_count += 1;
-
-                        (Some(current_id), Some(total))
-                    } else {
-                        (None, None)
+    fn reader(&self) -> Result<csv::Reader<File>, String> {
+        open_csv(&self.path, self.delimiter, false) // The header is read by `open`
+    }
+
+    /// Reads the records after the header. A record that can't be read at all, like one that
+    /// isn't UTF-8, is a malformed row too; an I/O error ends the rows with `Err`.
+    pub fn rows(&self) -> Result<impl Iterator<Item = Result<Row, String>> + '_, String> {
+        let mut records = self.reader()?.into_records().skip(usize::from(self.header));
+        let mut width = self.width;
+        let mut done = false;
+        Ok(std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let row = match records.next()? {
+                Ok(record) => {
+                    let line = record.position().map_or(0, |p| p.line());
+                    let width = *width.get_or_insert(record.len());
+                    match self.parse(&record, width) {
+                        Ok(features) => Row::Features(features),
+                        Err(error) => Row::Malformed(MalformedRow {
+                            line,
+                            error,
+                            data: self.lenient(&record),
+                        }),
                     }
-                } else {
-                    (None, None)
-                };
-
-                // Calculate duration and add trace step
-                let duration = start_time.elapsed();
-                let mut trace: crate::step::Trace = Default::default(); // Start with an empty trace
-                let trace_step =
-                    crate::step::TraceStep::new(&self.name, duration, self.params.clone());
-                trace.add_step(trace_step);
-
-                // Send the feature vector to the Coordinator
-                self.coordinator.do_send(ProcessMessage {
-                    id,
-                    node_id: self.output_name.clone(),
-                    data: feature_vector,
-                    batch_id,
-                    batch_total,
-                    trace,
-                });
-
-                info!(
-                    "CsvReader '{}' processed and sent CSV line with ID {}",
-                    self.name, id
+                }
+                Err(e) if !e.is_io_error() => Row::Malformed(MalformedRow {
+                    line: e.position().map_or(0, |p| p.line()),
+                    error: e.to_string(),
+                    data: vec![],
+                }),
+                Err(e) => {
+                    done = true;
+                    return Some(Err(format!(
+                        "Failed to read CSV file '{}': {}",
+                        self.path, e
+                    )));
+                }
+            };
+            Some(Ok(row))
+        }))
+    }
+
+    /// The cells sent from a record, in order.
+    fn cells<'r>(&self, record: &'r StringRecord) -> Result<Vec<&'r str>, String> {
+        match &self.columns {
+            Some(columns) => columns
+                .iter()
+                .map(|&index| {
+                    record
+                        .get(index)
+                        .ok_or_else(|| format!("no column {} in {} cells", index, record.len()))
+                })
+                .collect(),
+            None => Ok(record.iter().collect()),
+        }
+    }
+
+    /// Parses a record. With a schema every cell must parse as its feature's type; without one,
+    /// as a float. An empty cell is a missing value (NaN).
+    fn parse(&self, record: &StringRecord, width: usize) -> Result<Vec<f64>, String> {
+        if record.len() != width {
+            return Err(format!("{} cells but rows have {}", record.len(), width));
+        }
+        let cells = self.cells(record)?;
+        match &self.schema {
+            Some(schema) => schema.parse_row(&cells),
+            None => cells
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| match cell.trim() {
+                    "" => Ok(f64::NAN),
+                    cell => cell
+                        .parse::<f64>()
+                        .map_err(|_| format!("cell {} is not a number: '{}'", i, cell)),
+                })
+                .collect(),
+        }
+    }
+
+    /// The cells of a malformed record read as numbers where they can be, for routing it on.
+    fn lenient(&self, record: &StringRecord) -> Vec<f64> {
+        let cells: Vec<Option<&str>> = match &self.columns {
+            Some(columns) => columns.iter().map(|&index| record.get(index)).collect(),
+            None => record.iter().map(Some).collect(),
+        };
+        cells
+            .into_iter()
+            .map(|cell| {
+                cell.and_then(|cell| cell.trim().parse::<f64>().ok())
+                    .unwrap_or(f64::NAN)
+            })
+            .collect()
+    }
+}
+
+/// Reads the `delimiter` param of a CSV file: a single ASCII character, `,` by default.
+pub(crate) fn parse_delimiter(params: &Value) -> Result<u8, String> {
+    match params.get("delimiter") {
+        None | Some(Value::Null) => Ok(b','),
+        Some(Value::String(delimiter)) if delimiter.len() == 1 => Ok(delimiter.as_bytes()[0]),
+        Some(delimiter) => Err(format!(
+            "'delimiter' must be a single ASCII character, got {}",
+            delimiter
+        )),
+    }
+}
+
+/// Opens a CSV file split on `delimiter`, taking its first record as the header if `headers`.
+/// Records of any width are read, for the caller to check.
+pub(crate) fn open_csv(
+    path: &str,
+    delimiter: u8,
+    headers: bool,
+) -> Result<csv::Reader<File>, String> {
+    let file =
+        File::open(path).map_err(|e| format!("Failed to open CSV file '{}': {}", path, e))?;
+    Ok(ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(headers)
+        .flexible(true)
+        .from_reader(file))
+}
+
+/// Reads every row a CsvReader with these params would send from the file, e.g. for `--fit`,
+/// along with their schema. Malformed rows are skipped with a warning, unless `on_error` is
+/// `fail`.
+pub fn read_rows(
+    file_path: &str,
+    params: &Value,
+) -> Result<(Option<Schema>, Vec<Vec<f64>>), String> {
+    let csv_file = CsvFile::open(file_path, params)?;
+    let mut rows = Vec::new();
+    let mut skipped = 0;
+    for row in csv_file.rows()? {
+        match row? {
+            Row::Features(features) => rows.push(features),
+            Row::Malformed(row) if csv_file.on_error() == OnError::Fail => {
+                return Err(format!(
+                    "Line {} of '{}': {}",
+                    row.line, file_path, row.error
+                ))
+            }
+            Row::Malformed(row) => {
+                warn!(
+                    "Skipping line {} of '{}': {}",
+                    row.line, file_path, row.error
                 );
+                skipped += 1;
+            }
+        }
+    }
+    if skipped > 0 {
+        warn!("Skipped {} malformed rows of '{}'", skipped, file_path);
+    }
+    Ok((csv_file.schema().cloned(), rows))
+}
+
+impl CsvReader {
+    /// Opens the file and reads its header and the params. The Coordinator calls this while
+    /// handling `Initialize`, so a missing file or bad schema fails the DAG at startup.
+    pub fn load_file(&mut self) -> Result<(), String> {
+        let csv_file = CsvFile::open(&self.file_path, &self.params)?;
+        if csv_file.on_error() == OnError::Route && self.error_output.is_none() {
+            return Err("'on_error' is 'route' but the step has no second output".to_string());
+        }
+        self.schema = csv_file.schema().cloned().map(Arc::new);
+        self.csv_file = Some(csv_file);
+        Ok(())
+    }
+
+    /// Reads data from the CSV file and sends it to the Coordinator.
+    fn read_and_send_data(&mut self, ctx: &mut Context<Self>) {
+        let Some(csv_file) = self.csv_file.take() else {
+            error!("CsvReader '{}' started without its file loaded", self.name);
+            ctx.stop();
+            return;
+        };
+        let rows = match csv_file.rows() {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.fail(e);
+                ctx.stop();
+                return;
+            }
+        };
+
+        let (mut sent, mut routed) = (0u64, 0u64);
+        for row in rows {
+            let start_time = std::time::Instant::now();
+            match row {
+                Ok(Row::Features(feature_vector)) => {
+                    self.send_row(feature_vector, start_time);
+                    sent += 1;
+                }
+                Ok(Row::Malformed(row)) => match csv_file.on_error() {
+                    OnError::Skip => {
+                        // A row that doesn't parse is dropped whole rather than shifted
+                        warn!(
+                            "CsvReader '{}' skipping line {}: {}",
+                            self.name, row.line, row.error
+                        );
+                        self.skipped_rows += 1;
+                    }
+                    OnError::Fail => {
+                        self.fail(format!("line {}: {}", row.line, row.error));
+                        break;
+                    }
+                    OnError::Route => {
+                        self.route_row(row, start_time);
+                        routed += 1;
+                    }
+                },
+                Err(e) => {
+                    self.fail(e);
+                    break;
+                }
             }
         }
 
+        info!(
+            "CsvReader '{}' finished reading '{}': {} rows sent, {} skipped, {} routed",
+            self.name, self.file_path, sent, self.skipped_rows, routed
+        );
         // Stop the actor after processing the file
         ctx.stop();
     }
+
+    /// Sends one feature vector to the Coordinator.
+    fn send_row(&mut self, feature_vector: Vec<f64>, start_time: std::time::Instant) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.current_count += 1;
+
+        // Determine batch info
+        let (batch_id, batch_total) = if self.send_batch_info {
+            if let Some(size) = self.batch_size {
+                if self.current_batch_count >= size {
+                    self.current_batch_id += 1;
+                    self.current_batch_count = 0;
+                }
+
+                let current_id = self.current_batch_id;
+                let total = size;
+                self.current_batch_count += 1;
+
+                (Some(current_id), Some(total))
+            } else {
+                (None, None)
+            }
+        } else {
+            (None, None)
+        };
+
+        // Calculate duration and add trace step
+        let duration = start_time.elapsed();
+        let mut trace: Trace = Default::default(); // Start with an empty trace
+        let trace_step = TraceStep::new(&self.name, duration, self.params.clone());
+        trace.add_step(trace_step);
+
+        // Send the feature vector to the Coordinator
+        self.coordinator.do_send(ProcessMessage {
+            id,
+            node_id: self.output_name.clone(),
+            data: feature_vector,
+            batch_id,
+            batch_total,
+            trace,
+            member_ids: vec![],
+            schema: self.schema.clone(),
+        });
+
+        info!(
+            "CsvReader '{}' processed and sent CSV line with ID {}",
+            self.name, id
+        );
+    }
+
+    /// Sends a malformed row to the error output, with what was wrong in its trace.
+    fn route_row(&mut self, row: MalformedRow, start_time: std::time::Instant) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let error = StepError::new(format!("line {}: {}", row.line, row.error));
+        let mut trace: Trace = Default::default();
+        trace.add_step(
+            TraceStep::new(&self.name, start_time.elapsed(), self.params.clone()).with_error(error),
+        );
+
+        // The cells are not checked against the schema, so the message carries none
+        self.coordinator.do_send(ProcessMessage {
+            id,
+            node_id: self.error_output.clone().unwrap_or_default(),
+            data: row.data,
+            batch_id: None,
+            batch_total: None,
+            trace,
+            member_ids: vec![],
+            schema: None,
+        });
+        warn!(
+            "CsvReader '{}' routed line {} with ID {}: {}",
+            self.name, row.line, id, row.error
+        );
+    }
+
+    /// Stops reading and reports the step as failing.
+    fn fail(&self, reason: String) {
+        error!("CsvReader '{}' stopped reading: {}", self.name, reason);
+        self.coordinator.do_send(StepFailing {
+            step_name: self.name.clone(),
+            reason,
+        });
+    }
 }
@@ -3,11 +3,13 @@ use log::info;
 use rand::Rng;
 use serde_json::Value;
 
-use crate::{coordinator::Coordinator, messages::ProcessMessage, step::Step};
+use crate::{coordinator::Coordinator, messages::ProcessMessage, schema::Schema, step::Step};
+use std::sync::Arc;
 
 /// DataGenerator Actor
 ///
 /// Responsible for generating random feature vectors and sending them to the Coordinator.
+/// With a `schema` param it generates one value per named feature and the messages carry it.
 pub struct DataGenerator {
     pub name: String,
     pub output_name: String,
@@ -21,6 +23,7 @@ pub struct DataGenerator {
     pub current_batch_id: u64,
     pub current_batch_count: u32,
     pub params: Value,
+    schema: Option<Arc<Schema>>, // Set by `load_schema` before the actor starts
 }
 
 impl Step for DataGenerator {
@@ -58,6 +61,7 @@ impl Step for DataGenerator {
             current_batch_id: 1,
             current_batch_count: 0,
             params,
+            schema: None,
         }
     }
 
@@ -99,6 +103,13 @@ impl Handler<ProcessMessage> for DataGenerator {
 }
 
 impl DataGenerator {
+    /// Reads the `schema` param. The Coordinator calls this while handling `Initialize`, so a bad
+    /// schema fails the DAG at startup.
+    pub fn load_schema(&mut self) -> Result<(), String> {
+        self.schema = Schema::from_params(&self.params)?.map(Arc::new);
+        Ok(())
+    }
+
     /// Starts the periodic data generation.
     fn generate_data(&mut self, ctx: &mut Context<Self>) {
         ctx.run_interval(
@@ -119,7 +130,8 @@ impl DataGenerator {
                 }
 
                 // Generate random feature vector
-                let feature_vector = generate_random_features(5);
+                let width = act.schema.as_ref().map(|s| s.width()).unwrap_or(5);
+                let feature_vector = generate_random_features(width);
                 let id = act.next_id;
                 act.next_id += 1;
                 act.current_count += 1;
@@ -159,6 +171,8 @@ impl DataGenerator {
                     batch_id,
                     batch_total,
                     trace,
+                    member_ids: vec![],
+                    schema: act.schema.clone(),
                 });
 
                 info!(
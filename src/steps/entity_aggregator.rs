@@ -0,0 +1,481 @@
+// src/steps/entity_aggregator.rs
+
+use crate::coordinator::Coordinator;
+use crate::messages::{DeadLetter, ProcessMessage};
+use crate::schema::{check_width, Column, FeatureType, Field, Schema};
+use crate::step::{Step, TraceStep};
+use actix::prelude::*;
+use log::{debug, error, info};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How many events a key keeps when the params don't bound its window by count.
+const DEFAULT_MAX_EVENTS: usize = 1000;
+
+/// What an aggregate computes over a key's window (or, for `ewma`, over all its events).
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateKind {
+    Count,
+    Sum,
+    Mean,
+    Min,
+    Max,
+    Ewma,
+}
+
+/// One aggregate appended to every outgoing message, e.g. `{"type": "mean", "column": "amount"}`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Aggregate {
+    #[serde(rename = "type")]
+    pub kind: AggregateKind,
+    #[serde(default)]
+    pub column: Option<Column>, // Feature to aggregate; `count` takes none
+    #[serde(default)]
+    pub alpha: Option<f64>, // Weight of the newest event, for `ewma`
+    #[serde(default)]
+    pub name: Option<String>, // Name in the outgoing schema (default `<column>_<type>`)
+}
+
+impl Aggregate {
+    fn validate(&self) -> Result<(), String> {
+        match (self.kind, &self.column) {
+            (AggregateKind::Count, Some(_)) => return Err("count takes no 'column'".to_string()),
+            (AggregateKind::Count, None) => {}
+            (_, None) => return Err(format!("{:?} needs a 'column'", self.kind)),
+            _ => {}
+        }
+        match (self.kind, self.alpha) {
+            (AggregateKind::Ewma, Some(alpha)) if alpha > 0.0 && alpha <= 1.0 => Ok(()),
+            (AggregateKind::Ewma, _) => Err("ewma needs an 'alpha' in (0, 1]".to_string()),
+            (_, Some(_)) => Err("only ewma takes an 'alpha'".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn output_name(&self, schema: Option<&Schema>) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let kind = format!("{:?}", self.kind).to_lowercase();
+        match &self.column {
+            Some(Column::Name(column)) => format!("{}_{}", column, kind),
+            Some(Column::Index(index)) => match schema.and_then(|s| s.fields().get(*index)) {
+                Some(field) => format!("{}_{}", field.name, kind),
+                None => format!("f{}_{}", index, kind),
+            },
+            None => kind,
+        }
+    }
+}
+
+/// The window and running state kept for one key.
+struct EntityState {
+    events: VecDeque<(f64, Vec<f64>)>, // Event time and the value of each aggregate's column
+    ewma: Vec<Option<f64>>,            // Running average per aggregate (only used by `ewma`)
+    last_seen: f64,
+    sequence: u64, // Position in the eviction order
+}
+
+/// EntityStore Struct
+///
+/// The per-key windows behind an `EntityAggregator`, read from the same params. Kept apart from
+/// the actor so the aggregation can run on its own.
+pub struct EntityStore {
+    key: Column,
+    time: Option<Column>, // Event time in seconds; wall-clock time if unset
+    aggregates: Vec<Aggregate>,
+    max_events: usize,
+    window_secs: Option<f64>,
+    idle_secs: Option<f64>,
+    max_keys: Option<usize>,
+    entities: HashMap<u64, EntityState>, // Keyed by the key feature's bits
+    order: BTreeSet<(u64, u64)>,         // (sequence, key), least recently seen first
+    next_sequence: u64,
+    clock: f64, // Latest event time seen, for idle eviction
+}
+
+impl EntityStore {
+    /// Parses and checks the `key`, `time`, `aggregates` and window params.
+    pub fn from_params(params: &Value) -> Result<EntityStore, String> {
+        let column = |field: &str| -> Result<Option<Column>, String> {
+            params
+                .get(field)
+                .map(|c| serde_json::from_value(c.clone()))
+                .transpose()
+                .map_err(|e| format!("Invalid '{}': {}", field, e))
+        };
+        let key = column("key")?.ok_or("needs a 'key' feature")?;
+        let time = column("time")?;
+
+        let aggregates: Vec<Aggregate> = match params.get("aggregates") {
+            Some(aggregates) => serde_json::from_value(aggregates.clone())
+                .map_err(|e| format!("Invalid 'aggregates': {}", e))?,
+            None => return Err("needs a list of 'aggregates'".to_string()),
+        };
+        if aggregates.is_empty() {
+            return Err("needs at least one aggregate".to_string());
+        }
+        for (index, aggregate) in aggregates.iter().enumerate() {
+            aggregate
+                .validate()
+                .map_err(|e| format!("Aggregate {}: {}", index, e))?;
+        }
+
+        let positive = |field: &str| -> Result<Option<f64>, String> {
+            match params.get(field).map(|v| v.as_f64()) {
+                None => Ok(None),
+                Some(Some(value)) if value > 0.0 => Ok(Some(value)),
+                Some(_) => Err(format!("'{}' must be a positive number", field)),
+            }
+        };
+        let count = |field: &str| -> Result<Option<usize>, String> {
+            match params.get(field).map(|v| v.as_u64()) {
+                None => Ok(None),
+                Some(Some(value)) if value > 0 => Ok(Some(value as usize)),
+                Some(_) => Err(format!("'{}' must be a whole number of at least 1", field)),
+            }
+        };
+        let window_events = count("window_events")?;
+        let window_secs = positive("window_secs")?;
+        if window_events.is_none() && window_secs.is_none() {
+            return Err("needs a 'window_events' and/or 'window_secs'".to_string());
+        }
+
+        Ok(EntityStore {
+            key,
+            time,
+            aggregates,
+            max_events: window_events.unwrap_or(DEFAULT_MAX_EVENTS),
+            window_secs,
+            idle_secs: positive("idle_secs")?,
+            max_keys: count("max_keys")?,
+            entities: HashMap::new(),
+            order: BTreeSet::new(),
+            next_sequence: 0,
+            clock: f64::NEG_INFINITY,
+        })
+    }
+
+    /// Number of keys currently held.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// The schema of the features `update` returns for features with the given schema.
+    pub fn output_schema(&self, schema: &Schema) -> Result<Schema, String> {
+        let mut output = schema.clone();
+        output.extend(self.aggregates.iter().map(|aggregate| Field {
+            name: aggregate.output_name(Some(schema)),
+            dtype: match aggregate.kind {
+                AggregateKind::Count => FeatureType::Int,
+                _ => FeatureType::Float,
+            },
+        }))?;
+        Ok(output)
+    }
+
+    /// Records one event under its key and returns its features with the aggregates appended.
+    pub fn update(&mut self, data: &[f64], schema: Option<&Schema>) -> Result<Vec<f64>, String> {
+        let feature = |column: &Column| -> Result<f64, String> {
+            let index = Schema::resolve(schema, column)?;
+            data.get(index).copied().ok_or_else(|| {
+                format!(
+                    "feature index {} is out of range for {} features",
+                    index,
+                    data.len()
+                )
+            })
+        };
+
+        let key = feature(&self.key)?;
+        if key.is_nan() {
+            return Err("key feature is missing (NaN)".to_string());
+        }
+        let time = match &self.time {
+            Some(column) => feature(column)?,
+            None => now_secs(),
+        };
+        let values = self
+            .aggregates
+            .iter()
+            .map(|aggregate| aggregate.column.as_ref().map(feature).unwrap_or(Ok(0.0)))
+            .collect::<Result<Vec<f64>, String>>()?;
+        self.clock = self.clock.max(time);
+
+        // Move the key to the back of the eviction order (0.0 and -0.0 are the same key)
+        let key = if key == 0.0 { 0.0f64 } else { key }.to_bits();
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let aggregates = self.aggregates.len();
+        let state = self.entities.entry(key).or_insert_with(|| EntityState {
+            events: VecDeque::new(),
+            ewma: vec![None; aggregates],
+            last_seen: time,
+            sequence,
+        });
+        self.order.remove(&(state.sequence, key));
+        self.order.insert((sequence, key));
+        state.sequence = sequence;
+        state.last_seen = state.last_seen.max(time);
+
+        state.events.push_back((time, values.clone()));
+        while state.events.len() > self.max_events {
+            state.events.pop_front();
+        }
+        if let Some(window_secs) = self.window_secs {
+            while state
+                .events
+                .front()
+                .is_some_and(|(event_time, _)| *event_time <= time - window_secs)
+            {
+                state.events.pop_front();
+            }
+        }
+
+        let mut output = data.to_vec();
+        for (index, aggregate) in self.aggregates.iter().enumerate() {
+            // Missing values (NaN) don't count towards a column's aggregates
+            let window = state
+                .events
+                .iter()
+                .map(|(_, values)| values[index])
+                .filter(|value| !value.is_nan());
+            output.push(match aggregate.kind {
+                AggregateKind::Count => state.events.len() as f64,
+                AggregateKind::Sum => window.sum(),
+                AggregateKind::Mean => {
+                    let (sum, count) =
+                        window.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+                    if count == 0 {
+                        f64::NAN
+                    } else {
+                        sum / count as f64
+                    }
+                }
+                AggregateKind::Min => window.fold(f64::NAN, f64::min),
+                AggregateKind::Max => window.fold(f64::NAN, f64::max),
+                AggregateKind::Ewma => {
+                    let alpha = aggregate.alpha.unwrap_or(1.0);
+                    let value = values[index];
+                    let ewma = match (state.ewma[index], value.is_nan()) {
+                        (previous, true) => previous,
+                        (None, false) => Some(value),
+                        (Some(previous), false) => Some(alpha * value + (1.0 - alpha) * previous),
+                    };
+                    state.ewma[index] = ewma;
+                    ewma.unwrap_or(f64::NAN)
+                }
+            });
+        }
+
+        self.evict();
+        Ok(output)
+    }
+
+    /// Drops keys that have been idle for `idle_secs` and, past `max_keys`, the least recently
+    /// seen ones. Returns how many were dropped.
+    pub fn evict(&mut self) -> usize {
+        let mut evicted = 0;
+        while let Some(&(sequence, key)) = self.order.first() {
+            let idle = match (self.idle_secs, self.entities.get(&key)) {
+                (Some(idle_secs), Some(state)) => state.last_seen < self.clock - idle_secs,
+                _ => false,
+            };
+            let over = self
+                .max_keys
+                .is_some_and(|max_keys| self.entities.len() > max_keys);
+            if !idle && !over {
+                break;
+            }
+            self.order.remove(&(sequence, key));
+            self.entities.remove(&key);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Advances the eviction clock to the wall clock, for stores that key on arrival time.
+    fn tick(&mut self) -> usize {
+        if self.time.is_some() {
+            return 0;
+        }
+        self.clock = self.clock.max(now_secs());
+        self.evict()
+    }
+}
+
+fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// EntityAggregator Actor
+///
+/// Keeps a bounded window of recent events per entity, keyed by the feature `key`, and appends
+/// running aggregates (count, sum, mean, min/max over the window, EWMA over all events) to every
+/// message. The window holds the last `window_events` events and/or those from the last
+/// `window_secs` seconds, including the current one. Keys idle for `idle_secs` are evicted, and
+/// past `max_keys` the least recently seen key is.
+pub struct EntityAggregator {
+    pub name: String,
+    pub output_name: String,
+    pub coordinator: Addr<Coordinator>,
+    pub params: Value,
+    store: Option<EntityStore>, // Set by `load_aggregates` before the actor starts
+    schemas: Option<(Arc<Schema>, Arc<Schema>)>, // Last input schema and the output schema for it
+}
+
+impl EntityAggregator {
+    /// Parses and checks the aggregate params. The Coordinator calls this while handling
+    /// `Initialize`, so bad params fail the DAG at startup.
+    pub fn load_aggregates(&mut self) -> Result<(), String> {
+        self.store = Some(EntityStore::from_params(&self.params)?);
+        Ok(())
+    }
+
+    /// Aggregates one message, returning its features and schema with the aggregates appended.
+    fn process(&mut self, msg: &ProcessMessage) -> Result<(Vec<f64>, Option<Arc<Schema>>), String> {
+        check_width(msg)?;
+        if !msg.member_ids.is_empty() {
+            return Err("aggregates need one row per message, not a pooled batch".to_string());
+        }
+        let store = self
+            .store
+            .as_mut()
+            .ok_or("aggregates have not been loaded")?;
+
+        // Work out the output schema first so a message that can't get one leaves no state behind
+        let schema = match &msg.schema {
+            Some(schema) => Some(match &self.schemas {
+                Some((input, output)) if Arc::ptr_eq(input, schema) || input == schema => {
+                    output.clone()
+                }
+                _ => {
+                    let output = Arc::new(store.output_schema(schema)?);
+                    self.schemas = Some((schema.clone(), output.clone()));
+                    output
+                }
+            }),
+            None => None,
+        };
+        let data = store.update(&msg.data, msg.schema.as_deref())?;
+        Ok((data, schema))
+    }
+}
+
+impl Step for EntityAggregator {
+    fn new_from_params(
+        name: String,
+        output_name: String,
+        coordinator: Addr<Coordinator>,
+        params: Value,
+    ) -> Self {
+        EntityAggregator {
+            name,
+            output_name,
+            coordinator,
+            params,
+            store: None,
+            schemas: None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn output_name(&self) -> &str {
+        &self.output_name
+    }
+
+    fn coordinator(&self) -> Addr<Coordinator> {
+        self.coordinator.clone()
+    }
+
+    fn params(&self) -> &Value {
+        &self.params
+    }
+}
+
+impl Actor for EntityAggregator {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        info!("EntityAggregator '{}' started.", self.name);
+
+        // Keys also go idle while no messages arrive to trigger eviction
+        if let Some(idle_secs) = self.store.as_ref().and_then(|store| store.idle_secs) {
+            let interval = Duration::from_secs_f64(idle_secs / 4.0).max(Duration::from_millis(10));
+            ctx.run_interval(interval, |act, _ctx| {
+                if let Some(store) = act.store.as_mut() {
+                    let evicted = store.tick();
+                    if evicted > 0 {
+                        debug!(
+                            "EntityAggregator '{}' evicted {} idle keys, {} left",
+                            act.name,
+                            evicted,
+                            store.len()
+                        );
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl Handler<ProcessMessage> for EntityAggregator {
+    type Result = ();
+
+    fn handle(&mut self, mut msg: ProcessMessage, _ctx: &mut Context<Self>) -> Self::Result {
+        let start_time = Instant::now();
+
+        let (data, schema) = match self.process(&msg) {
+            Ok(aggregated) => aggregated,
+            Err(e) => {
+                error!("EntityAggregator '{}' failed: {}", self.name, e);
+                self.coordinator.do_send(DeadLetter {
+                    step_name: self.name.clone(),
+                    error: e,
+                    message: msg,
+                });
+                return;
+            }
+        };
+
+        let duration = start_time.elapsed();
+        let trace_step = TraceStep::new(&self.name, duration, self.params.clone());
+        msg.trace.add_step(trace_step);
+
+        self.coordinator.do_send(ProcessMessage {
+            id: msg.id,
+            node_id: self.output_name.clone(),
+            data,
+            batch_id: msg.batch_id,
+            batch_total: msg.batch_total,
+            trace: msg.trace.clone(),
+            member_ids: msg.member_ids.clone(),
+            schema,
+        });
+
+        info!(
+            "EntityAggregator '{}' aggregated features in {:?} ({} keys)",
+            self.name,
+            duration,
+            self.store
+                .as_ref()
+                .map(EntityStore::len)
+                .unwrap_or_default()
+        );
+    }
+}
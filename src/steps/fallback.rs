@@ -0,0 +1,183 @@
+// src/steps/fallback.rs
+
+use crate::steps::builtin_model::BuiltinModel;
+use crate::steps::remote::RemoteModel;
+use crate::steps::OnnxModel;
+use log::warn;
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where one link of the chain gets its predictions from.
+enum Backend {
+    Remote(Arc<RemoteModel>),
+    Onnx(Arc<OnnxModel>),
+    Builtin(BuiltinModel),
+    Constant(Vec<f64>), // Returned for every row
+}
+
+/// One backend of the chain, with the name the trace records when it answers.
+struct ChainLink {
+    name: String,
+    backend: Backend,
+    timeout: Option<Duration>,
+}
+
+/// FallbackChain Struct
+///
+/// An ordered list of backends, from the `backends` MLModel param. Each message is tried on
+/// every backend in turn until one answers within its timeout, e.g.
+///
+/// ```json
+/// "backends": [
+///   { "type": "remote", "remote_endpoint": "http://localhost:4000/process", "timeout_ms": 200 },
+///   { "type": "onnx", "onnx_model_path": "data/model.onnx", "timeout_ms": 50 },
+///   { "type": "builtin", "builtin_model_path": "data/linear.json" },
+///   { "type": "constant", "value": [0.0] }
+/// ]
+/// ```
+pub struct FallbackChain {
+    links: Vec<ChainLink>,
+}
+
+impl FallbackChain {
+    /// Builds (and loads) every backend in the `backends` list. Remote backends take the same
+    /// params as a remote MLModel and ONNX and built-in backends the same as a local one.
+    pub fn from_params(params: &JsonValue, feature_width: Option<usize>) -> Result<Self, String> {
+        let backends = params
+            .get("backends")
+            .and_then(|b| b.as_array())
+            .ok_or_else(|| "'backends' must be a list".to_string())?;
+        if backends.is_empty() {
+            return Err("'backends' must not be empty".to_string());
+        }
+
+        let mut links = Vec::new();
+        for (index, backend) in backends.iter().enumerate() {
+            let backend_type = backend
+                .get("type")
+                .and_then(|t| t.as_str())
+                .ok_or_else(|| format!("Backend {} has no 'type'", index))?;
+            let name = backend
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or(backend_type)
+                .to_string();
+            let timeout = backend
+                .get("timeout_ms")
+                .and_then(|t| t.as_u64())
+                .map(Duration::from_millis);
+
+            let backend = match backend_type {
+                "remote" => {
+                    let endpoint = backend
+                        .get("remote_endpoint")
+                        .and_then(|e| e.as_str())
+                        .ok_or_else(|| format!("Backend '{}' has no 'remote_endpoint'", name))?;
+                    let remote = RemoteModel::from_params(endpoint, backend)
+                        .map_err(|e| format!("Backend '{}': {}", name, e))?;
+                    Backend::Remote(Arc::new(remote))
+                }
+                "onnx" => {
+                    let model_path = backend
+                        .get("onnx_model_path")
+                        .and_then(|p| p.as_str())
+                        .ok_or_else(|| format!("Backend '{}' has no 'onnx_model_path'", name))?;
+                    let model = OnnxModel::load(model_path, backend, feature_width)
+                        .map_err(|e| format!("Backend '{}': {}", name, e))?;
+                    Backend::Onnx(Arc::new(model))
+                }
+                "builtin" => {
+                    let model_path = backend
+                        .get("builtin_model_path")
+                        .and_then(|p| p.as_str())
+                        .ok_or_else(|| format!("Backend '{}' has no 'builtin_model_path'", name))?;
+                    let model = BuiltinModel::load(model_path)
+                        .map_err(|e| format!("Backend '{}': {}", name, e))?;
+                    model
+                        .check_width(feature_width)
+                        .map_err(|e| format!("Backend '{}': {}", name, e))?;
+                    Backend::Builtin(model)
+                }
+                "constant" => {
+                    let value = match backend.get("value") {
+                        Some(JsonValue::Array(values)) => values
+                            .iter()
+                            .map(|v| v.as_f64())
+                            .collect::<Option<Vec<f64>>>(),
+                        Some(value) => value.as_f64().map(|v| vec![v]),
+                        None => None,
+                    };
+                    let value = value.ok_or_else(|| {
+                        format!("Backend '{}' needs a numeric 'value' (or list)", name)
+                    })?;
+                    Backend::Constant(value)
+                }
+                other => return Err(format!("Unknown backend type '{}'", other)),
+            };
+
+            links.push(ChainLink {
+                name,
+                backend,
+                timeout,
+            });
+        }
+
+        Ok(FallbackChain { links })
+    }
+
+    /// The features per row the first ONNX or built-in backend expects, if any declares it.
+    pub fn feature_width(&self) -> Option<usize> {
+        self.links.iter().find_map(|link| match &link.backend {
+            Backend::Onnx(model) => model.feature_width,
+            Backend::Builtin(model) => model.check_width(None).ok().flatten(),
+            _ => None,
+        })
+    }
+
+    /// Tries each backend in order and returns the name of the one that answered, with its
+    /// predictions per row. Fails only if every backend failed or timed out.
+    pub async fn predict(
+        &self,
+        data: Vec<f64>,
+        rows: usize,
+    ) -> Result<(String, Vec<Vec<f64>>), String> {
+        let mut errors = Vec::new();
+
+        for link in &self.links {
+            let attempt = async {
+                match &link.backend {
+                    Backend::Remote(remote) => remote.predict(&data, rows).await,
+                    Backend::Onnx(model) => {
+                        // Inference blocks, so keep it off the actor's thread. A timed out run
+                        // finishes in the background; its result is dropped.
+                        let model = model.clone();
+                        let data = data.clone();
+                        tokio::task::spawn_blocking(move || model.predict(&data, rows))
+                            .await
+                            .map_err(|e| format!("ONNX inference panicked: {:?}", e))?
+                    }
+                    Backend::Builtin(model) => model.predict(&data, rows),
+                    Backend::Constant(value) => Ok(vec![value.clone(); rows]),
+                }
+            };
+
+            let result = match link.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, attempt)
+                    .await
+                    .unwrap_or_else(|_| Err(format!("timed out after {:?}", timeout))),
+                None => attempt.await,
+            };
+
+            match result {
+                Ok(predictions) => return Ok((link.name.clone(), predictions)),
+                Err(e) => {
+                    warn!("Backend '{}' failed, trying the next one: {}", link.name, e);
+                    errors.push(format!("{}: {}", link.name, e));
+                }
+            }
+        }
+
+        Err(format!("All backends failed ({})", errors.join("; ")))
+    }
+}
@@ -0,0 +1,448 @@
+// src/steps/feature_lookup.rs
+
+use crate::coordinator::Coordinator;
+use crate::messages::{DeadLetter, ProcessMessage};
+use crate::schema::{check_width, Column, FeatureType, Field, Schema};
+use crate::step::{Step, TraceStep};
+use crate::steps::csv_reader::{open_csv, parse_delimiter};
+use actix::prelude::*;
+use log::{error, info, warn};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The file formats a table can be loaded from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TableFormat {
+    Csv,   // A header record naming the columns, then one record per key
+    Jsonl, // One JSON object per line
+}
+
+/// FeatureTable Struct
+///
+/// A keyed table of precomputed features loaded from a local file, plus what to append when a
+/// key is missing. Keys and features are numbers, like every other feature.
+pub struct FeatureTable {
+    pub path: String,
+    format: TableFormat,
+    delimiter: u8,                // Cell separator of a CSV table
+    key: String,                  // Column of the table holding the entity key
+    features: Vec<String>,        // Columns appended to messages, in order
+    defaults: Vec<f64>,           // Appended for keys that aren't in the table
+    rows: HashMap<u64, Vec<f64>>, // Key bits to the row's features
+    modified: Option<SystemTime>, // Modification time of the loaded file
+}
+
+impl FeatureTable {
+    /// Loads the table named by the params:
+    ///
+    /// - `path`: the CSV or JSONL file
+    /// - `format`: `csv` or `jsonl`, by default from the file extension
+    /// - `delimiter`: the cell separator of a CSV table, default `,`
+    /// - `table_key`: the column holding the key, default `key`
+    /// - `features`: the columns to append, default every other column (CSV) or required (JSONL)
+    /// - `default`: appended for missing keys, a number for every feature or an object by
+    ///   feature name; NaN otherwise
+    pub fn from_params(params: &Value) -> Result<FeatureTable, String> {
+        let path = params
+            .get("path")
+            .and_then(|p| p.as_str())
+            .ok_or("needs the 'path' of the table")?
+            .to_string();
+        let format = match params.get("format").and_then(|f| f.as_str()) {
+            Some("csv") => TableFormat::Csv,
+            Some("jsonl") => TableFormat::Jsonl,
+            Some(other) => return Err(format!("Unknown table format '{}'", other)),
+            None if path.ends_with(".jsonl") || path.ends_with(".ndjson") => TableFormat::Jsonl,
+            None => TableFormat::Csv,
+        };
+        let delimiter = parse_delimiter(params)?;
+        let key = params
+            .get("table_key")
+            .and_then(|k| k.as_str())
+            .unwrap_or("key")
+            .to_string();
+        let features: Vec<String> = match params.get("features") {
+            Some(features) => serde_json::from_value(features.clone())
+                .map_err(|e| format!("Invalid 'features': {}", e))?,
+            None => vec![],
+        };
+        if features.is_empty() && format == TableFormat::Jsonl {
+            return Err("JSONL tables need the list of 'features' to append".to_string());
+        }
+
+        let mut table = FeatureTable {
+            path,
+            format,
+            delimiter,
+            key,
+            features,
+            defaults: vec![],
+            rows: HashMap::new(),
+            modified: None,
+        };
+        table.reload()?;
+
+        table.defaults = match params.get("default") {
+            None | Some(Value::Null) => vec![f64::NAN; table.features.len()],
+            Some(Value::Number(value)) => {
+                vec![value.as_f64().unwrap_or(f64::NAN); table.features.len()]
+            }
+            Some(Value::Object(defaults)) => {
+                if let Some(unknown) = defaults.keys().find(|name| !table.features.contains(name)) {
+                    return Err(format!("'default' names unknown feature '{}'", unknown));
+                }
+                table
+                    .features
+                    .iter()
+                    .map(|name| {
+                        defaults
+                            .get(name)
+                            .map(|v| {
+                                v.as_f64()
+                                    .ok_or(format!("default for '{}' must be a number", name))
+                            })
+                            .unwrap_or(Ok(f64::NAN))
+                    })
+                    .collect::<Result<_, String>>()?
+            }
+            Some(_) => return Err("'default' must be a number or an object of numbers".to_string()),
+        };
+        Ok(table)
+    }
+
+    /// Names of the appended features.
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+
+    /// Number of keys in the table.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The features for a key, or the defaults if it isn't in the table.
+    pub fn lookup(&self, key: f64) -> (&[f64], bool) {
+        match self.rows.get(&key_bits(key)) {
+            Some(row) => (row, true),
+            None => (&self.defaults, false),
+        }
+    }
+
+    /// Reloads the file if it changed since it was last loaded. Returns whether it did.
+    pub fn reload_if_changed(&mut self) -> Result<bool, String> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .map_err(|e| format!("Failed to read table '{}': {}", self.path, e))?;
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// Reads the whole file, replacing the rows only if all of it loads.
+    fn reload(&mut self) -> Result<(), String> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .map_err(|e| format!("Failed to read table '{}': {}", self.path, e))?;
+        let rows = match self.format {
+            TableFormat::Csv => self.read_csv()?,
+            TableFormat::Jsonl => self.read_jsonl()?,
+        };
+        self.rows = rows;
+        self.modified = Some(modified);
+        Ok(())
+    }
+
+    /// Reads a CSV table, taking the columns from its header. Cells are trimmed, and an empty
+    /// cell is NaN.
+    fn read_csv(&mut self) -> Result<HashMap<u64, Vec<f64>>, String> {
+        let mut reader = open_csv(&self.path, self.delimiter, true)?;
+        let columns: Vec<String> = reader
+            .headers()
+            .map_err(|e| format!("Failed to read table '{}': {}", self.path, e))?
+            .iter()
+            .map(|c| c.trim().to_string())
+            .collect();
+        if columns.is_empty() {
+            return Err(format!("Table '{}' is empty", self.path));
+        }
+        let position = |name: &str| {
+            columns
+                .iter()
+                .position(|c| c == name)
+                .ok_or_else(|| format!("Table '{}' has no column '{}'", self.path, name))
+        };
+        let key = position(&self.key)?;
+        if self.features.is_empty() {
+            self.features = columns
+                .iter()
+                .filter(|c| **c != self.key)
+                .cloned()
+                .collect();
+        }
+        let positions = self
+            .features
+            .iter()
+            .map(|name| position(name))
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        let mut rows = HashMap::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| format!("{}: {}", self.path, e))?;
+            let line = record.position().map_or(0, |p| p.line());
+            let context = |e: String| format!("{} line {}: {}", self.path, line, e);
+            if record.len() != columns.len() {
+                return Err(context(format!(
+                    "{} cells for {} columns",
+                    record.len(),
+                    columns.len()
+                )));
+            }
+            let number_at = |position: usize| -> Result<f64, String> {
+                match record[position].trim() {
+                    "" => Ok(f64::NAN),
+                    cell => cell
+                        .parse::<f64>()
+                        .map_err(|_| context(format!("'{}' is not a number", cell))),
+                }
+            };
+            let row = positions
+                .iter()
+                .map(|&position| number_at(position))
+                .collect::<Result<Vec<f64>, String>>()?;
+            rows.insert(key_bits(number_at(key)?), row);
+        }
+        Ok(rows)
+    }
+
+    /// Reads a JSONL table, one object per non-blank line. An absent or null field is NaN.
+    fn read_jsonl(&self) -> Result<HashMap<u64, Vec<f64>>, String> {
+        let file = File::open(&self.path)
+            .map_err(|e| format!("Failed to open table '{}': {}", self.path, e))?;
+        let context =
+            |number: usize, e: String| format!("{} line {}: {}", self.path, number + 1, e);
+
+        let mut rows = HashMap::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| context(number, e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Value =
+                serde_json::from_str(&line).map_err(|e| context(number, e.to_string()))?;
+            let key = record
+                .get(&self.key)
+                .and_then(|k| k.as_f64())
+                .ok_or_else(|| context(number, format!("no numeric '{}'", self.key)))?;
+            let row = self
+                .features
+                .iter()
+                .map(|name| match record.get(name) {
+                    None | Some(Value::Null) => Ok(f64::NAN),
+                    Some(value) => value
+                        .as_f64()
+                        .ok_or_else(|| context(number, format!("'{}' is not a number", name))),
+                })
+                .collect::<Result<Vec<f64>, String>>()?;
+            rows.insert(key_bits(key), row);
+        }
+        Ok(rows)
+    }
+}
+
+/// The map key for an entity key, treating 0.0 and -0.0 alike.
+fn key_bits(key: f64) -> u64 {
+    if key == 0.0 { 0.0f64 } else { key }.to_bits()
+}
+
+/// FeatureLookup Actor
+///
+/// Enriches messages with precomputed features: looks up the message's `key` feature in a
+/// `FeatureTable` and appends the matching row (or the defaults). With `reload_secs` the file is
+/// checked that often and reloaded when it changes; a reload that fails keeps the old table.
+pub struct FeatureLookup {
+    pub name: String,
+    pub output_name: String,
+    pub coordinator: Addr<Coordinator>,
+    pub params: Value,
+    key: Column,
+    table: Option<FeatureTable>, // Set by `load_table` before the actor starts
+    schemas: Option<(Arc<Schema>, Arc<Schema>)>, // Last input schema and the output schema for it
+}
+
+impl FeatureLookup {
+    /// Loads the table. The Coordinator calls this while handling `Initialize`, so a missing or
+    /// malformed table fails the DAG at startup.
+    pub fn load_table(&mut self) -> Result<(), String> {
+        self.key = match self.params.get("key") {
+            Some(key) => {
+                serde_json::from_value(key.clone()).map_err(|e| format!("Invalid 'key': {}", e))?
+            }
+            None => return Err("needs the message's 'key' feature".to_string()),
+        };
+        let table = FeatureTable::from_params(&self.params)?;
+        info!(
+            "FeatureLookup '{}' loaded {} keys from '{}'",
+            self.name,
+            table.len(),
+            table.path
+        );
+        self.table = Some(table);
+        Ok(())
+    }
+
+    /// Appends the looked-up features to a message, returning its features and schema.
+    fn process(&mut self, msg: &ProcessMessage) -> Result<(Vec<f64>, Option<Arc<Schema>>), String> {
+        check_width(msg)?;
+        if !msg.member_ids.is_empty() {
+            return Err("lookups need one row per message, not a pooled batch".to_string());
+        }
+        let table = self.table.as_ref().ok_or("table has not been loaded")?;
+
+        let schema = match &msg.schema {
+            Some(schema) => Some(match &self.schemas {
+                Some((input, output)) if Arc::ptr_eq(input, schema) || input == schema => {
+                    output.clone()
+                }
+                _ => {
+                    let mut output = Schema::clone(schema);
+                    output.extend(table.features().iter().map(|name| Field {
+                        name: name.clone(),
+                        dtype: FeatureType::Float,
+                    }))?;
+                    let output = Arc::new(output);
+                    self.schemas = Some((schema.clone(), output.clone()));
+                    output
+                }
+            }),
+            None => None,
+        };
+
+        let index = Schema::resolve(msg.schema.as_deref(), &self.key)?;
+        let key = *msg.data.get(index).ok_or_else(|| {
+            format!(
+                "feature index {} is out of range for {} features",
+                index,
+                msg.data.len()
+            )
+        })?;
+        let (features, found) = table.lookup(key);
+        if !found {
+            info!("FeatureLookup '{}' has no row for key {}", self.name, key);
+        }
+
+        let mut data = msg.data.clone();
+        data.extend_from_slice(features);
+        Ok((data, schema))
+    }
+}
+
+impl Step for FeatureLookup {
+    fn new_from_params(
+        name: String,
+        output_name: String,
+        coordinator: Addr<Coordinator>,
+        params: Value,
+    ) -> Self {
+        FeatureLookup {
+            name,
+            output_name,
+            coordinator,
+            params,
+            key: Column::Index(0),
+            table: None,
+            schemas: None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn output_name(&self) -> &str {
+        &self.output_name
+    }
+
+    fn coordinator(&self) -> Addr<Coordinator> {
+        self.coordinator.clone()
+    }
+
+    fn params(&self) -> &Value {
+        &self.params
+    }
+}
+
+impl Actor for FeatureLookup {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        info!("FeatureLookup '{}' started.", self.name);
+
+        let reload_secs = self.params.get("reload_secs").and_then(|r| r.as_f64());
+        if let Some(reload_secs) = reload_secs.filter(|secs| *secs > 0.0) {
+            ctx.run_interval(Duration::from_secs_f64(reload_secs), |act, _ctx| {
+                let Some(table) = act.table.as_mut() else {
+                    return;
+                };
+                match table.reload_if_changed() {
+                    Ok(true) => info!(
+                        "FeatureLookup '{}' reloaded {} keys from '{}'",
+                        act.name,
+                        table.len(),
+                        table.path
+                    ),
+                    Ok(false) => {}
+                    Err(e) => warn!(
+                        "FeatureLookup '{}' kept its table, reload failed: {}",
+                        act.name, e
+                    ),
+                }
+            });
+        }
+    }
+}
+
+impl Handler<ProcessMessage> for FeatureLookup {
+    type Result = ();
+
+    fn handle(&mut self, mut msg: ProcessMessage, _ctx: &mut Context<Self>) -> Self::Result {
+        let start_time = Instant::now();
+
+        let (data, schema) = match self.process(&msg) {
+            Ok(enriched) => enriched,
+            Err(e) => {
+                error!("FeatureLookup '{}' failed: {}", self.name, e);
+                self.coordinator.do_send(DeadLetter {
+                    step_name: self.name.clone(),
+                    error: e,
+                    message: msg,
+                });
+                return;
+            }
+        };
+
+        let duration = start_time.elapsed();
+        let trace_step = TraceStep::new(&self.name, duration, self.params.clone());
+        msg.trace.add_step(trace_step);
+
+        self.coordinator.do_send(ProcessMessage {
+            id: msg.id,
+            node_id: self.output_name.clone(),
+            data,
+            batch_id: msg.batch_id,
+            batch_total: msg.batch_total,
+            trace: msg.trace.clone(),
+            member_ids: msg.member_ids.clone(),
+            schema,
+        });
+    }
+}
@@ -1,42 +1,78 @@
 use crate::coordinator::Coordinator;
-use crate::messages::ProcessMessage;
+use crate::messages::{DeadLetter, ProcessMessage};
+use crate::schema::{check_width, Schema};
 use crate::step::{Step, TraceStep};
+use crate::steps::transforms::{
+    apply_transforms, check_fitted, load_artifact, parse_transforms, schema_chain, Transform,
+};
 use actix::prelude::*;
-use log::{error, info, warn};
+use log::{error, info};
 use serde_json::Value;
+use std::sync::Arc;
 use std::time::Instant;
 
 /// FeatureProcessor Actor
 ///
-/// Responsible for processing feature data. It can perform operations like normalization and encoding.
+/// Responsible for processing feature data. It runs the ordered list of `transforms` from its
+/// params (normalization, encoding, selection, ...) over every message. Statistics computed by
+/// `--fit` are read from the `artifact_path` param. Messages with a schema leave with the schema
+/// the transforms produce.
 /// After processing, it sends the processed data to the Coordinator.
 pub struct FeatureProcessor {
     pub name: String,
     pub output_name: String,
     pub coordinator: Addr<Coordinator>,
     pub params: Value,
+    transforms: Vec<Transform>, // Set by `load_transforms` before the actor starts
+    schemas: Option<SchemaCache>, // Built for the last input schema seen
+}
+
+/// The `schema_chain` of the transforms for one input schema, and the schema they output.
+struct SchemaCache {
+    input: Arc<Schema>,
+    chain: Arc<Vec<Schema>>,
+    output: Arc<Schema>,
 }
 
 impl FeatureProcessor {
-    fn process_data(&self, data: &[f64]) -> Vec<f64> {
-        match self.output_name.as_str() {
-            "normalized_data" => {
-                let max = data.iter().cloned().fold(f64::NAN, f64::max);
-                if max == 0.0 || max.is_nan() {
-                    error!("Normalization failed: max value is zero or NaN");
-                    return data.to_vec();
-                }
-                data.iter().map(|x| x / max).collect()
+    /// Parses and checks the `transforms` list, replacing it with the fitted artifact if the step
+    /// has one. The Coordinator calls this while handling `Initialize`, so a bad list or a stale
+    /// artifact fails the DAG at startup.
+    pub fn load_transforms(&mut self) -> Result<(), String> {
+        let transforms = parse_transforms(&self.params)?;
+        self.transforms = match self.params.get("artifact_path").and_then(Value::as_str) {
+            Some(path) => load_artifact(path, &transforms)?,
+            None => transforms,
+        };
+        check_fitted(&self.transforms)
+    }
+
+    /// Runs the transforms over a message, returning the features and the schema they leave with.
+    fn process(&mut self, msg: &ProcessMessage) -> Result<(Vec<f64>, Option<Arc<Schema>>), String> {
+        check_width(msg)?;
+        let Some(schema) = &msg.schema else {
+            return Ok((apply_transforms(&self.transforms, &msg.data, None)?, None));
+        };
+
+        // Sources share one schema between their messages, so the chain rarely needs rebuilding
+        let (chain, output) = match &self.schemas {
+            Some(cache) if Arc::ptr_eq(&cache.input, schema) || cache.input == *schema => {
+                (cache.chain.clone(), cache.output.clone())
             }
-            "encoded_data" => data.iter().map(|x| x * x).collect(),
             _ => {
-                warn!(
-                    "Unknown FeatureProcessor '{}', passing data through.",
-                    self.output_name
-                );
-                data.to_vec()
+                let chain = schema_chain(&self.transforms, schema)?;
+                let output = Arc::new(chain[chain.len() - 1].clone());
+                let chain = Arc::new(chain);
+                self.schemas = Some(SchemaCache {
+                    input: schema.clone(),
+                    chain: chain.clone(),
+                    output: output.clone(),
+                });
+                (chain, output)
             }
-        }
+        };
+        let processed = apply_transforms(&self.transforms, &msg.data, Some(&chain))?;
+        Ok((processed, Some(output)))
     }
 }
 
@@ -52,6 +88,8 @@ impl Step for FeatureProcessor {
             output_name,
             coordinator,
             params,
+            transforms: Vec::new(),
+            schemas: None,
         }
     }
 
@@ -86,7 +124,18 @@ impl Handler<ProcessMessage> for FeatureProcessor {
             self.name, msg.data
         );
 
-        let processed = self.process_data(&msg.data);
+        let (processed, schema) = match self.process(&msg) {
+            Ok(processed) => processed,
+            Err(e) => {
+                error!("FeatureProcessor '{}' failed: {}", self.name, e);
+                self.coordinator.do_send(DeadLetter {
+                    step_name: self.name.clone(),
+                    error: e,
+                    message: msg,
+                });
+                return;
+            }
+        };
 
         // Update the trace
         let duration = start_time.elapsed();
@@ -100,6 +149,8 @@ impl Handler<ProcessMessage> for FeatureProcessor {
             batch_id: msg.batch_id,
             batch_total: msg.batch_total,
             trace: msg.trace.clone(),
+            member_ids: msg.member_ids.clone(),
+            schema,
         });
 
         info!(
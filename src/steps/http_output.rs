@@ -12,14 +12,14 @@ use tokio::sync::oneshot;
 pub struct HttpOutput {
     pub name: String,
     pub input_name: String,
-    pub sender_map: Arc<DashMap<u64, oneshot::Sender<Vec<f64>>>>, // Shared map for request senders
+    pub sender_map: Arc<DashMap<u64, oneshot::Sender<ProcessMessage>>>, // Shared map for request senders
 }
 
 impl HttpOutput {
     pub fn new(
         name: String,
         input_name: String,
-        sender_map: Arc<DashMap<u64, oneshot::Sender<Vec<f64>>>>,
+        sender_map: Arc<DashMap<u64, oneshot::Sender<ProcessMessage>>>,
     ) -> Self {
         HttpOutput {
             name,
@@ -72,8 +72,8 @@ impl Handler<ProcessMessage> for HttpOutput {
         debug!("HttpOutput had access to {:?}", self.sender_map);
         // Look up the sender in the DashMap using the request id
         if let Some((_req_id, sender)) = self.sender_map.remove(&msg.id) {
-            // Send the processed data back to the original request
-            if sender.send(msg.data.clone()).is_err() {
+            // Send the processed message (data and trace) back to the original request
+            if sender.send(msg.clone()).is_err() {
                 info!(
                     "HttpOutput: Failed to send response back to request ID: {}",
                     msg.id
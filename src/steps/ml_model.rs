@@ -1,14 +1,107 @@
 use crate::coordinator::Coordinator;
-use crate::messages::ProcessMessage;
+use crate::messages::{DeadLetter, ProcessMessage};
 use crate::step::{Step, TraceStep};
+use crate::steps::builtin_model::BuiltinModel;
+use crate::steps::fallback::FallbackChain;
+use crate::steps::model_variants::{parse_shadow, parse_variants, pick_variant};
+use crate::steps::remote::RemoteModel;
+use crate::steps::OnnxModel;
 use actix::prelude::*;
-use log::{debug, error, info};
-use ort::{Environment, GraphOptimizationLevel,  SessionBuilder, Value as OrtValue};
-use ndarray::{Array, CowArray};
-use reqwest::Client;
-use serde_json::{json, Value as JsonValue};
-use std::time::Instant;
+use log::{error, info};
+use serde_json::Value as JsonValue;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_BATCH_WAIT_MS: u64 = 10;
+
+/// A message waiting to go out in the next remote request.
+struct PendingRequest {
+    message: ProcessMessage,
+    rows: usize,       // Rows the message holds (more than one if a BatchPooler built it)
+    received: Instant, // When the step received it
+}
+
+/// Where an MLModel's results go. A shadow variant only logs them, so neither its predictions
+/// nor its failures reach the rest of the DAG (or cancel a pending HTTP request).
+#[derive(Clone)]
+struct Outlet {
+    coordinator: Addr<Coordinator>,
+    step_name: String,
+    variant: Option<String>,
+    shadow: bool,
+}
+
+impl Outlet {
+    fn send(&self, msg: ProcessMessage) {
+        if self.shadow {
+            info!(
+                "Shadow variant '{}' of MLModel '{}' predicted {:?} for ID={}",
+                self.variant.as_deref().unwrap_or_default(),
+                self.step_name,
+                msg.data,
+                msg.id
+            );
+        } else {
+            self.coordinator.do_send(msg);
+        }
+    }
+
+    /// Sends the predictions on, either as one message or (with `split_batch`) one message per
+    /// row under the ID it was pooled with.
+    fn send_predictions(
+        &self,
+        output_name: &str,
+        split_batch: bool,
+        msg: ProcessMessage,
+        predictions: Vec<Vec<f64>>,
+    ) {
+        if split_batch && predictions.len() > 1 {
+            // Send each row back out as its own message, under the ID it was pooled with
+            let rows = predictions.len() as u32;
+            for (index, prediction) in predictions.into_iter().enumerate() {
+                self.send(ProcessMessage {
+                    id: msg.member_ids.get(index).copied().unwrap_or(msg.id),
+                    node_id: output_name.to_string(),
+                    data: prediction,
+                    batch_id: msg.batch_id,
+                    batch_total: Some(rows),
+                    trace: msg.trace.clone(),
+                    member_ids: vec![],
+                    schema: None,
+                });
+            }
+        } else {
+            self.send(ProcessMessage {
+                id: msg.id,
+                node_id: output_name.to_string(),
+                data: predictions.concat(),
+                batch_id: msg.batch_id,
+                batch_total: msg.batch_total,
+                trace: msg.trace.clone(),
+                member_ids: msg.member_ids.clone(),
+                schema: None,
+            });
+        }
+    }
+
+    fn fail(&self, error: String, message: ProcessMessage) {
+        if self.shadow {
+            error!(
+                "Shadow variant '{}' of MLModel '{}' failed for ID={}: {}",
+                self.variant.as_deref().unwrap_or_default(),
+                self.step_name,
+                message.id,
+                error
+            );
+        } else {
+            self.coordinator.do_send(DeadLetter {
+                step_name: self.step_name.clone(),
+                error,
+                message,
+            });
+        }
+    }
+}
 
 /// MLModel Actor
 ///
@@ -21,80 +114,246 @@ pub struct MLModel {
     pub coordinator: Addr<Coordinator>,
     pub remote_endpoint: Option<String>,
     pub onnx_model_path: Option<String>,
+    pub builtin_model_path: Option<String>, // JSON file of a linear, logistic or tree model
+    pub feature_width: Option<usize>, // Expected number of features per row (from params or the model)
+    pub split_batch: bool, // Emit one message per row of a pooled batch instead of one combined message
+    model: Option<OnnxModel>, // Set by `load_model` before the actor starts
+    builtin_model: Option<BuiltinModel>, // Likewise, for `builtin_model_path`
+    remote: Option<Arc<RemoteModel>>, // Likewise; holds the pooled HTTP client for `remote_endpoint`
+    fallback: Option<Arc<FallbackChain>>, // Likewise, when `backends` lists several to try in order
+    max_batch_size: usize, // Remote messages sent per request; 1 sends each message on its own
+    max_batch_wait: Duration, // Longest a remote message waits for its batch to fill
+    pending: Vec<PendingRequest>, // Remote messages collected for the next request
+    flush_timer: Option<SpawnHandle>, // Sends `pending` once `max_batch_wait` has passed
+    pub variant: Option<String>, // Set on the child actors of an A/B split MLModel
+    pub shadow: bool,      // A shadow variant logs its predictions instead of forwarding them
+    variants: Vec<(f64, Addr<MLModel>)>, // Percentage and actor of each variant, if split
+    shadow_model: Option<Addr<MLModel>>, // Also gets every message, if configured
 }
 
 impl MLModel {
-    fn handle_local_prediction(&self, msg: ProcessMessage) -> Vec<f64> {
-        if let Some(model_path) = &self.onnx_model_path {
-            // Create environment wrapped in Arc
-            let environment = match Environment::builder()
-                .with_name("MLModelEnvironment")
-                .build()
-            {
-                Ok(env) => Arc::new(env),
-                Err(e) => {
-                    error!("Failed to build environment: {:?}", e);
-                    return vec![];
-                }
-            };
+    /// Builds the HTTP client for `remote_endpoint`, reads the built-in model file, or loads the
+    /// ONNX session and checks the `inputs`/`outputs` params and `feature_width` against what the
+    /// model declares. With
+    /// `variants` or a `shadow`, each of those is loaded and started as its own MLModel actor.
+    /// The Coordinator calls this while handling `Initialize`, so a bad model path, name, shape or
+    /// header fails the whole DAG up front instead of every message.
+    pub fn load_model(&mut self) -> Result<(), String> {
+        if let Some(shadow) = parse_shadow(&self.params)? {
+            let model = self.start_variant(&shadow.name, shadow.params, true)?;
+            self.shadow_model = Some(model);
+        }
 
-            let session = match SessionBuilder::new(&environment)
-                .expect("Failed to create session builder")
-                .with_optimization_level(GraphOptimizationLevel::Level1)
-                .expect("Failed to set optimization level")
-                .with_model_from_file(model_path)
-            {
-                Ok(s) => s,
-                Err(e) => {
-                    error!("Failed to create session: {:?}", e);
-                    return vec![];
-                }
-            };
+        let variants = parse_variants(&self.params)?;
+        if !variants.is_empty() {
+            for variant in variants {
+                let model = self.start_variant(&variant.name, variant.params, false)?;
+                self.variants.push((variant.percent, model));
+            }
+            return Ok(());
+        }
+
+        if self.params.get("backends").is_some() {
+            let fallback = FallbackChain::from_params(&self.params, self.feature_width)?;
+            self.feature_width = self.feature_width.or(fallback.feature_width());
+            self.fallback = Some(Arc::new(fallback));
+            return Ok(());
+        }
+
+        if let Some(remote_endpoint) = &self.remote_endpoint {
+            let remote = RemoteModel::from_params(remote_endpoint, &self.params)?;
+            self.remote = Some(Arc::new(remote));
+            return Ok(());
+        }
 
-            // Create input tensor
-            let array = Array::from_shape_vec(
-                (1, msg.data.len()),
-                msg.data.iter().map(|&x| x as f32).collect(),
-            ).expect("Failed to create input array");
+        if let Some(model_path) = &self.builtin_model_path {
+            let model = BuiltinModel::load(model_path)?;
+            self.feature_width = model.check_width(self.feature_width)?;
+            self.builtin_model = Some(model);
+            return Ok(());
+        }
 
-            let cow_array: CowArray<f32, _> = array.into_dyn().into();
+        let model_path = match &self.onnx_model_path {
+            Some(model_path) => model_path,
+            None => return Ok(()),
+        };
 
-            let input_tensor = OrtValue::from_array(session.allocator(), &cow_array)
-                .expect("Failed to create input tensor");
+        let model = OnnxModel::load(model_path, &self.params, self.feature_width)?;
+        self.feature_width = model.feature_width;
+        self.model = Some(model);
+        Ok(())
+    }
 
-            // Run inference and process results
-            let outputs = match session.run(vec![input_tensor]) {
-                Ok(o) => o,
-                Err(e) => {
-                    error!("ONNX inference failed: {:?}", e);
-                    return vec![];
+    /// Loads one variant as a child MLModel that reports under this step's name and output.
+    fn start_variant(
+        &self,
+        variant: &str,
+        params: JsonValue,
+        shadow: bool,
+    ) -> Result<Addr<MLModel>, String> {
+        let mut model = MLModel::new_from_params(
+            self.name.clone(),
+            self.output_name.clone(),
+            self.coordinator.clone(),
+            params,
+        );
+        model.variant = Some(variant.to_string());
+        model.shadow = shadow;
+        model
+            .load_model()
+            .map_err(|e| format!("variant '{}': {}", variant, e))?;
+        info!("MLModel '{}' loaded variant '{}'", self.name, variant);
+        Ok(model.start())
+    }
+
+    fn outlet(&self) -> Outlet {
+        Outlet {
+            coordinator: self.coordinator.clone(),
+            step_name: self.name.clone(),
+            variant: self.variant.clone(),
+            shadow: self.shadow,
+        }
+    }
+
+    /// Works out how many rows a message holds. A message pooled by a `BatchPooler` is
+    /// `rows * feature_width` values long; anything else is a single row.
+    fn batch_rows(&self, msg: &ProcessMessage) -> Result<usize, String> {
+        let width = match self.feature_width {
+            Some(width) if width > 0 => width,
+            _ => return Ok(1),
+        };
+
+        let rows = msg.data.len() / width;
+        if rows * width != msg.data.len() {
+            return Err(format!(
+                "Received {} values, which is not a whole number of rows of {} features",
+                msg.data.len(),
+                width
+            ));
+        }
+
+        if !msg.member_ids.is_empty() && msg.member_ids.len() != rows {
+            return Err(format!(
+                "Batch holds {} rows of {} features but {} pooled messages",
+                rows,
+                width,
+                msg.member_ids.len()
+            ));
+        }
+
+        Ok(rows)
+    }
+
+    /// Runs the model over every row of the message and returns the predictions per row, in order.
+    fn handle_local_prediction(&self, msg: &ProcessMessage) -> Result<Vec<Vec<f64>>, String> {
+        let rows = self.batch_rows(msg)?;
+        let width = msg.data.len() / rows;
+
+        if let Some(model) = &self.builtin_model {
+            model.predict(&msg.data, rows)
+        } else if self.onnx_model_path.is_some() {
+            let model = self
+                .model
+                .as_ref()
+                .ok_or_else(|| "ONNX session was not loaded".to_string())?;
+
+            // The whole batch runs through the model in one call
+            model.predict(&msg.data, rows)
+        } else {
+            // Fallback logic
+            Ok(msg
+                .data
+                .chunks(width.max(1))
+                .map(|row| match self.output_name.as_str() {
+                    "lr_output" => vec![row.iter().sum()],
+                    "am_output" => vec![row.iter().product()],
+                    _ => vec![row.iter().sum()],
+                })
+                .collect())
+        }
+    }
+
+    /// Sends every pending message to the remote endpoint in one request and routes each row
+    /// of the response back to the message it came from.
+    fn flush_remote(&mut self, ctx: &mut Context<Self>) {
+        if let Some(timer) = self.flush_timer.take() {
+            ctx.cancel_future(timer);
+        }
+        let batch = std::mem::take(&mut self.pending);
+        if batch.is_empty() {
+            return;
+        }
+
+        let outlet = self.outlet();
+        let remote = match &self.remote {
+            Some(remote) => remote.clone(),
+            None => {
+                for pending in batch {
+                    outlet.fail("Remote client was not built".to_string(), pending.message);
                 }
-            };
+                return;
+            }
+        };
+        let output_name = self.output_name.clone();
+        let params = self.params.clone();
+        let batched = self.max_batch_size > 1;
+        let split_batch = self.split_batch;
+
+        let flushed_at = Instant::now();
+
+        ctx.spawn(
+            async move {
+                let result = if batched {
+                    // Every row goes in its own row of the request, however many messages the
+                    // window caught, so each message gets back exactly the predictions for its rows
+                    let rows: Vec<&[f64]> = batch
+                        .iter()
+                        .flat_map(|pending| {
+                            let width = pending.message.data.len() / pending.rows;
+                            pending.message.data.chunks(width.max(1))
+                        })
+                        .collect();
+                    remote.predict_rows(&rows).await
+                } else {
+                    // Unbatched, the message is sent as it is and gets the whole response
+                    let pending = &batch[0];
+                    remote.predict(&pending.message.data, pending.rows).await
+                };
 
-            // Process outputs
-            match outputs.get(0) {
-                Some(output) => {
-                    match output.try_extract::<f32>() {
-                        Ok(tensor) => tensor.view().iter().map(|&x| x as f64).collect(),
-                        Err(e) => {
-                            error!("Failed to extract tensor data: {:?}", e);
-                            vec![]
+                let mut predictions = match result {
+                    Ok(predictions) => predictions.into_iter(),
+                    Err(error) => {
+                        for pending in batch {
+                            outlet.fail(error.clone(), pending.message);
                         }
+                        return;
                     }
+                };
+
+                let batch_size = batch.len();
+                for pending in batch {
+                    let mut msg = pending.message;
+                    let result: Vec<Vec<f64>> = if batched {
+                        predictions.by_ref().take(pending.rows).collect()
+                    } else {
+                        predictions.by_ref().collect()
+                    };
+                    info!("MLModel '{}' processed remotely: {:?}", msg.node_id, result);
+
+                    let duration = pending.received.elapsed();
+                    let mut trace_step = TraceStep::new(&output_name, duration, params.clone())
+                        .with_variant(outlet.variant.clone());
+                    if batched {
+                        trace_step = trace_step
+                            .with_batch(batch_size, flushed_at.duration_since(pending.received));
+                    }
+                    msg.trace.add_step(trace_step);
+
+                    outlet.send_predictions(&output_name, split_batch, msg, result);
                 }
-                None => {
-                    error!("ONNX model produced no output tensor");
-                    vec![]
-                }
-            }
-        } else {
-            // Fallback logic
-            match self.output_name.as_str() {
-                "lr_output" => vec![msg.data.iter().sum()],
-                "am_output" => vec![msg.data.iter().product()],
-                _ => vec![msg.data.iter().sum()],
             }
-        }
+            .into_actor(self),
+        );
     }
 }
 
@@ -115,6 +374,34 @@ impl Step for MLModel {
             .and_then(|path| path.as_str())
             .map(|path| path.to_string());
 
+        let builtin_model_path = params
+            .get("builtin_model_path")
+            .and_then(|path| path.as_str())
+            .map(|path| path.to_string());
+
+        let feature_width = params
+            .get("feature_width")
+            .and_then(|w| w.as_u64())
+            .map(|w| w as usize);
+
+        let split_batch = params
+            .get("split_batch")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let max_batch_size = params
+            .get("max_batch_size")
+            .and_then(|v| v.as_u64())
+            .map(|v| v.max(1) as usize)
+            .unwrap_or(1);
+
+        let max_batch_wait = Duration::from_millis(
+            params
+                .get("max_batch_wait_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_MAX_BATCH_WAIT_MS),
+        );
+
         MLModel {
             name,
             output_name,
@@ -122,6 +409,21 @@ impl Step for MLModel {
             remote_endpoint,
             params,
             onnx_model_path,
+            builtin_model_path,
+            feature_width,
+            split_batch,
+            model: None,
+            builtin_model: None,
+            remote: None,
+            fallback: None,
+            max_batch_size,
+            max_batch_wait,
+            pending: Vec::new(),
+            flush_timer: None,
+            variant: None,
+            shadow: false,
+            variants: Vec::new(),
+            shadow_model: None,
         }
     }
 
@@ -152,94 +454,107 @@ impl Handler<ProcessMessage> for MLModel {
     fn handle(&mut self, mut msg: ProcessMessage, ctx: &mut Context<Self>) -> Self::Result {
         let start_time = Instant::now();
         if msg.data.is_empty() {
-            error!("Received empty feature data in MLModel '{}'", self.name);
+            self.outlet()
+                .fail("Received empty feature data".to_string(), msg);
             return;
         }
 
-        // Infer processing mode based on the presence of `remote_endpoint`
-        if let Some(remote_endpoint) = &self.remote_endpoint {
-            // Remote processing
-            let coordinator = self.coordinator.clone();
-            let remote_endpoint = remote_endpoint.clone();
+        if let Some(shadow_model) = &self.shadow_model {
+            shadow_model.do_send(msg.clone());
+        }
+
+        // An A/B split step hands the message to one variant, chosen by its ID
+        if !self.variants.is_empty() {
+            let percents: Vec<f64> = self.variants.iter().map(|(percent, _)| *percent).collect();
+            let (_, variant) = &self.variants[pick_variant(msg.id, &percents)];
+            variant.do_send(msg);
+            return;
+        }
+
+        // Try each backend in turn when there is a fallback chain
+        if let Some(fallback) = &self.fallback {
+            let outlet = self.outlet();
+            let rows = match self.batch_rows(&msg) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    outlet.fail(e, msg);
+                    return;
+                }
+            };
+            let fallback = fallback.clone();
             let output_name = self.output_name.clone();
             let params = self.params.clone();
+            let split_batch = self.split_batch;
 
             ctx.spawn(
                 async move {
-                    let client = Client::new();
-                    let input_data = json!({ "features": msg.data });
-                    debug!("Sending payload: {:?}", input_data);
-                    let response = client.post(&remote_endpoint).json(&input_data).send().await;
-
-                    match response {
-                        Ok(response) if response.status().is_success() => {
-                            let result: Vec<f64> = match response.json::<serde_json::Value>().await {
-                                Ok(json_response) => {
-                                    json_response
-                                        .get("processed_features")
-                                        .and_then(|features| features.as_array())
-                                        .map(|array| {
-                                            array
-                                                .iter()
-                                                .filter_map(|v| v.as_f64())
-                                                .collect()
-                                        })
-                                        .unwrap_or_else(|| {
-                                            error!("Invalid 'processed_features' in response");
-                                            vec![]
-                                        })
-                                }
-                                Err(_) => {
-                                    error!("Failed to parse JSON response from remote");
-                                    vec![]
-                                }
-                            };
-                            info!("MLModel '{}' processed remotely: {:?}", msg.node_id, result);
-
-                            let duration = start_time.elapsed();
-                            let trace_step = TraceStep::new(&output_name, duration, params);
+                    match fallback.predict(msg.data.clone(), rows).await {
+                        Ok((backend, predictions)) => {
+                            info!(
+                                "MLModel '{}' prediction from backend '{}': {:?}",
+                                output_name, backend, predictions
+                            );
+                            let trace_step =
+                                TraceStep::new(&output_name, start_time.elapsed(), params)
+                                    .with_variant(outlet.variant.clone())
+                                    .with_backend(&backend);
                             msg.trace.add_step(trace_step);
-
-                            coordinator.do_send(ProcessMessage {
-                                id: msg.id,
-                                node_id: output_name,
-                                data: result,
-                                batch_id: msg.batch_id,
-                                batch_total: msg.batch_total,
-                                trace: msg.trace.clone(),
-                            });
-                        }
-                        Ok(response) => {
-                            error!("Remote endpoint error: {:?}", response.status());
-                        }
-                        Err(e) => {
-                            error!("Request to remote endpoint failed: {:?}", e);
+                            outlet.send_predictions(&output_name, split_batch, msg, predictions);
                         }
+                        Err(e) => outlet.fail(e, msg),
                     }
                 }
                 .into_actor(self),
             );
+            return;
+        }
+
+        // Infer processing mode based on the presence of `remote_endpoint`
+        if self.remote_endpoint.is_some() {
+            // Remote processing, batched with other messages if `max_batch_size` allows
+            let rows = match self.batch_rows(&msg) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    self.outlet().fail(e, msg);
+                    return;
+                }
+            };
+            self.pending.push(PendingRequest {
+                message: msg,
+                rows,
+                received: start_time,
+            });
+
+            if self.pending.len() >= self.max_batch_size {
+                self.flush_remote(ctx);
+            } else if self.flush_timer.is_none() {
+                self.flush_timer = Some(ctx.run_later(self.max_batch_wait, |act, ctx| {
+                    act.flush_timer = None;
+                    act.flush_remote(ctx);
+                }));
+            }
         } else {
             // Local processing
-            let prediction = self.handle_local_prediction(msg.clone());
+            let outlet = self.outlet();
+            let predictions = match self.handle_local_prediction(&msg) {
+                Ok(predictions) => predictions,
+                Err(e) => {
+                    outlet.fail(e, msg);
+                    return;
+                }
+            };
 
             info!(
                 "MLModel '{}' prediction: {:?} (processed locally)",
-                self.output_name, prediction
+                self.output_name, predictions
             );
 
             let duration = start_time.elapsed();
-            let trace_step = TraceStep::new(&self.output_name, duration, self.params.clone());
+            let trace_step = TraceStep::new(&self.output_name, duration, self.params.clone())
+                .with_variant(self.variant.clone());
             msg.trace.add_step(trace_step);
 
-            self.coordinator.do_send(ProcessMessage {
-                id: msg.id,
-                node_id: self.output_name.clone(),
-                data: prediction,
-                batch_id: msg.batch_id,
-                batch_total: msg.batch_total,
-                trace: msg.trace.clone(),
-            });
+            outlet.send_predictions(&self.output_name, self.split_batch, msg, predictions);
         }
 
         info!(
@@ -248,4 +563,4 @@ impl Handler<ProcessMessage> for MLModel {
             start_time.elapsed()
         );
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,33 @@
 pub mod batch_pooler;
+pub mod builtin_model;
 pub mod csv_reader;
 pub mod data_generator;
+pub mod entity_aggregator;
+pub mod fallback;
+pub mod feature_lookup;
 pub mod feature_processor;
 pub mod http_output;
 pub mod ml_model;
+pub mod model_variants;
+#[cfg(feature = "onnxruntime")]
+pub mod onnx;
+#[cfg(feature = "tract")]
+pub mod onnx_tract;
+#[cfg(not(any(feature = "onnxruntime", feature = "tract")))]
+pub mod onnx_unavailable;
 pub mod printer;
 pub mod py_feature_processor;
+pub mod py_step;
+pub mod py_worker;
+pub mod remote;
 pub mod step_join_point;
+pub mod tensor_spec;
+pub mod transforms;
+
+// The ONNX backend MLModel uses: tract when the `tract` feature is on, otherwise onnxruntime
+#[cfg(all(feature = "onnxruntime", not(feature = "tract")))]
+pub use onnx::OnnxModel;
+#[cfg(feature = "tract")]
+pub use onnx_tract::OnnxModel;
+#[cfg(not(any(feature = "onnxruntime", feature = "tract")))]
+pub use onnx_unavailable::OnnxModel;
@@ -0,0 +1,108 @@
+// src/steps/model_variants.rs
+
+use serde_json::Value as JsonValue;
+
+/// VariantSpec Struct
+///
+/// One model variant of an A/B split `MLModel` (or its shadow), from the `variants` and `shadow`
+/// params. The variant's params are merged over the step's own params, so shared settings like
+/// `feature_width` only need to be given once.
+#[derive(Debug, Clone)]
+pub struct VariantSpec {
+    pub name: String,
+    pub percent: f64, // Share of the traffic this variant gets (unused for the shadow)
+    pub params: JsonValue, // MLModel params for this variant
+}
+
+/// Reads the `variants` list. The percentages must add up to 100.
+pub fn parse_variants(params: &JsonValue) -> Result<Vec<VariantSpec>, String> {
+    let variants = match params.get("variants").and_then(|v| v.as_array()) {
+        Some(variants) => variants,
+        None => return Ok(vec![]),
+    };
+
+    let variants = variants
+        .iter()
+        .map(|variant| {
+            let spec = parse_variant(params, variant)?;
+            if spec.percent < 0.0 {
+                return Err(format!("Variant '{}' has a negative percent", spec.name));
+            }
+            Ok(spec)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let total: f64 = variants.iter().map(|variant| variant.percent).sum();
+    if !variants.is_empty() && (total - 100.0).abs() > 1e-6 {
+        return Err(format!(
+            "Variant percentages add up to {} instead of 100",
+            total
+        ));
+    }
+
+    Ok(variants)
+}
+
+/// Reads the optional `shadow` variant.
+pub fn parse_shadow(params: &JsonValue) -> Result<Option<VariantSpec>, String> {
+    params
+        .get("shadow")
+        .map(|shadow| parse_variant(params, shadow))
+        .transpose()
+}
+
+fn parse_variant(params: &JsonValue, variant: &JsonValue) -> Result<VariantSpec, String> {
+    let name = variant
+        .get("name")
+        .and_then(|n| n.as_str())
+        .ok_or_else(|| "Every variant needs a 'name'".to_string())?
+        .to_string();
+
+    let percent = variant
+        .get("percent")
+        .and_then(|p| p.as_f64())
+        .unwrap_or(0.0);
+
+    // Start from the step's own params, minus the variant settings, and apply the overrides
+    let mut merged = params.as_object().cloned().unwrap_or_default();
+    merged.remove("variants");
+    merged.remove("shadow");
+    if let Some(overrides) = variant.get("params").and_then(|p| p.as_object()) {
+        for (key, value) in overrides {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+
+    Ok(VariantSpec {
+        name,
+        percent,
+        params: JsonValue::Object(merged),
+    })
+}
+
+/// Picks the variant for a message. The choice only depends on the message ID (and the
+/// percentages), so the same ID always goes to the same variant, across runs and replicas.
+pub fn pick_variant(id: u64, percents: &[f64]) -> usize {
+    let bucket = (mix(id) % 10_000) as f64 / 100.0;
+
+    let mut cumulative = 0.0;
+    for (index, percent) in percents.iter().enumerate() {
+        cumulative += percent;
+        if bucket < cumulative {
+            return index;
+        }
+    }
+    // Only reachable through rounding; use the last variant that gets any traffic
+    percents
+        .iter()
+        .rposition(|&percent| percent > 0.0)
+        .unwrap_or(0)
+}
+
+/// SplitMix64 finalizer, so sequential IDs spread evenly over the buckets.
+fn mix(id: u64) -> u64 {
+    let mut z = id.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,458 @@
+// src/steps/onnx.rs
+
+use crate::steps::tensor_spec::{gather_features, label_to_f64, InputSpec, OutputSpec};
+use log::{debug, info};
+use ort::environment::Environment;
+use ort::memory::Allocator;
+use ort::session::builder::GraphOptimizationLevel;
+use ort::session::Session;
+use ort::tensor::TensorElementType;
+use ort::value::{DynMapValueType, DynValue, Tensor, ValueType};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Sessions already loaded, keyed by model path, so every MLModel replica pointing at the same
+/// file shares a single session (and a single copy of the model weights).
+static SESSIONS: OnceLock<Mutex<HashMap<String, Arc<Session>>>> = OnceLock::new();
+
+/// The ONNX Runtime environment shared by all sessions in the process.
+static ENVIRONMENT: OnceLock<Arc<Environment>> = OnceLock::new();
+
+fn shared_environment() -> Result<Arc<Environment>, String> {
+    if let Some(environment) = ENVIRONMENT.get() {
+        return Ok(environment.clone());
+    }
+    let environment = ort::init()
+        .with_name("MLModelEnvironment")
+        .commit()
+        .map_err(|e| format!("Failed to build environment: {:?}", e))?;
+    Ok(ENVIRONMENT.get_or_init(|| environment).clone())
+}
+
+/// Loads the ONNX model at `model_path`, or returns the session already loaded for it.
+pub fn load_session(model_path: &str) -> Result<Arc<Session>, String> {
+    let sessions = SESSIONS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut sessions = sessions
+        .lock()
+        .map_err(|_| "ONNX session cache lock was poisoned".to_string())?;
+
+    if let Some(session) = sessions.get(model_path) {
+        debug!("Reusing ONNX session for '{}'", model_path);
+        return Ok(session.clone());
+    }
+
+    shared_environment()?;
+    let session = Session::builder()
+        .and_then(|builder| builder.with_optimization_level(GraphOptimizationLevel::Level1))
+        .and_then(|builder| builder.commit_from_file(model_path))
+        .map(Arc::new)
+        .map_err(|e| format!("Failed to load ONNX model '{}': {:?}", model_path, e))?;
+
+    info!(
+        "Loaded ONNX model '{}' with inputs {:?} and outputs {:?}",
+        model_path, session.inputs, session.outputs
+    );
+    sessions.insert(model_path.to_string(), session.clone());
+    Ok(session)
+}
+
+/// An input resolved against the session: the element type is always known.
+#[derive(Debug, Clone)]
+struct ResolvedInput {
+    name: String,
+    dtype: TensorElementType,
+    indices: Option<Vec<usize>>,
+}
+
+/// OnnxModel Struct
+///
+/// A loaded ONNX session plus the mapping between message features and the model's named
+/// inputs and outputs. Every output is converted to `f64` so it can travel in a `ProcessMessage`.
+pub struct OnnxModel {
+    pub model_path: String,
+    pub feature_width: Option<usize>, // Features per row, from params or the model's declared shape
+    session: Arc<Session>,
+    inputs: Vec<ResolvedInput>,
+    outputs: Vec<OutputSpec>,
+}
+
+impl OnnxModel {
+    /// Loads (or reuses) the session and checks the `inputs`/`outputs` params against it.
+    pub fn load(
+        model_path: &str,
+        params: &JsonValue,
+        feature_width: Option<usize>,
+    ) -> Result<Self, String> {
+        let session = load_session(model_path)?;
+
+        let input_specs: Vec<InputSpec> = match params.get("inputs") {
+            Some(inputs) => serde_json::from_value(inputs.clone())
+                .map_err(|e| format!("Invalid 'inputs' for '{}': {}", model_path, e))?,
+            // Default: the model's first input gets every feature
+            None => session
+                .inputs
+                .iter()
+                .take(1)
+                .map(|input| InputSpec {
+                    name: input.name.clone(),
+                    dtype: None,
+                    indices: None,
+                })
+                .collect(),
+        };
+
+        let outputs: Vec<OutputSpec> = match params.get("outputs") {
+            Some(outputs) => serde_json::from_value(outputs.clone())
+                .map_err(|e| format!("Invalid 'outputs' for '{}': {}", model_path, e))?,
+            // Default: the model's first output
+            None => session
+                .outputs
+                .iter()
+                .take(1)
+                .map(|output| OutputSpec {
+                    name: output.name.clone(),
+                    classes: vec![],
+                })
+                .collect(),
+        };
+
+        // The model needs a value for every one of its inputs
+        for input in &session.inputs {
+            if !input_specs.iter().any(|spec| spec.name == input.name) {
+                return Err(format!(
+                    "ONNX model '{}' input '{}' is not mapped in 'inputs'",
+                    model_path, input.name
+                ));
+            }
+        }
+
+        let mut feature_width = feature_width;
+        let single_input = input_specs.len() == 1;
+        let mut inputs = Vec::new();
+        for spec in input_specs {
+            let input = session
+                .inputs
+                .iter()
+                .find(|input| input.name == spec.name)
+                .ok_or_else(|| {
+                    format!("ONNX model '{}' has no input '{}'", model_path, spec.name)
+                })?;
+
+            let (declared_type, dimensions) = match &input.input_type {
+                ValueType::Tensor { ty, dimensions, .. } => (*ty, dimensions),
+                other => {
+                    return Err(format!(
+                        "ONNX model '{}' input '{}' is {}, only tensor inputs are supported",
+                        model_path, input.name, other
+                    ))
+                }
+            };
+
+            let dtype = match &spec.dtype {
+                Some(dtype) => parse_dtype(dtype)?,
+                None => declared_type,
+            };
+
+            // Only a fixed trailing dimension tells us the width; a dynamic one accepts anything
+            let declared_width = dimensions.last().filter(|&&d| d > 0).map(|&d| d as usize);
+            let expected_width = match &spec.indices {
+                Some(indices) => Some(indices.len()),
+                None => feature_width,
+            };
+            match (expected_width, declared_width) {
+                (Some(expected), Some(declared)) if expected != declared => {
+                    return Err(format!(
+                        "ONNX model '{}' input '{}' expects {} features but is fed {}",
+                        model_path, input.name, declared, expected
+                    ));
+                }
+                (None, Some(declared)) if single_input => feature_width = Some(declared),
+                _ => {}
+            }
+
+            if let (Some(indices), Some(width)) = (&spec.indices, feature_width) {
+                if let Some(index) = indices.iter().find(|&&index| index >= width) {
+                    return Err(format!(
+                        "Input '{}' uses feature index {} but rows only have {} features",
+                        spec.name, index, width
+                    ));
+                }
+            }
+
+            inputs.push(ResolvedInput {
+                name: spec.name,
+                dtype,
+                indices: spec.indices,
+            });
+        }
+
+        for spec in &outputs {
+            let output = session
+                .outputs
+                .iter()
+                .find(|output| output.name == spec.name)
+                .ok_or_else(|| {
+                    format!("ONNX model '{}' has no output '{}'", model_path, spec.name)
+                })?;
+            check_output_type(&output.output_type).map_err(|e| {
+                format!("ONNX model '{}' output '{}': {}", model_path, spec.name, e)
+            })?;
+        }
+
+        Ok(OnnxModel {
+            model_path: model_path.to_string(),
+            feature_width,
+            session,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Runs `rows` rows of features (laid out back to back in `data`) through the model in a
+    /// single call and returns the selected outputs per row, concatenated in `outputs` order.
+    pub fn predict(&self, data: &[f64], rows: usize) -> Result<Vec<Vec<f64>>, String> {
+        let mut input_values: Vec<(String, DynValue)> = Vec::new();
+        for input in &self.inputs {
+            let values = gather_features(&input.name, input.indices.as_deref(), data, rows)?;
+            let input_width = values.len() / rows.max(1);
+            let value = build_tensor(input.dtype, [rows, input_width], values)
+                .map_err(|e| format!("Failed to create input tensor '{}': {}", input.name, e))?;
+            input_values.push((input.name.clone(), value));
+        }
+
+        // Run inference and process results
+        let outputs = self
+            .session
+            .run(input_values)
+            .map_err(|e| format!("ONNX inference failed: {:?}", e))?;
+
+        let mut predictions = vec![Vec::new(); rows];
+        for spec in &self.outputs {
+            let value = outputs
+                .get(spec.name.as_str())
+                .ok_or_else(|| format!("ONNX model produced no output '{}'", spec.name))?;
+            for (row, values) in output_rows(value, spec, rows)?.into_iter().enumerate() {
+                predictions[row].extend(values);
+            }
+        }
+
+        Ok(predictions)
+    }
+}
+
+/// Parses the `dtype` of an input spec.
+fn parse_dtype(dtype: &str) -> Result<TensorElementType, String> {
+    match dtype {
+        "float32" | "float" | "f32" => Ok(TensorElementType::Float32),
+        "float64" | "double" | "f64" => Ok(TensorElementType::Float64),
+        "int64" | "i64" => Ok(TensorElementType::Int64),
+        "int32" | "i32" => Ok(TensorElementType::Int32),
+        "bool" => Ok(TensorElementType::Bool),
+        "string" => Ok(TensorElementType::String),
+        other => Err(format!("Unsupported input dtype '{}'", other)),
+    }
+}
+
+/// Builds an input tensor of the requested element type from `f64` features.
+fn build_tensor(
+    dtype: TensorElementType,
+    shape: [usize; 2],
+    values: Vec<f64>,
+) -> Result<DynValue, String> {
+    let value = match dtype {
+        TensorElementType::Float32 => {
+            let values: Vec<f32> = values.iter().map(|&x| x as f32).collect();
+            Tensor::from_array((shape, values)).map(|t| t.into_dyn())
+        }
+        TensorElementType::Float64 => Tensor::from_array((shape, values)).map(|t| t.into_dyn()),
+        TensorElementType::Int64 => {
+            let values: Vec<i64> = values.iter().map(|&x| x as i64).collect();
+            Tensor::from_array((shape, values)).map(|t| t.into_dyn())
+        }
+        TensorElementType::Int32 => {
+            let values: Vec<i32> = values.iter().map(|&x| x as i32).collect();
+            Tensor::from_array((shape, values)).map(|t| t.into_dyn())
+        }
+        TensorElementType::Bool => {
+            let values: Vec<bool> = values.iter().map(|&x| x != 0.0).collect();
+            Tensor::from_array((shape, values)).map(|t| t.into_dyn())
+        }
+        TensorElementType::String => {
+            let values: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+            Tensor::from_string_array((shape, values)).map(|t| t.into_dyn())
+        }
+        other => return Err(format!("Unsupported input element type {}", other)),
+    };
+    value.map_err(|e| format!("{:?}", e))
+}
+
+/// Checks at load time that an output can be converted to numbers.
+fn check_output_type(output_type: &ValueType) -> Result<(), String> {
+    match output_type {
+        ValueType::Tensor { .. } => Ok(()),
+        ValueType::Sequence(element) => match element.as_ref() {
+            ValueType::Map {
+                key: TensorElementType::Int64 | TensorElementType::String,
+                value: TensorElementType::Float32 | TensorElementType::Float64,
+            } => Ok(()),
+            other => Err(format!("sequence of {} is not supported", other)),
+        },
+        other => Err(format!("{} is not supported", other)),
+    }
+}
+
+/// Converts one output value into `rows` rows of `f64`.
+fn output_rows(value: &DynValue, spec: &OutputSpec, rows: usize) -> Result<Vec<Vec<f64>>, String> {
+    let extract_error =
+        |e: ort::Error| format!("Failed to extract output '{}': {:?}", spec.name, e);
+
+    match value.dtype() {
+        ValueType::Tensor { ty, .. } => {
+            let flat: Vec<f64> = match ty {
+                TensorElementType::Float32 => {
+                    let (_, data) = value
+                        .try_extract_raw_tensor::<f32>()
+                        .map_err(extract_error)?;
+                    data.iter().map(|&x| x as f64).collect()
+                }
+                TensorElementType::Float64 => {
+                    let (_, data) = value
+                        .try_extract_raw_tensor::<f64>()
+                        .map_err(extract_error)?;
+                    data.to_vec()
+                }
+                TensorElementType::Int64 => {
+                    let (_, data) = value
+                        .try_extract_raw_tensor::<i64>()
+                        .map_err(extract_error)?;
+                    data.iter().map(|&x| x as f64).collect()
+                }
+                TensorElementType::Int32 => {
+                    let (_, data) = value
+                        .try_extract_raw_tensor::<i32>()
+                        .map_err(extract_error)?;
+                    data.iter().map(|&x| x as f64).collect()
+                }
+                TensorElementType::Bool => {
+                    let (_, data) = value
+                        .try_extract_raw_tensor::<bool>()
+                        .map_err(extract_error)?;
+                    data.iter().map(|&x| if x { 1.0 } else { 0.0 }).collect()
+                }
+                TensorElementType::String => {
+                    let (_, data) = value
+                        .try_extract_raw_string_tensor()
+                        .map_err(extract_error)?;
+                    data.iter()
+                        .map(|label| label_to_f64(label, &spec.classes))
+                        .collect::<Result<_, _>>()?
+                }
+                other => {
+                    return Err(format!(
+                        "Output '{}' has unsupported element type {}",
+                        spec.name, other
+                    ))
+                }
+            };
+
+            // The leading output dimension is the batch, so each row owns an equal share
+            let per_row = flat.len() / rows.max(1);
+            if per_row * rows != flat.len() {
+                return Err(format!(
+                    "Output '{}' has {} values for a batch of {} rows",
+                    spec.name,
+                    flat.len(),
+                    rows
+                ));
+            }
+            Ok(flat
+                .chunks(per_row.max(1))
+                .map(|row| row.to_vec())
+                .collect())
+        }
+        ValueType::Sequence(_) => {
+            // e.g. the ZipMap output of scikit-learn classifiers: one {class: probability} map per row
+            let maps = value
+                .try_extract_sequence::<DynMapValueType>(&Allocator::default())
+                .map_err(extract_error)?;
+            if maps.len() != rows {
+                return Err(format!(
+                    "Output '{}' has {} maps for a batch of {} rows",
+                    spec.name,
+                    maps.len(),
+                    rows
+                ));
+            }
+
+            maps.iter()
+                .map(|map| match map.dtype() {
+                    ValueType::Map {
+                        key: TensorElementType::Int64,
+                        value: TensorElementType::Float32,
+                    } => {
+                        let map = map.try_extract_map::<i64, f32>().map_err(extract_error)?;
+                        Ok(sorted_by_key(map.into_iter().map(|(k, v)| (k, v as f64))))
+                    }
+                    ValueType::Map {
+                        key: TensorElementType::Int64,
+                        value: TensorElementType::Float64,
+                    } => {
+                        let map = map.try_extract_map::<i64, f64>().map_err(extract_error)?;
+                        Ok(sorted_by_key(map.into_iter()))
+                    }
+                    ValueType::Map {
+                        key: TensorElementType::String,
+                        value: TensorElementType::Float32,
+                    } => {
+                        let map = map
+                            .try_extract_map::<String, f32>()
+                            .map_err(extract_error)?;
+                        Ok(by_class(
+                            map.into_iter().map(|(k, v)| (k, v as f64)).collect(),
+                            &spec.classes,
+                        ))
+                    }
+                    ValueType::Map {
+                        key: TensorElementType::String,
+                        value: TensorElementType::Float64,
+                    } => {
+                        let map = map
+                            .try_extract_map::<String, f64>()
+                            .map_err(extract_error)?;
+                        Ok(by_class(map, &spec.classes))
+                    }
+                    other => Err(format!(
+                        "Output '{}' contains unsupported {}",
+                        spec.name, other
+                    )),
+                })
+                .collect()
+        }
+        other => Err(format!(
+            "Output '{}' has unsupported type {}",
+            spec.name, other
+        )),
+    }
+}
+
+/// Orders map values by their integer key.
+fn sorted_by_key(entries: impl Iterator<Item = (i64, f64)>) -> Vec<f64> {
+    let mut entries: Vec<(i64, f64)> = entries.collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries.into_iter().map(|(_, value)| value).collect()
+}
+
+/// Orders map values by `classes` (missing classes become 0), or by key when no classes are given.
+fn by_class(map: HashMap<String, f64>, classes: &[String]) -> Vec<f64> {
+    if classes.is_empty() {
+        let mut entries: Vec<(String, f64)> = map.into_iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.into_iter().map(|(_, value)| value).collect()
+    } else {
+        classes
+            .iter()
+            .map(|class| map.get(class).copied().unwrap_or(0.0))
+            .collect()
+    }
+}
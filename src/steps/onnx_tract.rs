@@ -0,0 +1,471 @@
+// src/steps/onnx_tract.rs
+//
+// The ONNX backend used with the `tract` feature: the same params as `onnx.rs`, run on the
+// pure-Rust tract runtime instead of the native onnxruntime library.
+
+use crate::steps::tensor_spec::{gather_features, label_to_f64, InputSpec, OutputSpec};
+use log::{debug, info};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tract_onnx::pb::ModelProto;
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+
+type Plan = TypedRunnableModel<TypedModel>;
+
+/// Plans already built, keyed by model path and input facts, so every MLModel replica with the
+/// same model and `inputs` shares a single plan (and a single copy of the model weights).
+static PLANS: OnceLock<Mutex<HashMap<String, Arc<Plan>>>> = OnceLock::new();
+
+/// A graph input, in the order the model declares them.
+#[derive(Debug, Clone)]
+struct ResolvedInput {
+    name: String,
+    dtype: DatumType,
+    indices: Option<Vec<usize>>,
+}
+
+/// A binary `TreeEnsembleClassifier` and its outputs. tract picks the label of such a classifier
+/// before expanding its single score into two class probabilities, so the label always comes out
+/// as the first class; onnxruntime picks the more likely class.
+#[derive(Debug, Clone)]
+struct BinaryClassifier {
+    label: String,         // Output holding the label
+    probabilities: String, // Output holding both class probabilities
+    classes: Vec<String>,  // The two class labels
+    numeric: bool,         // The labels are `classlabels_int64s`, so they are output as numbers
+}
+
+/// OnnxModel Struct
+///
+/// A tract plan for an ONNX model plus the mapping between message features and the model's
+/// named inputs and outputs. Every output is converted to `f64` so it can travel in a
+/// `ProcessMessage`.
+pub struct OnnxModel {
+    pub model_path: String,
+    pub feature_width: Option<usize>, // Features per row, from params or the model's declared shape
+    plan: Arc<Plan>,
+    inputs: Vec<ResolvedInput>,
+    output_names: Vec<String>, // Every output the plan produces, in order
+    outputs: Vec<OutputSpec>,
+    binary_classifiers: Vec<BinaryClassifier>,
+}
+
+impl OnnxModel {
+    /// Parses the model, checks the `inputs`/`outputs` params against it and builds (or reuses)
+    /// a plan for `[rows, width]` inputs of the requested types.
+    pub fn load(
+        model_path: &str,
+        params: &JsonValue,
+        feature_width: Option<usize>,
+    ) -> Result<Self, String> {
+        let onnx = tract_onnx::onnx();
+        let proto = onnx
+            .proto_model_for_path(model_path)
+            .map_err(|e| format!("Failed to read ONNX model '{}': {:?}", model_path, e))?;
+        let mut model = onnx
+            .model_for_proto_model(&proto)
+            .map_err(|e| format!("Failed to load ONNX model '{}': {:?}", model_path, e))?;
+
+        let model_inputs: Vec<String> = model
+            .input_outlets()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .map(|outlet| model.node(outlet.node).name.clone())
+            .collect();
+        let output_names: Vec<String> = model
+            .output_outlets()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .map(|outlet| {
+                model
+                    .outlet_label(*outlet)
+                    .unwrap_or(&model.node(outlet.node).name)
+                    .to_string()
+            })
+            .collect();
+
+        let input_specs: Vec<InputSpec> = match params.get("inputs") {
+            Some(inputs) => serde_json::from_value(inputs.clone())
+                .map_err(|e| format!("Invalid 'inputs' for '{}': {}", model_path, e))?,
+            // Default: the model's first input gets every feature
+            None => model_inputs
+                .iter()
+                .take(1)
+                .map(|name| InputSpec {
+                    name: name.clone(),
+                    dtype: None,
+                    indices: None,
+                })
+                .collect(),
+        };
+
+        let outputs: Vec<OutputSpec> = match params.get("outputs") {
+            Some(outputs) => serde_json::from_value(outputs.clone())
+                .map_err(|e| format!("Invalid 'outputs' for '{}': {}", model_path, e))?,
+            // Default: the model's first output
+            None => output_names
+                .iter()
+                .take(1)
+                .map(|name| OutputSpec {
+                    name: name.clone(),
+                    classes: vec![],
+                })
+                .collect(),
+        };
+
+        for spec in &input_specs {
+            if !model_inputs.contains(&spec.name) {
+                return Err(format!(
+                    "ONNX model '{}' has no input '{}'",
+                    model_path, spec.name
+                ));
+            }
+        }
+
+        let mut feature_width = feature_width;
+        let single_input = input_specs.len() == 1;
+        let batch = model.symbols.sym("N");
+        let mut inputs = Vec::new();
+        let mut facts = Vec::new(); // The type and width each input was compiled for
+        for (index, name) in model_inputs.iter().enumerate() {
+            // The model needs a value for every one of its inputs
+            let spec = input_specs
+                .iter()
+                .find(|spec| &spec.name == name)
+                .ok_or_else(|| {
+                    format!(
+                        "ONNX model '{}' input '{}' is not mapped in 'inputs'",
+                        model_path, name
+                    )
+                })?;
+
+            let fact = model.input_fact(index).map_err(|e| format!("{:?}", e))?;
+            let declared_type = fact.datum_type.concretize();
+            // Only a fixed trailing dimension tells us the width; a dynamic one accepts anything
+            let declared_width = fact
+                .shape
+                .dims()
+                .last()
+                .and_then(|dim| dim.concretize())
+                .and_then(|dim| dim.as_i64())
+                .filter(|&dim| dim > 0)
+                .map(|dim| dim as usize);
+
+            let dtype = match (&spec.dtype, declared_type) {
+                (Some(dtype), _) => parse_dtype(dtype)?,
+                (None, Some(declared)) => declared,
+                (None, None) => f32::datum_type(),
+            };
+
+            let expected_width = match &spec.indices {
+                Some(indices) => Some(indices.len()),
+                None => feature_width,
+            };
+            match (expected_width, declared_width) {
+                (Some(expected), Some(declared)) if expected != declared => {
+                    return Err(format!(
+                        "ONNX model '{}' input '{}' expects {} features but is fed {}",
+                        model_path, name, declared, expected
+                    ));
+                }
+                (None, Some(declared)) if single_input => feature_width = Some(declared),
+                _ => {}
+            }
+
+            if let (Some(indices), Some(width)) = (&spec.indices, feature_width) {
+                if let Some(index) = indices.iter().find(|&&index| index >= width) {
+                    return Err(format!(
+                        "Input '{}' uses feature index {} but rows only have {} features",
+                        spec.name, index, width
+                    ));
+                }
+            }
+
+            // Rows are batched on the first axis; the width is left symbolic if nothing fixes it
+            let resolved_width = expected_width.or(declared_width);
+            facts.push((dtype, resolved_width));
+            let width: TDim = match resolved_width {
+                Some(width) => width.into(),
+                None => model.symbols.sym(&format!("W{}", index)).into(),
+            };
+            model = model
+                .with_input_fact(
+                    index,
+                    InferenceFact::dt_shape(dtype, [batch.clone().into(), width]),
+                )
+                .map_err(|e| format!("{:?}", e))?;
+
+            inputs.push(ResolvedInput {
+                name: name.clone(),
+                dtype,
+                indices: spec.indices.clone(),
+            });
+        }
+
+        for spec in &outputs {
+            if !output_names.contains(&spec.name) {
+                return Err(format!(
+                    "ONNX model '{}' has no output '{}'",
+                    model_path, spec.name
+                ));
+            }
+        }
+
+        // Models fed other types or widths need plans of their own
+        let key = format!("{}|{:?}", model_path, facts);
+        let plan = load_plan(&key, model, model_path)?;
+
+        for (index, name) in output_names.iter().enumerate() {
+            let fact = plan
+                .model()
+                .output_fact(index)
+                .map_err(|e| format!("{:?}", e))?;
+            if outputs.iter().any(|spec| &spec.name == name) {
+                check_output_type(fact.datum_type)
+                    .map_err(|e| format!("ONNX model '{}' output '{}': {}", model_path, name, e))?;
+            }
+        }
+
+        Ok(OnnxModel {
+            model_path: model_path.to_string(),
+            feature_width,
+            plan,
+            inputs,
+            binary_classifiers: binary_classifiers(&proto),
+            output_names,
+            outputs,
+        })
+    }
+
+    /// Runs `rows` rows of features (laid out back to back in `data`) through the model in a
+    /// single call and returns the selected outputs per row, concatenated in `outputs` order.
+    pub fn predict(&self, data: &[f64], rows: usize) -> Result<Vec<Vec<f64>>, String> {
+        let mut input_values: TVec<TValue> = tvec!();
+        for input in &self.inputs {
+            let values = gather_features(&input.name, input.indices.as_deref(), data, rows)?;
+            let input_width = values.len() / rows.max(1);
+            let tensor = build_tensor(input.dtype, [rows, input_width], values)
+                .map_err(|e| format!("Failed to create input tensor '{}': {}", input.name, e))?;
+            input_values.push(tensor.into());
+        }
+
+        // Run inference and process results
+        let results = self
+            .plan
+            .run(input_values)
+            .map_err(|e| format!("ONNX inference failed: {:?}", e))?;
+        let by_name: HashMap<&str, &Tensor> = self
+            .output_names
+            .iter()
+            .map(|name| name.as_str())
+            .zip(results.iter().map(|value| &**value))
+            .collect();
+
+        let mut predictions = vec![Vec::new(); rows];
+        for spec in &self.outputs {
+            let value = by_name
+                .get(spec.name.as_str())
+                .ok_or_else(|| format!("ONNX model produced no output '{}'", spec.name))?;
+
+            let values = match self
+                .binary_classifiers
+                .iter()
+                .find(|classifier| classifier.label == spec.name)
+            {
+                Some(classifier) => binary_labels(classifier, &by_name, spec, rows)?,
+                None => output_rows(value, spec, rows)?,
+            };
+            for (row, values) in values.into_iter().enumerate() {
+                predictions[row].extend(values);
+            }
+        }
+
+        Ok(predictions)
+    }
+}
+
+/// Optimizes the model into a runnable plan, or returns the plan already built for `key`.
+fn load_plan(key: &str, model: InferenceModel, model_path: &str) -> Result<Arc<Plan>, String> {
+    let plans = PLANS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut plans = plans
+        .lock()
+        .map_err(|_| "ONNX plan cache lock was poisoned".to_string())?;
+
+    if let Some(plan) = plans.get(key) {
+        debug!("Reusing tract plan for '{}'", model_path);
+        return Ok(plan.clone());
+    }
+
+    let plan = model
+        .into_optimized()
+        .and_then(|model| model.into_runnable())
+        .map(Arc::new)
+        .map_err(|e| format!("Failed to load ONNX model '{}': {:?}", model_path, e))?;
+
+    info!("Loaded ONNX model '{}' with tract", model_path);
+    plans.insert(key.to_string(), plan.clone());
+    Ok(plan)
+}
+
+/// Parses the `dtype` of an input spec.
+fn parse_dtype(dtype: &str) -> Result<DatumType, String> {
+    match dtype {
+        "float32" | "float" | "f32" => Ok(f32::datum_type()),
+        "float64" | "double" | "f64" => Ok(f64::datum_type()),
+        "int64" | "i64" => Ok(i64::datum_type()),
+        "int32" | "i32" => Ok(i32::datum_type()),
+        "bool" => Ok(bool::datum_type()),
+        "string" => Ok(String::datum_type()),
+        other => Err(format!("Unsupported input dtype '{}'", other)),
+    }
+}
+
+/// Builds an input tensor of the requested element type from `f64` features.
+fn build_tensor(dtype: DatumType, shape: [usize; 2], values: Vec<f64>) -> Result<Tensor, String> {
+    let tensor = if dtype == String::datum_type() {
+        let values: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+        tract_ndarray::Array2::from_shape_vec(shape, values)
+            .map_err(|e| format!("{:?}", e))?
+            .into_tensor()
+    } else {
+        tract_ndarray::Array2::from_shape_vec(shape, values)
+            .map_err(|e| format!("{:?}", e))?
+            .into_tensor()
+            .cast_to_dt(dtype)
+            .map_err(|e| format!("{:?}", e))?
+            .into_owned()
+    };
+    Ok(tensor)
+}
+
+/// Checks at load time that an output can be converted to numbers.
+fn check_output_type(dtype: DatumType) -> Result<(), String> {
+    if dtype.is_number() || dtype == bool::datum_type() || dtype == String::datum_type() {
+        Ok(())
+    } else {
+        Err(format!("{:?} is not supported", dtype))
+    }
+}
+
+/// Converts one output tensor into `rows` rows of `f64`.
+fn output_rows(value: &Tensor, spec: &OutputSpec, rows: usize) -> Result<Vec<Vec<f64>>, String> {
+    let extract_error =
+        |e: TractError| format!("Failed to extract output '{}': {:?}", spec.name, e);
+
+    let flat: Vec<f64> = if value.datum_type() == String::datum_type() {
+        value
+            .as_slice::<String>()
+            .map_err(extract_error)?
+            .iter()
+            .map(|label| label_to_f64(label, &spec.classes))
+            .collect::<Result<_, _>>()?
+    } else {
+        value
+            .cast_to::<f64>()
+            .map_err(extract_error)?
+            .as_slice::<f64>()
+            .map_err(extract_error)?
+            .to_vec()
+    };
+
+    // The leading output dimension is the batch, so each row owns an equal share
+    let per_row = flat.len() / rows.max(1);
+    if per_row * rows != flat.len() {
+        return Err(format!(
+            "Output '{}' has {} values for a batch of {} rows",
+            spec.name,
+            flat.len(),
+            rows
+        ));
+    }
+    Ok(flat
+        .chunks(per_row.max(1))
+        .map(|row| row.to_vec())
+        .collect())
+}
+
+/// Finds the binary `TreeEnsembleClassifier`s (two class labels, every leaf weight on the first
+/// class id) whose label tract gets wrong. Only those without a `post_transform` are handled.
+fn binary_classifiers(proto: &ModelProto) -> Vec<BinaryClassifier> {
+    let nodes = proto.graph.iter().flat_map(|graph| graph.node.iter());
+    nodes
+        .filter(|node| node.op_type == "TreeEnsembleClassifier" && node.output.len() == 2)
+        .filter_map(|node| {
+            let attribute = |name: &str| node.attribute.iter().find(|a| a.name == name);
+
+            let numeric = attribute("classlabels_int64s").is_some();
+            let classes: Vec<String> = match attribute("classlabels_int64s") {
+                Some(labels) => labels.ints.iter().map(|l| l.to_string()).collect(),
+                None => attribute("classlabels_strings")?
+                    .strings
+                    .iter()
+                    .map(|l| String::from_utf8_lossy(l).into_owned())
+                    .collect(),
+            };
+            let single_class = attribute("class_ids")?.ints.iter().all(|&id| id == 0);
+            let post_transform = attribute("post_transform")
+                .map(|a| String::from_utf8_lossy(&a.s).into_owned())
+                .unwrap_or_else(|| "NONE".to_string());
+
+            (classes.len() == 2 && single_class && post_transform == "NONE").then(|| {
+                BinaryClassifier {
+                    label: node.output[0].clone(),
+                    probabilities: node.output[1].clone(),
+                    classes,
+                    numeric,
+                }
+            })
+        })
+        .collect()
+}
+
+/// The label of each row of a binary classifier: the class with the higher probability, the
+/// first on a tie, as onnxruntime reports it.
+fn binary_labels(
+    classifier: &BinaryClassifier,
+    outputs: &HashMap<&str, &Tensor>,
+    spec: &OutputSpec,
+    rows: usize,
+) -> Result<Vec<Vec<f64>>, String> {
+    let probabilities = outputs
+        .get(classifier.probabilities.as_str())
+        .ok_or_else(|| {
+            format!(
+                "ONNX model produced no output '{}'",
+                classifier.probabilities
+            )
+        })?;
+    let probabilities = output_rows(
+        probabilities,
+        &OutputSpec {
+            name: classifier.probabilities.clone(),
+            classes: vec![],
+        },
+        rows,
+    )?;
+
+    probabilities
+        .iter()
+        .map(|row| {
+            let [first, second] = row[..] else {
+                return Err(format!(
+                    "Output '{}' has {} probabilities per row but the classifier has 2 classes",
+                    classifier.probabilities,
+                    row.len()
+                ));
+            };
+            let class = &classifier.classes[(second > first) as usize];
+            if classifier.numeric {
+                // Like any numeric output, integer labels are passed through as they are
+                class
+                    .parse::<f64>()
+                    .map(|label| vec![label])
+                    .map_err(|e| e.to_string())
+            } else {
+                label_to_f64(class, &spec.classes).map(|label| vec![label])
+            }
+        })
+        .collect()
+}
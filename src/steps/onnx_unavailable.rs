@@ -0,0 +1,31 @@
+// src/steps/onnx_unavailable.rs
+//
+// Stands in for the ONNX backends when the crate is built with neither the `onnxruntime` nor the
+// `tract` feature, so configs that use ONNX models fail at startup with a clear error.
+
+use serde_json::Value as JsonValue;
+
+const UNAVAILABLE: &str =
+    "ONNX models are not available in this build (enable the `onnxruntime` or `tract` feature)";
+
+/// OnnxModel Struct
+///
+/// Never loads; see `onnx.rs` and `onnx_tract.rs` for the real backends.
+pub struct OnnxModel {
+    pub model_path: String,
+    pub feature_width: Option<usize>,
+}
+
+impl OnnxModel {
+    pub fn load(
+        _model_path: &str,
+        _params: &JsonValue,
+        _feature_width: Option<usize>,
+    ) -> Result<Self, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn predict(&self, _data: &[f64], _rows: usize) -> Result<Vec<Vec<f64>>, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+}
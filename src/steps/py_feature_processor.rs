@@ -1,18 +1,310 @@
 use crate::coordinator::Coordinator;
-use crate::messages::ProcessMessage;
+use crate::messages::{DeadLetter, ProcessMessage, StepFailing};
 use crate::step::Step;
-use crate::step::TraceStep;
+use crate::step::{StepError, TraceStep};
+use crate::steps::py_worker::{PyWorkerPool, WorkerCommand};
 use actix::prelude::*;
-use log::{error, info};
+use log::{error, info, warn};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyBufferError;
 use pyo3::prelude::*;
-use pyo3::types::PyDict;
+use pyo3::types::PyModule;
+use pyo3::{ffi, AsPyPointer};
 use serde_json::Value;
 use std::env;
+use std::ffi::CStr;
 use std::fs;
-use std::time::Instant;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::Path;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+/// The buffer-protocol format of a float64.
+const FLOAT64_FORMAT: &CStr = c"d";
+
+/// FeatureArray Struct
+///
+/// Features lent to Python through the buffer protocol, so `numpy.asarray` views them in place
+/// instead of boxing every value into a Python float. One row is 1-D, a pooled batch 2-D
+/// `(rows, width)`. The view is read-only.
+#[pyclass]
+pub struct FeatureArray {
+    data: Vec<f64>,
+    shape: [isize; 2],
+    strides: [isize; 2],
+    ndim: c_int,
+}
+
+impl FeatureArray {
+    pub fn new(data: Vec<f64>, rows: usize) -> FeatureArray {
+        if rows > 1 {
+            return FeatureArray::matrix(data, rows);
+        }
+        let item_size = mem::size_of::<f64>() as isize;
+        FeatureArray {
+            shape: [data.len() as isize, 0],
+            strides: [item_size, 0],
+            ndim: 1,
+            data,
+        }
+    }
+
+    /// A 2-D `(rows, width)` array, even for a single row.
+    pub fn matrix(data: Vec<f64>, rows: usize) -> FeatureArray {
+        let item_size = mem::size_of::<f64>() as isize;
+        let rows = rows.max(1) as isize;
+        let width = data.len() as isize / rows;
+        FeatureArray {
+            data,
+            shape: [rows, width],
+            strides: [width * item_size, item_size],
+            ndim: 2,
+        }
+    }
+}
+
+#[pymethods]
+impl FeatureArray {
+    unsafe fn __getbuffer__(
+        mut slf: PyRefMut<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+        if (flags & ffi::PyBUF_WRITABLE) == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("Features are read-only"));
+        }
+
+        (*view).obj = ffi::_Py_NewRef(slf.as_ptr());
+        (*view).buf = slf.data.as_ptr() as *mut c_void;
+        (*view).len = (slf.data.len() * mem::size_of::<f64>()) as isize;
+        (*view).readonly = 1;
+        (*view).itemsize = mem::size_of::<f64>() as isize;
+        (*view).format = if (flags & ffi::PyBUF_FORMAT) == ffi::PyBUF_FORMAT {
+            FLOAT64_FORMAT.as_ptr() as *mut c_char
+        } else {
+            ptr::null_mut()
+        };
+        (*view).ndim = slf.ndim;
+        (*view).shape = slf.shape.as_mut_ptr();
+        (*view).strides = slf.strides.as_mut_ptr();
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = ptr::null_mut();
+        Ok(())
+    }
+}
+
+/// PyScript Struct
+///
+/// A Python script imported once as a module, and the function in it that's called per message.
+/// Module-level state, like a loaded model or a lookup table, lives as long as the step.
+pub struct PyScript {
+    pub path: String,
+    function: PyObject,        // Keeps the module's globals alive too
+    asarray: Option<PyObject>, // `numpy.asarray` when the function takes NumPy arrays
+}
+
+impl PyScript {
+    /// Imports the script and looks up `function` in it. With `numpy` the function gets a NumPy
+    /// array instead of a list, so NumPy must be importable.
+    pub fn load(path: &str, function: &str, numpy: bool) -> Result<PyScript, String> {
+        Python::with_gil(|py| {
+            let module = import_script(py, path)?;
+            let callable = module
+                .getattr(function)
+                .map_err(|_| format!("Python script '{}' has no function '{}'", path, function))?;
+            if !callable.is_callable() {
+                return Err(format!(
+                    "'{}' in Python script '{}' is not callable",
+                    function, path
+                ));
+            }
+            let asarray = if numpy {
+                Some(numpy_asarray(py)?)
+            } else {
+                None
+            };
+            Ok(PyScript {
+                path: path.to_string(),
+                function: callable.into(),
+                asarray,
+            })
+        })
+    }
+
+    /// Calls the function with the features, `rows` rows of them, returning its output and
+    /// anything it printed. A failure carries the traceback and the output too.
+    pub fn call(&self, data: &[f64], rows: usize) -> Result<(Vec<f64>, String), StepError> {
+        Python::with_gil(|py| {
+            let input: PyObject = match &self.asarray {
+                Some(asarray) => Py::new(py, FeatureArray::new(data.to_vec(), rows))
+                    .and_then(|features| asarray.call1(py, (features,)))
+                    .map_err(|e| python_error(py, &e, "Failed to share features with Python"))?,
+                None => data.to_vec().into_py(py),
+            };
+            let (result, captured) = capture_output(py, || self.function.call1(py, (input,)))
+                .map_err(|e| python_error(py, &e, "Failed to capture Python output"))?;
+            let output = result
+                .map_err(|e| python_error(py, &e, "Python function failed"))
+                .and_then(|result| {
+                    extract_features(result.as_ref(py), rows).map_err(StepError::new)
+                });
+            match output {
+                Ok(output) => Ok((output, captured)),
+                Err(e) => Err(e.with_output(captured)),
+            }
+        })
+    }
+}
+
+/// Imports a script as a module named after the file, logging anything it prints.
+pub(crate) fn import_script<'p>(py: Python<'p>, path: &str) -> Result<&'p PyModule, String> {
+    let code = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read Python script at '{}': {}", path, e))?;
+    let module_name = Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("script");
+
+    let (module, captured) =
+        capture_output(py, || PyModule::from_code(py, &code, path, module_name))
+            .map_err(|e| format!("Failed to capture Python output: {}", e))?;
+    let module = module.map_err(|e| {
+        python_error(
+            py,
+            &e,
+            &format!("Failed to import Python script '{}'", path),
+        )
+        .with_output(captured.clone())
+        .to_string()
+    })?;
+    if !captured.is_empty() {
+        info!("Python script '{}' printed on import: {}", path, captured);
+    }
+    Ok(module)
+}
+
+/// `numpy.asarray`, for steps that take NumPy arrays.
+pub(crate) fn numpy_asarray(py: Python) -> Result<PyObject, String> {
+    py.import("numpy")
+        .and_then(|numpy| numpy.getattr("asarray"))
+        .map(|asarray| asarray.into())
+        .map_err(|e| format!("'numpy' needs NumPy to be importable: {}", e))
+}
+
+/// Describes a Python exception: its type and message, and the traceback if there is one.
+pub(crate) fn python_error(py: Python, error: &PyErr, context: &str) -> StepError {
+    let exception = error.to_string();
+    StepError {
+        message: format!("{}: {}", context, exception),
+        traceback: error
+            .traceback(py)
+            .and_then(|traceback| traceback.format().ok())
+            .map(|traceback| format!("{}{}", traceback, exception)),
+        output: None,
+        limit: None,
+    }
+}
+
+/// Reads what the function returned: a NumPy array of numbers (1-D, or 2-D with one row per
+/// input row), any other buffer of float64, or a sequence of numbers.
+pub(crate) fn extract_features(result: &PyAny, rows: usize) -> Result<Vec<f64>, String> {
+    let py = result.py();
+    let buffer = match PyBuffer::<f64>::get(result) {
+        Ok(buffer) => Some(buffer),
+        Err(_) if result.hasattr("dtype").unwrap_or(false) => {
+            let dtype = result
+                .getattr("dtype")
+                .map_err(|e| format!("Python function returned an unreadable array: {}", e))?;
+            let kind: String = dtype
+                .getattr("kind")
+                .and_then(|k| k.extract())
+                .unwrap_or_default();
+            if !matches!(kind.as_str(), "b" | "i" | "u" | "f") {
+                return Err(format!(
+                    "Python function must return numbers, got an array of dtype {}",
+                    dtype
+                ));
+            }
+            let converted = result
+                .call_method1("astype", ("float64",))
+                .and_then(PyBuffer::<f64>::get)
+                .map_err(|e| format!("Failed to convert the returned array: {}", e))?;
+            Some(converted)
+        }
+        Err(_) => None,
+    };
+
+    match buffer {
+        Some(buffer) => {
+            let shape = buffer.shape();
+            match shape.len() {
+                1 => {}
+                2 if shape[0] == rows.max(1) => {}
+                2 => {
+                    return Err(format!(
+                        "Python function returned {} rows for {} input rows",
+                        shape[0],
+                        rows.max(1)
+                    ))
+                }
+                dimensions => {
+                    return Err(format!(
+                        "Python function must return a 1-D or 2-D array, got {} dimensions",
+                        dimensions
+                    ))
+                }
+            }
+            buffer
+                .to_vec(py)
+                .map_err(|e| format!("Failed to read the returned array: {}", e))
+        }
+        None => result.extract::<Vec<f64>>().map_err(|e| {
+            format!(
+                "Python function must return an array or list of numbers: {}",
+                e
+            )
+        }),
+    }
+}
+
+/// Runs `f` with `sys.stdout` and `sys.stderr` redirected to one buffer, returning its result and
+/// what it printed.
+pub(crate) fn capture_output<T>(py: Python, f: impl FnOnce() -> T) -> PyResult<(T, String)> {
+    let sys = py.import("sys")?;
+    let buffer = py.import("io")?.getattr("StringIO")?.call0()?;
+    let (stdout, stderr) = (sys.getattr("stdout")?, sys.getattr("stderr")?);
+    sys.setattr("stdout", buffer)?;
+    sys.setattr("stderr", buffer)?;
+    let result = f();
+    sys.setattr("stdout", stdout)?;
+    sys.setattr("stderr", stderr)?;
+    let captured: String = buffer.call_method0("getvalue")?.extract()?;
+    Ok((result, captured))
+}
+
+/// Where a `PyFeatureProcessor` runs its script.
+enum PyBackend {
+    InProcess(PyScript),   // The step's own interpreter, through pyo3
+    Workers(PyWorkerPool), // A pool of Python subprocesses
+}
 
 /// The idea of this processor is an escape-hatch to let you just play in Python.
 /// Obviously it would not be very performant in Production settings.
+///
+/// The script is imported once, by `load_script`, and its `function` (default `process`) is
+/// called with each message's features, as a list or, with `numpy`, a NumPy array (2-D for a
+/// pooled batch). Whatever it prints is captured and logged. When it raises or returns something
+/// that isn't numbers, the error, traceback and output go into the message's trace. With
+/// `workers` the script runs in that many Python subprocesses instead of in-process.
+///
+/// `call_timeout_ms` and `memory_limit_mb` bound each call; they need a subprocess to interrupt,
+/// so either starts one worker when `workers` isn't set. After `max_limit_violations` calls in a
+/// row exceed a limit the step is marked failing: it dead-letters messages without calling the
+/// script, and the health endpoint reports it.
 pub struct PyFeatureProcessor {
     pub name: String,
     pub output_name: String,
@@ -20,7 +312,13 @@ pub struct PyFeatureProcessor {
     pub python_home: Option<String>,   // Path to Python environment
     pub python_path: Option<String>,   // Path to Python packages
     pub python_script: Option<String>, // Path to the Python script
+    pub function: String,              // Name of the function called per message
+    pub numpy: bool,                   // Pass NumPy arrays instead of lists
     pub params: Value,                 // Parameters used in the processor
+    pub max_limit_violations: u32,     // Calls in a row over a limit before the step is failing
+    limit_violations: u32,             // Calls in a row that exceeded a limit so far
+    failing: Option<String>,           // Why the step stopped calling the script, once it has
+    backend: Option<PyBackend>,        // Set by `load_script` before the actor starts
 }
 
 impl PyFeatureProcessor {
@@ -38,50 +336,165 @@ impl PyFeatureProcessor {
         );
     }
 
-    /// Load and run the Python script, passing the input data
-    fn run_python_script(&self, data: Vec<f64>) -> Option<Vec<f64>> {
-        if let Some(ref script_path) = self.python_script {
-            // Read the Python script from the specified path
-            let script_content = fs::read_to_string(script_path);
-            match script_content {
-                Ok(script) => {
-                    // Execute the Python code
-                    Python::with_gil(|py| {
-                        let locals = PyDict::new(py);
-                        locals.set_item("input_data", data.clone()).unwrap();
-
-                        let result = py.run(&script, None, Some(locals));
-                        match result {
-                            Ok(_) => {
-                                let output: Vec<f64> = locals
-                                    .get_item("output_data")
-                                    .unwrap()
-                                    .extract::<Vec<f64>>()
-                                    .unwrap();
-                                // Extract captured stdout
-                                let captured_output: String = locals
-                                    .get_item("captured_output")
-                                    .unwrap()
-                                    .extract::<String>()
-                                    .unwrap_or("None".to_owned());
-                                info!("Captured Python output was: {}", captured_output);
-                                Some(output)
-                            }
-                            Err(e) => {
-                                error!("Python script execution failed: {:?}", e);
-                                None
-                            }
-                        }
-                    })
+    /// Imports the script, or starts the workers that import it. The Coordinator calls this while
+    /// handling `Initialize`, so a script that doesn't import, or lacks the function, fails the DAG
+    /// at startup.
+    pub fn load_script(&mut self) -> Result<(), String> {
+        let script_path = self
+            .python_script
+            .clone()
+            .ok_or("No Python script path provided in configuration.")?;
+
+        let timeout = self
+            .params
+            .get("call_timeout_ms")
+            .and_then(|t| t.as_u64())
+            .map(Duration::from_millis);
+        let memory_limit_mb = self.params.get("memory_limit_mb").and_then(|m| m.as_u64());
+        // Limits are enforced by killing or capping a worker process, so they need at least one
+        let workers = self
+            .params
+            .get("workers")
+            .and_then(|w| w.as_u64())
+            .or((timeout.is_some() || memory_limit_mb.is_some()).then_some(1));
+        self.backend = Some(match workers {
+            Some(workers) => {
+                let env = [
+                    ("PYTHONHOME", &self.python_home),
+                    ("PYTHONPATH", &self.python_path),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.to_string(), value.clone()?)))
+                .collect();
+                let command = WorkerCommand {
+                    python: self
+                        .params
+                        .get("python_executable")
+                        .and_then(|p| p.as_str())
+                        .unwrap_or("python3")
+                        .to_string(),
+                    script: script_path,
+                    function: self.function.clone(),
+                    numpy: self.numpy,
+                    memory_limit_mb,
+                    env,
+                };
+                PyBackend::Workers(PyWorkerPool::start(command, workers as usize, timeout)?)
+            }
+            None => {
+                self.initialize_python_env();
+                PyBackend::InProcess(PyScript::load(&script_path, &self.function, self.numpy)?)
+            }
+        });
+        Ok(())
+    }
+
+    /// Passes a call's result on, counting calls that exceeded a limit and marking the step
+    /// failing once too many in a row have.
+    fn finish(
+        &mut self,
+        msg: ProcessMessage,
+        start_time: Instant,
+        processed: Result<(Vec<f64>, String), StepError>,
+    ) {
+        match &processed {
+            Err(StepError {
+                limit: Some(limit), ..
+            }) => {
+                self.limit_violations += 1;
+                warn!(
+                    "PyFeatureProcessor '{}' exceeded its {} limit ({} in a row)",
+                    self.name, limit, self.limit_violations
+                );
+                if self.limit_violations >= self.max_limit_violations && self.failing.is_none() {
+                    let reason = format!(
+                        "exceeded its {} limit on {} calls in a row",
+                        limit, self.limit_violations
+                    );
+                    self.coordinator.do_send(StepFailing {
+                        step_name: self.name.clone(),
+                        reason: reason.clone(),
+                    });
+                    self.failing = Some(reason);
                 }
-                Err(e) => {
-                    error!("Failed to read Python script at '{}': {:?}", script_path, e);
-                    None
+            }
+            Err(_) => {}
+            Ok(_) => self.limit_violations = 0,
+        }
+        self.outlet().finish(msg, start_time, processed);
+    }
+
+    fn outlet(&self) -> PyOutlet {
+        PyOutlet {
+            step_type: "PyFeatureProcessor".to_string(),
+            name: self.name.clone(),
+            output_name: self.output_name.clone(),
+            coordinator: self.coordinator.clone(),
+            params: self.params.clone(),
+        }
+    }
+}
+
+/// What a Python step needs to pass a result on: downstream, or to the dead letters with the
+/// failure in the trace.
+pub(crate) struct PyOutlet {
+    pub step_type: String, // For the logs
+    pub name: String,
+    pub output_name: String,
+    pub coordinator: Addr<Coordinator>,
+    pub params: Value,
+}
+
+impl PyOutlet {
+    /// Sends the script's output downstream, or dead-letters the message with the failure recorded
+    /// in its trace.
+    pub fn finish(
+        &self,
+        mut msg: ProcessMessage,
+        start_time: Instant,
+        processed: Result<(Vec<f64>, String), StepError>,
+    ) {
+        match processed {
+            Ok((output, captured)) => {
+                if !captured.is_empty() {
+                    info!("Captured Python output was: {}", captured);
                 }
+                // Update the trace
+                let duration = start_time.elapsed();
+                let trace_step = TraceStep::new(&self.name, duration, self.params.clone());
+                msg.trace.add_step(trace_step);
+
+                self.coordinator.do_send(ProcessMessage {
+                    id: msg.id,
+                    node_id: self.output_name.clone(),
+                    data: output,
+                    batch_id: msg.batch_id,
+                    batch_total: msg.batch_total,
+                    trace: msg.trace.clone(),
+                    member_ids: msg.member_ids.clone(),
+                    schema: None, // The script may reshape the features
+                });
+                info!(
+                    "{} '{}' processed data in {:?}",
+                    self.step_type,
+                    self.output_name,
+                    start_time.elapsed()
+                );
+            }
+            Err(e) => {
+                error!("{} '{}' failed to process data.", self.step_type, self.name);
+                // Record the failure in the trace, so HTTP callers get the traceback back
+                let duration = start_time.elapsed();
+                let trace_step =
+                    TraceStep::new(&self.name, duration, self.params.clone()).with_error(e.clone());
+                msg.trace.add_step(trace_step);
+
+                self.coordinator.do_send(DeadLetter {
+                    step_name: self.name.clone(),
+                    error: e.to_string(),
+                    message: msg,
+                });
             }
-        } else {
-            error!("No Python script path provided in configuration.");
-            None
         }
     }
 }
@@ -109,6 +522,22 @@ impl Step for PyFeatureProcessor {
             .and_then(|v| v.as_str()) // Extract string slice
             .map(|s| s.to_string()); // Convert to String
 
+        let function = params
+            .get("function")
+            .and_then(|v| v.as_str())
+            .unwrap_or("process")
+            .to_string();
+
+        let numpy = params
+            .get("numpy")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let max_limit_violations = params
+            .get("max_limit_violations")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(3) as u32;
+
         PyFeatureProcessor {
             name,
             output_name,
@@ -116,7 +545,13 @@ impl Step for PyFeatureProcessor {
             python_home,
             python_path,
             python_script,
+            function,
+            numpy,
             params,
+            max_limit_violations,
+            limit_violations: 0,
+            failing: None,
+            backend: None,
         }
     }
 
@@ -142,46 +577,47 @@ impl Actor for PyFeatureProcessor {
 
     fn started(&mut self, _ctx: &mut Context<Self>) {
         info!("PyFeatureProcessor '{}' started.", self.name);
-        self.initialize_python_env(); // Initialize Python environment when the actor starts
     }
 }
 
 impl Handler<ProcessMessage> for PyFeatureProcessor {
     type Result = ();
 
-    fn handle(&mut self, mut msg: ProcessMessage, _ctx: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: ProcessMessage, ctx: &mut Context<Self>) -> Self::Result {
         let start_time = Instant::now();
         info!(
             "PyFeatureProcessor '{}' received data: {:?}",
             self.name, msg.data
         );
+        let rows = msg.member_ids.len();
 
-        // Run the Python script to process the input data
-        let processed = self.run_python_script(msg.data.clone());
-
-        match processed {
-            Some(output) => {
-                // Update the trace
-                let duration = start_time.elapsed();
-                let trace_step = TraceStep::new(&self.name, duration, self.params.clone());
-                msg.trace.add_step(trace_step);
+        if let Some(reason) = &self.failing {
+            let error = StepError::new(format!("PyFeatureProcessor is failing: {}", reason));
+            self.outlet().finish(msg, start_time, Err(error));
+            return;
+        }
 
-                self.coordinator.do_send(ProcessMessage {
-                    id: msg.id,
-                    node_id: self.output_name.clone(),
-                    data: output,
-                    batch_id: msg.batch_id,
-                    batch_total: msg.batch_total,
-                    trace: msg.trace.clone(),
-                });
-                info!(
-                    "PyFeatureProcessor '{}' processed data in {:?}",
-                    self.output_name,
-                    start_time.elapsed()
+        // Run the Python script to process the input data
+        match &self.backend {
+            Some(PyBackend::InProcess(script)) => {
+                let processed = script.call(&msg.data, rows);
+                self.finish(msg, start_time, processed);
+            }
+            Some(PyBackend::Workers(pool)) => {
+                // Workers answer in their own time, so several messages can be in flight
+                let pool = pool.clone();
+                ctx.spawn(
+                    async move {
+                        let processed = pool.call(msg.data.clone(), rows).await;
+                        (msg, processed)
+                    }
+                    .into_actor(self)
+                    .map(move |(msg, processed), act, _ctx| act.finish(msg, start_time, processed)),
                 );
             }
             None => {
-                error!("PyFeatureProcessor '{}' failed to process data.", self.name);
+                let error = StepError::new("Python script has not been loaded");
+                self.finish(msg, start_time, Err(error));
             }
         }
     }
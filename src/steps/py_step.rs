@@ -0,0 +1,328 @@
+// src/steps/py_step.rs
+
+use crate::config::PythonStepType;
+use crate::coordinator::Coordinator;
+use crate::messages::ProcessMessage;
+use crate::step::{Step, StepError};
+use crate::steps::py_feature_processor::{
+    capture_output, extract_features, import_script, numpy_asarray, python_error, FeatureArray,
+    PyOutlet,
+};
+use actix::prelude::*;
+use log::{info, warn};
+use pyo3::prelude::*;
+use pyo3::types::{PyList, PyTuple};
+use serde_json::Value;
+use std::time::Instant;
+
+/// PyStepObject Struct
+///
+/// An instance of a step type's Python class, built with the step's params. The class defines
+/// `process(features)` for single messages, `process_batch(rows)` for pooled batches, or both;
+/// a missing one is covered by calling the other. Optional `start()` and `stop()` hooks run when
+/// the object is loaded and dropped, and whatever it keeps on `self` is the step's state.
+pub struct PyStepObject {
+    class: String,
+    instance: PyObject,
+    has_process: bool,
+    has_process_batch: bool,
+    asarray: Option<PyObject>, // `numpy.asarray` when the class takes NumPy arrays
+    stopped: bool,
+}
+
+impl PyStepObject {
+    /// Imports the step type's script, instantiates its class with `params` as a dict, and calls
+    /// `start()` if the class has one.
+    pub fn load(step_type: &PythonStepType, params: &Value) -> Result<PyStepObject, String> {
+        Python::with_gil(|py| {
+            let path = &step_type.python_script;
+            let class = import_script(py, path)?
+                .getattr(step_type.class.as_str())
+                .map_err(|_| {
+                    format!(
+                        "Python script '{}' has no class '{}'",
+                        path, step_type.class
+                    )
+                })?;
+            let has_process = class.hasattr("process").unwrap_or(false);
+            let has_process_batch = class.hasattr("process_batch").unwrap_or(false);
+            if !has_process && !has_process_batch {
+                return Err(format!(
+                    "Python class '{}' must define 'process' or 'process_batch'",
+                    step_type.class
+                ));
+            }
+            let asarray = if step_type.numpy {
+                Some(numpy_asarray(py)?)
+            } else {
+                None
+            };
+
+            let params = if params.is_null() {
+                "{}".to_string()
+            } else {
+                params.to_string()
+            };
+            let params = py
+                .import("json")
+                .and_then(|json| json.call_method1("loads", (params,)))
+                .map_err(|e| format!("Failed to pass params to Python: {}", e))?;
+            let (instance, captured) = capture_output(py, || {
+                let instance = class.call1((params,))?;
+                if instance.hasattr("start")? {
+                    instance.call_method0("start")?;
+                }
+                Ok(instance)
+            })
+            .map_err(|e| format!("Failed to capture Python output: {}", e))?;
+            let instance = instance.map_err(|e: PyErr| {
+                python_error(
+                    py,
+                    &e,
+                    &format!("Failed to start Python class '{}'", step_type.class),
+                )
+                .with_output(captured.clone())
+                .to_string()
+            })?;
+            if !captured.is_empty() {
+                info!(
+                    "Python class '{}' printed on start: {}",
+                    step_type.class, captured
+                );
+            }
+
+            Ok(PyStepObject {
+                class: step_type.class.clone(),
+                instance: instance.into(),
+                has_process,
+                has_process_batch,
+                asarray,
+                stopped: false,
+            })
+        })
+    }
+
+    /// Processes a message's features, `rows` rows of them for a pooled batch (0 otherwise),
+    /// returning the output and anything the object printed.
+    pub fn process(&self, data: &[f64], rows: usize) -> Result<(Vec<f64>, String), StepError> {
+        Python::with_gil(|py| {
+            let (output, captured) = capture_output(py, || self.dispatch(py, data, rows))
+                .map_err(|e| python_error(py, &e, "Failed to capture Python output"))?;
+            match output {
+                Ok(output) => Ok((output, captured)),
+                Err(e) => Err(e.with_output(captured)),
+            }
+        })
+    }
+
+    /// Calls `stop()` if the class has one, returning anything it printed. Only the first call
+    /// runs the hook.
+    pub fn stop(&mut self) -> Result<String, StepError> {
+        if self.stopped {
+            return Ok(String::new());
+        }
+        self.stopped = true;
+        Python::with_gil(|py| {
+            let instance = self.instance.as_ref(py);
+            if !instance.hasattr("stop").unwrap_or(false) {
+                return Ok(String::new());
+            }
+            let (stopped, captured) = capture_output(py, || instance.call_method0("stop"))
+                .map_err(|e| python_error(py, &e, "Failed to capture Python output"))?;
+            match stopped {
+                Ok(_) => Ok(captured),
+                Err(e) => Err(
+                    python_error(py, &e, &format!("'{}.stop' failed", self.class))
+                        .with_output(captured),
+                ),
+            }
+        })
+    }
+
+    /// Picks the method for the message: batches go to `process_batch` and single rows to
+    /// `process`, unless the class only has the other one.
+    fn dispatch(&self, py: Python, data: &[f64], rows: usize) -> Result<Vec<f64>, StepError> {
+        match (rows, self.has_process, self.has_process_batch) {
+            (0, true, _) => self.call_process(py, data),
+            (0, false, _) => self.call_process_batch(py, data, 1),
+            (_, _, true) => self.call_process_batch(py, data, rows),
+            (_, _, false) => {
+                let width = data.len() / rows;
+                let mut output = Vec::new();
+                for row in data.chunks(width.max(1)) {
+                    output.extend(self.call_process(py, row)?);
+                }
+                Ok(output)
+            }
+        }
+    }
+
+    fn call_process(&self, py: Python, data: &[f64]) -> Result<Vec<f64>, StepError> {
+        let input = match &self.asarray {
+            Some(asarray) => Py::new(py, FeatureArray::new(data.to_vec(), 0))
+                .and_then(|features| asarray.call1(py, (features,))),
+            None => Ok(data.to_vec().into_py(py)),
+        }
+        .map_err(|e| python_error(py, &e, "Failed to share features with Python"))?;
+        let result = self
+            .instance
+            .call_method1(py, "process", (input,))
+            .map_err(|e| python_error(py, &e, &format!("'{}.process' failed", self.class)))?;
+        extract_features(result.as_ref(py), 0).map_err(StepError::new)
+    }
+
+    fn call_process_batch(
+        &self,
+        py: Python,
+        data: &[f64],
+        rows: usize,
+    ) -> Result<Vec<f64>, StepError> {
+        let width = data.len() / rows;
+        let input = match &self.asarray {
+            Some(asarray) => Py::new(py, FeatureArray::matrix(data.to_vec(), rows))
+                .and_then(|features| asarray.call1(py, (features,))),
+            None => {
+                let rows: Vec<Vec<f64>> = (0..rows)
+                    .map(|row| data[row * width..(row + 1) * width].to_vec())
+                    .collect();
+                Ok(rows.into_py(py))
+            }
+        }
+        .map_err(|e| python_error(py, &e, "Failed to share features with Python"))?;
+        let result = self
+            .instance
+            .call_method1(py, "process_batch", (input,))
+            .map_err(|e| python_error(py, &e, &format!("'{}.process_batch' failed", self.class)))?;
+        extract_rows(result.as_ref(py), rows).map_err(StepError::new)
+    }
+}
+
+impl Drop for PyStepObject {
+    fn drop(&mut self) {
+        match self.stop() {
+            Ok(captured) if !captured.is_empty() => {
+                info!(
+                    "Python class '{}' printed on stop: {}",
+                    self.class, captured
+                )
+            }
+            Ok(_) => {}
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+/// Reads what `process_batch` returned: a list of rows, or an array like `extract_features` takes.
+fn extract_rows(result: &PyAny, rows: usize) -> Result<Vec<f64>, String> {
+    if result.downcast::<PyList>().is_err() && result.downcast::<PyTuple>().is_err() {
+        return extract_features(result, rows);
+    }
+    match result.extract::<Vec<Vec<f64>>>() {
+        Ok(output) if output.len() != rows => Err(format!(
+            "Python function returned {} rows for {} input rows",
+            output.len(),
+            rows
+        )),
+        Ok(output) => Ok(output.concat()),
+        // A flat list of numbers
+        Err(_) => extract_features(result, rows),
+    }
+}
+
+/// PyStep Actor
+///
+/// Runs a step type declared under `python_steps`: the Coordinator builds it like any other step
+/// and `load_class` gives it its own instance of the type's class. Results and failures go through
+/// the same trace recording and dead-lettering as `PyFeatureProcessor`.
+pub struct PyStep {
+    pub name: String,
+    pub output_name: String,
+    pub coordinator: Addr<Coordinator>,
+    pub step_type: String,        // The type name the config uses
+    pub params: Value,            // Passed to the class's constructor
+    object: Option<PyStepObject>, // Set by `load_class` before the actor starts
+}
+
+impl PyStep {
+    /// Instantiates the step type's class. The Coordinator calls this while handling
+    /// `Initialize`, so a class that fails to import or start fails the DAG at startup.
+    pub fn load_class(
+        &mut self,
+        type_name: &str,
+        step_type: &PythonStepType,
+    ) -> Result<(), String> {
+        self.step_type = type_name.to_string();
+        self.object = Some(PyStepObject::load(step_type, &self.params)?);
+        Ok(())
+    }
+
+    fn outlet(&self) -> PyOutlet {
+        PyOutlet {
+            step_type: self.step_type.clone(),
+            name: self.name.clone(),
+            output_name: self.output_name.clone(),
+            coordinator: self.coordinator.clone(),
+            params: self.params.clone(),
+        }
+    }
+}
+
+impl Step for PyStep {
+    fn new_from_params(
+        name: String,
+        output_name: String,
+        coordinator: Addr<Coordinator>,
+        params: Value,
+    ) -> Self {
+        PyStep {
+            name,
+            output_name,
+            coordinator,
+            step_type: "PyStep".to_string(),
+            params,
+            object: None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn output_name(&self) -> &str {
+        &self.output_name
+    }
+
+    fn coordinator(&self) -> Addr<Coordinator> {
+        self.coordinator.clone()
+    }
+
+    fn params(&self) -> &Value {
+        &self.params
+    }
+}
+
+impl Actor for PyStep {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Context<Self>) {
+        info!("{} '{}' started.", self.step_type, self.name);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        // Dropping the object runs its `stop` hook
+        self.object = None;
+    }
+}
+
+impl Handler<ProcessMessage> for PyStep {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProcessMessage, _ctx: &mut Context<Self>) -> Self::Result {
+        let start_time = Instant::now();
+        let processed = match &self.object {
+            Some(object) => object.process(&msg.data, msg.member_ids.len()),
+            None => Err(StepError::new("Python class has not been loaded")),
+        };
+        self.outlet().finish(msg, start_time, processed);
+    }
+}
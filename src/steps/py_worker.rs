@@ -0,0 +1,299 @@
+// src/steps/py_worker.rs
+
+use crate::step::StepError;
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// The worker side of the protocol, run with `python -c`.
+const WORKER_SOURCE: &str = include_str!("../../scripts/py_worker.py");
+
+/// Largest JSON header a worker may send, to catch a corrupted stream early.
+const MAX_HEADER_BYTES: u32 = 64 * 1024 * 1024;
+
+/// How to start a worker: the interpreter, the script and its function, and the environment.
+#[derive(Debug, Clone)]
+pub struct WorkerCommand {
+    pub python: String,
+    pub script: String,
+    pub function: String,
+    pub numpy: bool,
+    pub memory_limit_mb: Option<u64>, // Caps each worker's address space once the script is imported
+    pub env: Vec<(String, String)>,   // PYTHONHOME and PYTHONPATH, if configured
+}
+
+/// Worker Struct
+///
+/// One Python process with the script imported, and the pipes to talk to it.
+struct Worker {
+    child: Arc<Mutex<Child>>, // Shared so a timed out call can kill it
+    stdin: BufWriter<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    /// Starts a worker and waits for it to import the script.
+    fn spawn(command: &WorkerCommand) -> Result<Worker, String> {
+        let mut child = Command::new(&command.python)
+            .arg("-c")
+            .arg(WORKER_SOURCE)
+            .arg(&command.script)
+            .arg(&command.function)
+            .arg(if command.numpy { "1" } else { "0" })
+            .arg((command.memory_limit_mb.unwrap_or(0) * 1024 * 1024).to_string())
+            .envs(command.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("Failed to start Python worker '{}': {}", command.python, e))?;
+
+        let stdin = BufWriter::new(child.stdin.take().ok_or("worker has no stdin")?);
+        let stdout = BufReader::new(child.stdout.take().ok_or("worker has no stdout")?);
+        let mut worker = Worker {
+            child: Arc::new(Mutex::new(child)),
+            stdin,
+            stdout,
+        };
+
+        let (header, _) = read_frame(&mut worker.stdout).map_err(|e| {
+            format!(
+                "Python worker for '{}' exited while starting: {}",
+                command.script, e
+            )
+        })?;
+        if let Some(error) = header.get("error") {
+            worker.kill();
+            return Err(step_error(error).to_string());
+        }
+        if let Some(output) = header.get("output").and_then(|o| o.as_str()) {
+            if !output.is_empty() {
+                info!(
+                    "Python script '{}' printed on import: {}",
+                    command.script, output
+                );
+            }
+        }
+        Ok(worker)
+    }
+
+    /// Sends one request and reads the answer. An `Err` means the worker is unusable.
+    fn exchange(&mut self, data: &[f64], rows: usize) -> io::Result<(Value, Vec<f64>)> {
+        write_frame(&mut self.stdin, &json!({ "rows": rows }), data)?;
+        read_frame(&mut self.stdout)
+    }
+
+    fn kill(&self) {
+        if let Ok(mut child) = self.child.lock() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// PyWorkerPool Struct
+///
+/// Runs a `PyFeatureProcessor` script in a pool of Python processes instead of the step's own
+/// interpreter, so calls don't share the GIL and a crash in a native extension only takes down
+/// one worker. Each call takes an idle worker; one that crashes or runs past the timeout is
+/// killed and replaced in the background. Calls that time out or run out of memory fail with a
+/// `StepError` naming the limit.
+#[derive(Clone)]
+pub struct PyWorkerPool {
+    command: Arc<WorkerCommand>,
+    idle: Arc<Mutex<Vec<Option<Worker>>>>, // `None` for a worker that failed to restart
+    permits: Arc<Semaphore>,               // One per worker
+    timeout: Option<Duration>,
+}
+
+impl PyWorkerPool {
+    /// Starts `size` workers, failing if any of them can't import the script.
+    pub fn start(
+        command: WorkerCommand,
+        size: usize,
+        timeout: Option<Duration>,
+    ) -> Result<PyWorkerPool, String> {
+        if size == 0 {
+            return Err("'workers' must be at least 1".to_string());
+        }
+        let workers = (0..size)
+            .map(|_| Worker::spawn(&command).map(Some))
+            .collect::<Result<Vec<_>, String>>()?;
+        info!("Started {} Python workers for '{}'", size, command.script);
+        Ok(PyWorkerPool {
+            command: Arc::new(command),
+            idle: Arc::new(Mutex::new(workers)),
+            permits: Arc::new(Semaphore::new(size)),
+            timeout,
+        })
+    }
+
+    /// Calls the function in an idle worker, returning its output and anything it printed.
+    pub async fn call(&self, data: Vec<f64>, rows: usize) -> Result<(Vec<f64>, String), StepError> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| StepError::new("Python worker pool is closed"))?;
+        let slot = self
+            .idle
+            .lock()
+            .ok()
+            .and_then(|mut idle| idle.pop())
+            .flatten();
+        let mut worker = match slot {
+            Some(worker) => worker,
+            // A worker that failed to restart gets another try
+            None => match self.respawn().await {
+                Ok(worker) => worker,
+                Err(e) => {
+                    self.give_back(None, permit);
+                    return Err(StepError::new(e));
+                }
+            },
+        };
+
+        let child = worker.child.clone();
+        let exchange = tokio::task::spawn_blocking(move || {
+            let result = worker.exchange(&data, rows);
+            (worker, result)
+        });
+        let finished = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, exchange).await.ok(),
+            None => Some(exchange.await),
+        };
+
+        match finished {
+            Some(Ok((worker, Ok((header, values))))) => {
+                self.give_back(Some(worker), permit);
+                let output = header
+                    .get("output")
+                    .and_then(|o| o.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                match header.get("error") {
+                    Some(error) => Err(step_error(error)),
+                    None => Ok((values, output)),
+                }
+            }
+            Some(Ok((worker, Err(e)))) => {
+                let status = worker
+                    .child
+                    .lock()
+                    .ok()
+                    .and_then(|mut child| child.try_wait().ok().flatten());
+                drop(worker);
+                self.replace(permit);
+                Err(StepError::new(match status {
+                    Some(status) => format!("Python worker crashed ({})", status),
+                    None => format!("Python worker stopped answering: {}", e),
+                }))
+            }
+            Some(Err(e)) => {
+                self.replace(permit);
+                Err(StepError::new(format!(
+                    "Python worker call panicked: {}",
+                    e
+                )))
+            }
+            None => {
+                // Killing the worker ends the blocked call, which then drops it
+                if let Ok(mut child) = child.lock() {
+                    let _ = child.kill();
+                }
+                self.replace(permit);
+                Err(StepError::new(format!(
+                    "Python function timed out after {:?}",
+                    self.timeout.unwrap_or_default()
+                ))
+                .with_limit("timeout"))
+            }
+        }
+    }
+
+    /// Returns a worker (or an empty slot) to the pool.
+    fn give_back(&self, worker: Option<Worker>, permit: OwnedSemaphorePermit) {
+        if let Ok(mut idle) = self.idle.lock() {
+            idle.push(worker);
+        }
+        drop(permit);
+    }
+
+    /// Starts a replacement for a dead worker in the background; its slot stays taken until then.
+    fn replace(&self, permit: OwnedSemaphorePermit) {
+        warn!("Restarting a Python worker for '{}'", self.command.script);
+        let pool = self.clone();
+        tokio::spawn(async move {
+            let worker = match pool.respawn().await {
+                Ok(worker) => Some(worker),
+                Err(e) => {
+                    error!("Failed to restart a Python worker: {}", e);
+                    None
+                }
+            };
+            pool.give_back(worker, permit);
+        });
+    }
+
+    async fn respawn(&self) -> Result<Worker, String> {
+        let command = self.command.clone();
+        tokio::task::spawn_blocking(move || Worker::spawn(&command))
+            .await
+            .map_err(|e| format!("Python worker start panicked: {}", e))?
+    }
+}
+
+/// Reads a `StepError` sent by a worker.
+fn step_error(error: &Value) -> StepError {
+    serde_json::from_value(error.clone())
+        .unwrap_or_else(|_| StepError::new(format!("Python worker failed: {}", error)))
+}
+
+/// Writes a frame: a big-endian u32 length and a JSON header, then a big-endian u32 count and
+/// that many little-endian float64 values.
+fn write_frame(writer: &mut impl Write, header: &Value, values: &[f64]) -> io::Result<()> {
+    let header = serde_json::to_vec(header)?;
+    writer.write_all(&(header.len() as u32).to_be_bytes())?;
+    writer.write_all(&header)?;
+    writer.write_all(&(values.len() as u32).to_be_bytes())?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Reads a frame written by `write_frame` (or the worker).
+fn read_frame(reader: &mut impl Read) -> io::Result<(Value, Vec<f64>)> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let header_len = u32::from_be_bytes(length);
+    if header_len > MAX_HEADER_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame header of {} bytes", header_len),
+        ));
+    }
+    let mut header = vec![0u8; header_len as usize];
+    reader.read_exact(&mut header)?;
+    let header: Value = serde_json::from_slice(&header)?;
+
+    reader.read_exact(&mut length)?;
+    let mut bytes = vec![0u8; u32::from_be_bytes(length) as usize * 8];
+    reader.read_exact(&mut bytes)?;
+    let values = bytes
+        .chunks_exact(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap_or_default()))
+        .collect();
+    Ok((header, values))
+}
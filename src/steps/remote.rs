@@ -0,0 +1,480 @@
+// src/steps/remote.rs
+
+use crate::steps::tensor_spec::{gather_features, label_to_f64, InputSpec, OutputSpec};
+use log::debug;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Method};
+use serde_json::{json, Map, Value as JsonValue};
+use std::env;
+use std::time::Duration;
+
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_READ_TIMEOUT_MS: u64 = 30_000;
+
+/// The wire format spoken by the remote endpoint.
+enum Protocol {
+    /// Our own JSON: a flat feature array in the request, a flat prediction array in the response.
+    Json {
+        request_field: Vec<String>,  // Path of the features in the request body
+        response_field: Vec<String>, // Path of the predictions in the response body
+    },
+    /// The KServe v2 / Open Inference Protocol, with typed input and output tensors.
+    V2 {
+        inputs: Vec<V2Input>,
+        outputs: Vec<OutputSpec>, // Outputs to read, in order; every output when empty
+    },
+}
+
+/// An input tensor of a v2 request.
+struct V2Input {
+    name: String,
+    datatype: &'static str,
+    indices: Option<Vec<usize>>, // Feature indices (per row) that make up this input; defaults to all
+}
+
+/// RemoteModel Struct
+///
+/// A remote inference endpoint plus the HTTP client used for every request to it. The client
+/// keeps a connection pool, so it is built once (when the Coordinator handles `Initialize`)
+/// and shared by every message the step sends.
+pub struct RemoteModel {
+    pub endpoint: String, // Full request URL (for v2 this includes the model name and version)
+    pub method: Method,
+    client: Client,
+    protocol: Protocol,
+}
+
+impl RemoteModel {
+    /// Builds the client from the MLModel params:
+    ///
+    /// - `protocol`: `json` (default) or `v2`
+    /// - `method`: HTTP method, default `POST`
+    /// - `request_field` / `response_field` (json): dotted paths (array indices allowed in the
+    ///   response) of the features sent and predictions read, default `features` and
+    ///   `processed_features`
+    /// - `model_name` / `model_version` (v2): the model to call; the name is required
+    /// - `inputs` / `outputs` (v2): input tensors (`name`, `dtype`, `indices`) and outputs
+    ///   (`name`, `classes`) in the same form as for ONNX models
+    /// - `headers`: static headers, e.g. `{"X-Model": "churn"}`
+    /// - `env_headers`: headers read from environment variables, e.g. `{"Authorization": "MODEL_TOKEN"}`
+    /// - `connect_timeout_ms` / `read_timeout_ms`: default 5s and 30s
+    pub fn from_params(endpoint: &str, params: &JsonValue) -> Result<Self, String> {
+        let method = params
+            .get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or("POST");
+        let method = Method::from_bytes(method.to_uppercase().as_bytes())
+            .map_err(|_| format!("Invalid HTTP method '{}'", method))?;
+
+        let (endpoint, protocol) = match params.get("protocol").and_then(|p| p.as_str()) {
+            None | Some("json") => (
+                endpoint.to_string(),
+                Protocol::Json {
+                    request_field: field_path(params, "request_field", "features"),
+                    response_field: field_path(params, "response_field", "processed_features"),
+                },
+            ),
+            Some("v2") => v2_protocol(endpoint, params)?,
+            Some(other) => return Err(format!("Unknown remote protocol '{}'", other)),
+        };
+
+        let mut headers = HeaderMap::new();
+        if let Some(static_headers) = params.get("headers").and_then(|h| h.as_object()) {
+            for (name, value) in static_headers {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| format!("Header '{}' must be a string", name))?;
+                insert_header(&mut headers, name, value)?;
+            }
+        }
+        if let Some(env_headers) = params.get("env_headers").and_then(|h| h.as_object()) {
+            for (name, variable) in env_headers {
+                let variable = variable.as_str().ok_or_else(|| {
+                    format!("Header '{}' must name an environment variable", name)
+                })?;
+                let value = env::var(variable).map_err(|_| {
+                    format!(
+                        "Environment variable '{}' for header '{}' is not set",
+                        variable, name
+                    )
+                })?;
+                insert_header(&mut headers, name, &value)?;
+            }
+        }
+
+        let connect_timeout = params
+            .get("connect_timeout_ms")
+            .and_then(|t| t.as_u64())
+            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS);
+        let read_timeout = params
+            .get("read_timeout_ms")
+            .and_then(|t| t.as_u64())
+            .unwrap_or(DEFAULT_READ_TIMEOUT_MS);
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .connect_timeout(Duration::from_millis(connect_timeout))
+            .read_timeout(Duration::from_millis(read_timeout))
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {:?}", e))?;
+
+        Ok(RemoteModel {
+            endpoint,
+            method,
+            client,
+            protocol,
+        })
+    }
+
+    /// Sends `rows` rows of features (laid out back to back in `data`) to the endpoint and returns
+    /// the predictions per row. The json protocol has no notion of rows, so its whole response
+    /// comes back as one row. Transport failures, non-2xx statuses and malformed responses all
+    /// come back as errors.
+    pub async fn predict(&self, data: &[f64], rows: usize) -> Result<Vec<Vec<f64>>, String> {
+        let body = match &self.protocol {
+            Protocol::Json { request_field, .. } => build_request_body(request_field, json!(data)),
+            Protocol::V2 { inputs, outputs } => build_v2_request(inputs, outputs, data, rows)?,
+        };
+        let json_response = self.send(body).await?;
+
+        match &self.protocol {
+            Protocol::Json { response_field, .. } => {
+                parse_response_field(response_field, &json_response).map(|row| vec![row])
+            }
+            Protocol::V2 { outputs, .. } => parse_v2_response(outputs, &json_response, rows),
+        }
+    }
+
+    /// Sends several rows, which may come from different messages, in one request and returns
+    /// exactly one row of predictions per row sent. With json the request field holds one array
+    /// per row and the response field must hold one entry (a number or an array) per row; with v2
+    /// the rows must be equally wide to make one tensor.
+    pub async fn predict_rows(&self, rows: &[&[f64]]) -> Result<Vec<Vec<f64>>, String> {
+        let predictions = match &self.protocol {
+            Protocol::Json {
+                request_field,
+                response_field,
+            } => {
+                let json_response = self
+                    .send(build_request_body(request_field, json!(rows)))
+                    .await?;
+                parse_response_rows(response_field, &json_response)?
+            }
+            Protocol::V2 { .. } => {
+                let width = rows.first().map_or(0, |row| row.len());
+                if let Some(row) = rows.iter().find(|row| row.len() != width) {
+                    return Err(format!(
+                        "Rows of {} and {} features can't be sent as one v2 tensor",
+                        width,
+                        row.len()
+                    ));
+                }
+                self.predict(&rows.concat(), rows.len()).await?
+            }
+        };
+        if predictions.len() != rows.len() {
+            return Err(format!(
+                "Remote returned predictions for {} rows but {} were sent",
+                predictions.len(),
+                rows.len()
+            ));
+        }
+        Ok(predictions)
+    }
+
+    /// Posts a request body and reads the JSON response.
+    async fn send(&self, body: JsonValue) -> Result<JsonValue, String> {
+        debug!("Sending payload to '{}': {:?}", self.endpoint, body);
+
+        let response = self
+            .client
+            .request(self.method.clone(), &self.endpoint)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Request to '{}' failed: {}", self.endpoint, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!(
+                "Remote endpoint '{}' returned {}: {}",
+                self.endpoint,
+                status,
+                truncate(&body, 200)
+            ));
+        }
+
+        response
+            .json::<JsonValue>()
+            .await
+            .map_err(|e| format!("Failed to parse JSON response from remote: {:?}", e))
+    }
+}
+
+/// Follows a field path into the response; numeric parts index into arrays.
+fn response_field<'a>(path: &[String], response: &'a JsonValue) -> Result<&'a JsonValue, String> {
+    path.iter()
+        .try_fold(response, |value, key| match key.parse::<usize>() {
+            Ok(index) if value.is_array() => value.get(index),
+            _ => value.get(key.as_str()),
+        })
+        .ok_or_else(|| format!("Response has no '{}' field", path.join(".")))
+}
+
+/// Reads one entry per row from the response field of a batched json request.
+fn parse_response_rows(path: &[String], response: &JsonValue) -> Result<Vec<Vec<f64>>, String> {
+    let entries = response_field(path, response)?
+        .as_array()
+        .ok_or_else(|| format!("'{}' in response is not an array of rows", path.join(".")))?;
+    entries
+        .iter()
+        .map(|entry| {
+            let mut predictions = Vec::new();
+            flatten_numbers(entry, &mut predictions)
+                .map_err(|e| format!("Invalid '{}' in response: {}", path.join("."), e))?;
+            Ok(predictions)
+        })
+        .collect()
+}
+
+fn parse_response_field(path: &[String], response: &JsonValue) -> Result<Vec<f64>, String> {
+    let value = response_field(path, response)?;
+    let mut predictions = Vec::new();
+    flatten_numbers(value, &mut predictions)
+        .map_err(|e| format!("Invalid '{}' in response: {}", path.join("."), e))?;
+    Ok(predictions)
+}
+
+/// Builds the `/v2/models/{name}[/versions/{version}]/infer` URL and the tensor specs.
+fn v2_protocol(endpoint: &str, params: &JsonValue) -> Result<(String, Protocol), String> {
+    let model_name = params
+        .get("model_name")
+        .and_then(|n| n.as_str())
+        .ok_or_else(|| "The v2 protocol requires a 'model_name'".to_string())?;
+
+    let mut url = format!(
+        "{}/v2/models/{}",
+        endpoint.trim_end_matches('/'),
+        model_name
+    );
+    if let Some(version) = params.get("model_version") {
+        let version = match version {
+            JsonValue::String(version) => version.clone(),
+            other => other.to_string(),
+        };
+        url = format!("{}/versions/{}", url, version);
+    }
+    url.push_str("/infer");
+
+    let input_specs: Vec<InputSpec> = match params.get("inputs") {
+        Some(inputs) => serde_json::from_value(inputs.clone())
+            .map_err(|e| format!("Invalid 'inputs': {}", e))?,
+        None => vec![InputSpec {
+            name: "input-0".to_string(),
+            dtype: None,
+            indices: None,
+        }],
+    };
+    let inputs = input_specs
+        .into_iter()
+        .map(|spec| {
+            Ok(V2Input {
+                datatype: v2_datatype(spec.dtype.as_deref().unwrap_or("float32"))?,
+                name: spec.name,
+                indices: spec.indices,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let outputs: Vec<OutputSpec> = match params.get("outputs") {
+        Some(outputs) => serde_json::from_value(outputs.clone())
+            .map_err(|e| format!("Invalid 'outputs': {}", e))?,
+        None => vec![],
+    };
+
+    Ok((url, Protocol::V2 { inputs, outputs }))
+}
+
+/// Maps an input `dtype` (ours or the v2 name) to the v2 `datatype`.
+fn v2_datatype(dtype: &str) -> Result<&'static str, String> {
+    match dtype {
+        "float32" | "float" | "f32" | "FP32" => Ok("FP32"),
+        "float64" | "double" | "f64" | "FP64" => Ok("FP64"),
+        "int64" | "i64" | "INT64" => Ok("INT64"),
+        "int32" | "i32" | "INT32" => Ok("INT32"),
+        "bool" | "BOOL" => Ok("BOOL"),
+        "string" | "BYTES" => Ok("BYTES"),
+        other => Err(format!("Unsupported input dtype '{}'", other)),
+    }
+}
+
+/// Builds a v2 inference request with one `[rows, width]` tensor per input.
+fn build_v2_request(
+    inputs: &[V2Input],
+    outputs: &[OutputSpec],
+    data: &[f64],
+    rows: usize,
+) -> Result<JsonValue, String> {
+    let mut tensors = Vec::new();
+
+    for input in inputs {
+        let values = gather_features(&input.name, input.indices.as_deref(), data, rows)?;
+        let input_width = values.len() / rows.max(1);
+        let tensor_data: Vec<JsonValue> = match input.datatype {
+            "INT64" | "INT32" => values.iter().map(|&x| json!(x as i64)).collect(),
+            "BOOL" => values.iter().map(|&x| json!(x != 0.0)).collect(),
+            "BYTES" => values.iter().map(|x| json!(x.to_string())).collect(),
+            _ => values.iter().map(|&x| json!(x)).collect(),
+        };
+
+        tensors.push(json!({
+            "name": input.name,
+            "shape": [rows, input_width],
+            "datatype": input.datatype,
+            "data": tensor_data,
+        }));
+    }
+
+    let mut request = json!({ "inputs": tensors });
+    if !outputs.is_empty() {
+        let requested: Vec<JsonValue> = outputs
+            .iter()
+            .map(|output| json!({ "name": output.name }))
+            .collect();
+        request["outputs"] = json!(requested);
+    }
+    Ok(request)
+}
+
+/// Reads the selected output tensors of a v2 response and splits them into `rows` rows,
+/// concatenating the outputs per row in the order they were selected.
+fn parse_v2_response(
+    outputs: &[OutputSpec],
+    response: &JsonValue,
+    rows: usize,
+) -> Result<Vec<Vec<f64>>, String> {
+    let tensors = response
+        .get("outputs")
+        .and_then(|o| o.as_array())
+        .ok_or_else(|| "Response has no 'outputs' array".to_string())?;
+
+    let tensor_name = |tensor: &JsonValue| {
+        tensor
+            .get("name")
+            .and_then(|n| n.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    // Every output in response order unless specific ones were asked for
+    let selected: Vec<(&JsonValue, OutputSpec)> = if outputs.is_empty() {
+        tensors
+            .iter()
+            .map(|tensor| {
+                let spec = OutputSpec {
+                    name: tensor_name(tensor),
+                    classes: vec![],
+                };
+                (tensor, spec)
+            })
+            .collect()
+    } else {
+        outputs
+            .iter()
+            .map(|spec| {
+                tensors
+                    .iter()
+                    .find(|tensor| tensor_name(tensor) == spec.name)
+                    .map(|tensor| (tensor, spec.clone()))
+                    .ok_or_else(|| format!("Response has no output '{}'", spec.name))
+            })
+            .collect::<Result<_, String>>()?
+    };
+
+    let mut predictions = vec![Vec::new(); rows];
+    for (tensor, spec) in selected {
+        let data = tensor
+            .get("data")
+            .and_then(|d| d.as_array())
+            .ok_or_else(|| format!("Output '{}' has no 'data' array", spec.name))?;
+
+        let values = data
+            .iter()
+            .map(|value| match value {
+                JsonValue::Number(number) => Ok(number.as_f64().unwrap_or_default()),
+                JsonValue::Bool(flag) => Ok(if *flag { 1.0 } else { 0.0 }),
+                JsonValue::String(label) => label_to_f64(label, &spec.classes),
+                other => Err(format!(
+                    "Output '{}' holds unsupported value {}",
+                    spec.name, other
+                )),
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+
+        // The leading output dimension is the batch, so each row owns an equal share
+        let per_row = values.len() / rows.max(1);
+        if per_row * rows != values.len() {
+            return Err(format!(
+                "Output '{}' has {} values for a batch of {} rows",
+                spec.name,
+                values.len(),
+                rows
+            ));
+        }
+        for (row, chunk) in values.chunks(per_row.max(1)).enumerate() {
+            predictions[row].extend_from_slice(chunk);
+        }
+    }
+
+    Ok(predictions)
+}
+
+/// Reads a dotted field path param, e.g. `"outputs.0.data"`.
+fn field_path(params: &JsonValue, key: &str, default: &str) -> Vec<String> {
+    params
+        .get(key)
+        .and_then(|f| f.as_str())
+        .unwrap_or(default)
+        .split('.')
+        .map(|part| part.to_string())
+        .collect()
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &str, value: &str) -> Result<(), String> {
+    let header_name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| format!("Invalid header name '{}'", name))?;
+    let header_value =
+        HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header '{}'", name))?;
+    headers.insert(header_name, header_value);
+    Ok(())
+}
+
+/// Nests the features under the request field path, e.g. `inputs.data` becomes
+/// `{"inputs": {"data": [...]}}`.
+fn build_request_body(path: &[String], data: JsonValue) -> JsonValue {
+    path.iter().rev().fold(data, |value, key| {
+        let mut object = Map::new();
+        object.insert(key.clone(), value);
+        JsonValue::Object(object)
+    })
+}
+
+/// Collects every number in a (possibly nested) array, or a single number.
+fn flatten_numbers(value: &JsonValue, out: &mut Vec<f64>) -> Result<(), String> {
+    match value {
+        JsonValue::Array(values) => values.iter().try_for_each(|v| flatten_numbers(v, out)),
+        JsonValue::Number(number) => {
+            out.push(number.as_f64().unwrap_or_default());
+            Ok(())
+        }
+        other => Err(format!("expected numbers, found {}", other)),
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
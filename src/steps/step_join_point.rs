@@ -1,4 +1,5 @@
-use crate::messages::ProcessMessage;
+use crate::messages::{DeadLetter, ProcessMessage};
+use crate::schema::{check_width, Column, Field, Schema};
 use crate::step::TraceStep;
 use crate::{coordinator::Coordinator, step::Step};
 use actix::prelude::*;
@@ -6,6 +7,12 @@ use log::info;
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How many timeouts a timed out join waits for its missing branches, to dead-letter them when
+/// they arrive instead of starting the join over.
+const EXPIRED_JOIN_RETENTION: u32 = 10;
 
 /// StepJoinPointMode Enum
 ///
@@ -36,6 +43,11 @@ impl Default for StepJoinPointOutputMode {
     }
 }
 
+/// StepJoinPoint Actor
+///
+/// Joins the messages of its inputs that share an ID (or batch ID). When every input carries a
+/// schema the joined message carries their concatenation, and `columns` can pick features from
+/// it by name or position.
 pub struct StepJoinPoint {
     pub name: String,
     pub output_name: String,
@@ -45,6 +57,11 @@ pub struct StepJoinPoint {
     pub output_mode: StepJoinPointOutputMode, // New field for controlling output mode
     pub pending: HashMap<u64, HashMap<String, Vec<f64>>>, // Maps ID to (input_name -> processed data)
     pub completed_ids: HashMap<u64, usize>,               // Tracks remaining branches for each ID
+    pub timeout: Option<Duration>, // How long an AND join waits for missing branches (no limit if unset)
+    pub pending_since: HashMap<u64, (Instant, ProcessMessage)>, // First arrival time and latest message per ID
+    pub expired: HashMap<u64, (Instant, Vec<String>)>, // First arrival time and branches still due per timed out ID
+    pub schemas: HashMap<String, Arc<Schema>>,         // Latest schema seen from each input
+    pub columns: Option<Vec<Column>>, // Features to keep from the joined data, in order
     pub params: Value,
 }
 
@@ -70,6 +87,137 @@ impl StepJoinPoint {
             })
             .unwrap_or(StepJoinPointOutputMode::Flatten) // Default to Flatten if "output_mode" is missing or invalid
     }
+
+    /// The schema of the joined data, if every input has one. A feature name that more than one
+    /// input uses is qualified with the input's name, e.g. `scores.amount`.
+    fn joined_schema(&self) -> Result<Option<Schema>, String> {
+        let mut schemas = Vec::new();
+        for node in &self.expected_nodes {
+            match self.schemas.get(node) {
+                Some(schema) => schemas.push((node, schema)),
+                None => return Ok(None),
+            }
+        }
+
+        let count = |name: &str| {
+            schemas
+                .iter()
+                .filter(|(_, schema)| schema.index_of(name).is_some())
+                .count()
+        };
+        let fields = schemas
+            .iter()
+            .flat_map(|(node, schema)| schema.fields().iter().map(move |field| (node, field)))
+            .map(|(node, field)| Field {
+                name: if count(&field.name) > 1 {
+                    format!("{}.{}", node, field.name)
+                } else {
+                    field.name.clone()
+                },
+                dtype: field.dtype,
+            })
+            .collect();
+        Schema::new(fields).map(Some)
+    }
+
+    /// Reads the `columns` param: names or positions in the joined data. The Coordinator calls
+    /// this while handling `Initialize`, so a malformed list fails the DAG at startup.
+    pub fn load_columns(&mut self) -> Result<(), String> {
+        self.columns = match self.params.get("columns") {
+            None | Some(Value::Null) => None,
+            Some(columns) => Some(
+                serde_json::from_value(columns.clone())
+                    .map_err(|e| format!("Invalid 'columns': {}", e))?,
+            ),
+        };
+        Ok(())
+    }
+
+    /// Applies `columns` to the joined data and its schema.
+    fn select_columns(
+        &self,
+        data: Vec<f64>,
+        schema: Option<Schema>,
+    ) -> Result<(Vec<f64>, Option<Schema>), String> {
+        let Some(columns) = &self.columns else {
+            return Ok((data, schema));
+        };
+        let indices = columns
+            .iter()
+            .map(|column| {
+                let index = Schema::resolve(schema.as_ref(), column)?;
+                if index >= data.len() {
+                    return Err(format!(
+                        "column {} is out of range for {} joined features",
+                        index,
+                        data.len()
+                    ));
+                }
+                Ok(index)
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        let schema = schema.map(|schema| schema.select(&indices)).transpose()?;
+        Ok((indices.iter().map(|&i| data[i]).collect(), schema))
+    }
+
+    /// Dead-letters any AND join that has been waiting longer than the configured timeout,
+    /// counted from its first branch.
+    fn expire_pending(&mut self) {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        // Stop waiting for the branches of long-expired joins
+        self.expired
+            .retain(|_, (since, _)| since.elapsed() < timeout * EXPIRED_JOIN_RETENTION);
+
+        let expired: Vec<u64> = self
+            .pending_since
+            .iter()
+            .filter(|(_, (since, _))| since.elapsed() >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in expired {
+            let received = self.pending.remove(&key).unwrap_or_default();
+            if let Some((since, message)) = self.pending_since.remove(&key) {
+                let missing: Vec<String> = self
+                    .expected_nodes
+                    .iter()
+                    .filter(|node| !received.contains_key(*node))
+                    .cloned()
+                    .collect();
+
+                self.coordinator.do_send(DeadLetter {
+                    step_name: self.name.clone(),
+                    error: format!(
+                        "Join timed out after {:?} waiting for {:?}",
+                        timeout, missing
+                    ),
+                    message,
+                });
+                self.expired.insert(key, (since, missing));
+            }
+        }
+    }
+
+    /// The error for a branch of a join that has already timed out, if `key` has. The join is
+    /// forgotten once all of its missing branches have turned up.
+    fn late_branch(&mut self, key: u64, node: &str) -> Option<String> {
+        let (since, missing) = self.expired.get_mut(&key)?;
+        let error = format!(
+            "Join timed out after {:?}; '{}' arrived {:?} after its first branch",
+            self.timeout.unwrap_or_default(),
+            node,
+            since.elapsed()
+        );
+        missing.retain(|missing| missing != node);
+        if missing.is_empty() {
+            self.expired.remove(&key);
+        }
+        Some(error)
+    }
 }
 
 impl Step for StepJoinPoint {
@@ -92,6 +240,10 @@ impl Step for StepJoinPoint {
 
         let mode = StepJoinPoint::parse_join_mode(&params);
         let output_mode = StepJoinPoint::parse_output_mode(&params);
+        let timeout = params
+            .get("timeout_ms")
+            .and_then(|t| t.as_u64())
+            .map(Duration::from_millis);
 
         StepJoinPoint {
             name,
@@ -102,6 +254,11 @@ impl Step for StepJoinPoint {
             output_mode,
             pending: HashMap::new(),
             completed_ids: HashMap::new(),
+            timeout,
+            pending_since: HashMap::new(),
+            expired: HashMap::new(),
+            schemas: HashMap::new(),
+            columns: None,
             params,
         }
     }
@@ -126,11 +283,17 @@ impl Step for StepJoinPoint {
 impl Actor for StepJoinPoint {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Context<Self>) {
+    fn started(&mut self, ctx: &mut Context<Self>) {
         info!(
             "StepJoinPoint '{}' started in {:?} mode.",
             self.name, self.mode
         );
+
+        // Check for stale joins at a fraction of the timeout so they expire reasonably close to it
+        if let Some(timeout) = self.timeout {
+            let interval = (timeout / 4).max(Duration::from_millis(10));
+            ctx.run_interval(interval, |act, _ctx| act.expire_pending());
+        }
     }
 }
 
@@ -145,11 +308,42 @@ impl Handler<ProcessMessage> for StepJoinPoint {
             self.name, msg.id, msg.node_id, msg.data
         );
 
+        // A branch whose data doesn't match its schema can't be joined by name
+        if let Err(e) = check_width(&msg) {
+            self.coordinator.do_send(DeadLetter {
+                step_name: self.name.clone(),
+                error: e,
+                message: msg,
+            });
+            return;
+        }
+        match &msg.schema {
+            Some(schema) => self.schemas.insert(msg.node_id.clone(), schema.clone()),
+            None => self.schemas.remove(&msg.node_id),
+        };
+
         match self.mode {
             JoinMode::AND => {
                 let key = msg.batch_id.unwrap_or(msg.id); // Use batch_id if present, else id
+
+                // A branch arriving after the join ran out of time neither completes it nor
+                // starts it over
+                self.expire_pending();
+                if let Some(e) = self.late_branch(key, &msg.node_id) {
+                    self.coordinator.do_send(DeadLetter {
+                        step_name: self.name.clone(),
+                        error: e,
+                        message: msg,
+                    });
+                    return;
+                }
+
                 let entry = self.pending.entry(key).or_insert_with(HashMap::new);
                 entry.insert(msg.node_id.clone(), msg.data.clone());
+                self.pending_since
+                    .entry(key)
+                    .and_modify(|(_, latest)| *latest = msg.clone())
+                    .or_insert_with(|| (Instant::now(), msg.clone()));
 
                 info!(
                     "StepJoinPoint '{}' current pending data for Key {}: {:?}",
@@ -171,6 +365,22 @@ impl Handler<ProcessMessage> for StepJoinPoint {
                         .iter()
                         .flat_map(|node| entry.get(node).unwrap().clone())
                         .collect();
+                    let joined = self
+                        .joined_schema()
+                        .and_then(|schema| self.select_columns(combined_data, schema));
+                    let (combined_data, schema) = match joined {
+                        Ok(joined) => joined,
+                        Err(e) => {
+                            self.pending.remove(&key);
+                            self.pending_since.remove(&key);
+                            self.coordinator.do_send(DeadLetter {
+                                step_name: self.name.clone(),
+                                error: e,
+                                message: msg,
+                            });
+                            return;
+                        }
+                    };
 
                     info!(
                         "StepJoinPoint '{}' combining data for Key {}: {:?}",
@@ -188,9 +398,12 @@ impl Handler<ProcessMessage> for StepJoinPoint {
                         batch_id: msg.batch_id,
                         batch_total: msg.batch_total,
                         trace: msg.trace.clone(),
+                        member_ids: msg.member_ids.clone(),
+                        schema: schema.map(Arc::new),
                     });
 
                     self.pending.remove(&key);
+                    self.pending_since.remove(&key);
                 } else {
                     info!(
                         "StepJoinPoint '{}' is waiting for more data for Key {}",
@@ -239,6 +452,8 @@ impl Handler<ProcessMessage> for StepJoinPoint {
                     batch_id: msg.batch_id,
                     batch_total: msg.batch_total,
                     trace: msg.trace.clone(),
+                    member_ids: msg.member_ids.clone(),
+                    schema: msg.schema.clone(),
                 });
 
                 let remaining = if self.expected_nodes.len() > 1 {
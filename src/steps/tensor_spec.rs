@@ -0,0 +1,73 @@
+// src/steps/tensor_spec.rs
+
+use serde::Deserialize;
+
+/// InputSpec Struct
+///
+/// Maps message features to one named input tensor, from the `inputs` list in MLModel params.
+#[derive(Deserialize, Debug, Clone)]
+pub struct InputSpec {
+    pub name: String,
+    #[serde(default)]
+    pub dtype: Option<String>, // Element type to feed; defaults to the type the model declares
+    #[serde(default)]
+    pub indices: Option<Vec<usize>>, // Feature indices (per row) that make up this input; defaults to all
+}
+
+/// OutputSpec Struct
+///
+/// Selects one named model output, from the `outputs` list in MLModel params.
+#[derive(Deserialize, Debug, Clone)]
+pub struct OutputSpec {
+    pub name: String,
+    #[serde(default)]
+    pub classes: Vec<String>, // Class labels, used to turn string labels and string-keyed maps into numbers
+}
+
+/// Converts a class label to a number: its position in `classes` if given, otherwise the label parsed as a number.
+pub fn label_to_f64(label: &str, classes: &[String]) -> Result<f64, String> {
+    if classes.is_empty() {
+        label.parse::<f64>().map_err(|_| {
+            format!(
+                "Label '{}' is not numeric and no 'classes' were given",
+                label
+            )
+        })
+    } else {
+        classes
+            .iter()
+            .position(|class| class == label)
+            .map(|index| index as f64)
+            .ok_or_else(|| format!("Label '{}' is not one of the configured classes", label))
+    }
+}
+
+/// Gathers the values of one input tensor from `rows` rows laid out back to back in `data`: the
+/// given feature indices of each row, or every feature. A row too narrow for an index is an error
+/// rather than a panic, as the width is only checked at load time when it is known.
+pub fn gather_features(
+    input: &str,
+    indices: Option<&[usize]>,
+    data: &[f64],
+    rows: usize,
+) -> Result<Vec<f64>, String> {
+    let Some(indices) = indices else {
+        return Ok(data.to_vec());
+    };
+    let width = data.len() / rows.max(1);
+    let mut values = Vec::with_capacity(indices.len() * rows);
+    for row in data.chunks(width.max(1)) {
+        for &index in indices {
+            let value = row.get(index).ok_or_else(|| {
+                format!(
+                    "Input '{}' uses feature index {} but rows only have {} features",
+                    input,
+                    index,
+                    row.len()
+                )
+            })?;
+            values.push(*value);
+        }
+    }
+    Ok(values)
+}
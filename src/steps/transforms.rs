@@ -0,0 +1,786 @@
+// src/steps/transforms.rs
+
+use crate::schema::{Column, FeatureType, Field, Schema};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fs;
+
+/// A parameter given either as one value for every feature or one value per feature.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum PerFeature {
+    One(f64),
+    Many(Vec<f64>),
+}
+
+impl PerFeature {
+    /// The value for the `position`th feature the transform applies to.
+    fn at(&self, position: usize) -> Result<f64, String> {
+        match self {
+            PerFeature::One(value) => Ok(*value),
+            PerFeature::Many(values) => values.get(position).copied().ok_or_else(|| {
+                format!(
+                    "{} per-feature values given but the transform applies to more features",
+                    values.len()
+                )
+            }),
+        }
+    }
+
+    fn values(&self) -> Vec<f64> {
+        match self {
+            PerFeature::One(value) => vec![*value],
+            PerFeature::Many(values) => values.clone(),
+        }
+    }
+}
+
+/// A list of values (bucket boundaries, a vocabulary) shared by every feature or given per feature.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Lists {
+    Shared(Vec<f64>),
+    PerFeature(Vec<Vec<f64>>),
+}
+
+impl Lists {
+    /// The list for the `position`th feature the transform applies to.
+    fn at(&self, position: usize) -> Result<&[f64], String> {
+        match self {
+            Lists::Shared(values) => Ok(values),
+            Lists::PerFeature(lists) => {
+                lists.get(position).map(|l| l.as_slice()).ok_or_else(|| {
+                    format!(
+                        "{} per-feature lists given but the transform applies to more features",
+                        lists.len()
+                    )
+                })
+            }
+        }
+    }
+
+    fn all(&self) -> Vec<&[f64]> {
+        match self {
+            Lists::Shared(values) => vec![values],
+            Lists::PerFeature(lists) => lists.iter().map(|l| l.as_slice()).collect(),
+        }
+    }
+}
+
+/// Transform Enum
+///
+/// One step of a `FeatureProcessor`'s `transforms` list. Elementwise transforms apply to every
+/// feature, or only to the features at `indices` (positions, or names from the message's schema);
+/// per-feature params line up with those features.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Transform {
+    /// Scales to [0, 1]. A missing `min`/`max` is taken from the message's own values.
+    MinMax {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<PerFeature>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<PerFeature>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// Centres on `mean` and divides by `std`. Missing ones are taken from the message's own values.
+    ZScore {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mean: Option<PerFeature>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        std: Option<PerFeature>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// `ln(1 + x)`.
+    Log1p {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// `x^exponent`.
+    Power {
+        exponent: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// Limits values to `min` and/or `max`.
+    Clip {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// Replaces NaN with `value` (0 by default).
+    Impute {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<PerFeature>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// Replaces a value with the number of `boundaries` at or below it, i.e. its bucket index.
+    /// `--fit` can place the boundaries at the column's `quantiles` instead.
+    Bucketize {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        boundaries: Option<Lists>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        quantiles: Option<usize>, // Number of equal-sized buckets to fit
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// Replaces a category value with its position in the `vocabulary`, or `unknown` if it isn't
+    /// in it. `--fit` builds the vocabulary from the most frequent values.
+    Category {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        vocabulary: Option<Lists>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_categories: Option<usize>, // Keep only this many of the most frequent values when fitting
+        #[serde(default = "default_unknown")]
+        unknown: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        indices: Option<Vec<Column>>,
+    },
+    /// Appends every feature raised to each power from 2 to `degree`, and with `interactions`
+    /// the product of every pair of features.
+    Polynomial {
+        degree: u32,
+        #[serde(default)]
+        interactions: bool,
+    },
+    /// Keeps the features at `indices`, in that order. An index may repeat; the schema names the
+    /// repeat `name#2`, `name#3` and so on.
+    Select { indices: Vec<Column> },
+}
+
+fn default_unknown() -> f64 {
+    -1.0
+}
+
+/// TransformArtifact Struct
+///
+/// What `--fit` writes for a `FeatureProcessor`: its transforms with every statistic filled in
+/// from the training data, so serving scales exactly like training did.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TransformArtifact {
+    pub step: String,   // FeatureProcessor the artifact was fitted for
+    pub source: String, // CSV it was fitted on
+    pub rows: usize,
+    pub transforms: Vec<Transform>,
+}
+
+/// Reads and validates the `transforms` list. A step without one passes its data through.
+pub fn parse_transforms(params: &JsonValue) -> Result<Vec<Transform>, String> {
+    let transforms: Vec<Transform> = match params.get("transforms") {
+        Some(transforms) => serde_json::from_value(transforms.clone())
+            .map_err(|e| format!("Invalid 'transforms': {}", e))?,
+        None => return Ok(vec![]),
+    };
+
+    for (index, transform) in transforms.iter().enumerate() {
+        transform
+            .validate()
+            .map_err(|e| format!("Transform {} ({}): {}", index, transform.name(), e))?;
+    }
+    Ok(transforms)
+}
+
+/// Loads a fitted artifact. Its transforms must be the step's `transforms` (if it lists any), in
+/// the same order, or the artifact is stale.
+pub fn load_artifact(path: &str, transforms: &[Transform]) -> Result<Vec<Transform>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read artifact '{}': {}", path, e))?;
+    let artifact: TransformArtifact = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid artifact '{}': {}", path, e))?;
+
+    let names =
+        |transforms: &[Transform]| -> Vec<&str> { transforms.iter().map(|t| t.name()).collect() };
+    if !transforms.is_empty() && names(transforms) != names(&artifact.transforms) {
+        return Err(format!(
+            "Artifact '{}' was fitted for transforms {:?} but the step has {:?}; fit it again",
+            path,
+            names(&artifact.transforms),
+            names(transforms)
+        ));
+    }
+
+    for (index, transform) in artifact.transforms.iter().enumerate() {
+        transform
+            .validate()
+            .map_err(|e| format!("Artifact '{}' transform {}: {}", path, index, e))?;
+    }
+    Ok(artifact.transforms)
+}
+
+/// Checks that nothing in the list still needs fitting before it can serve.
+pub fn check_fitted(transforms: &[Transform]) -> Result<(), String> {
+    for (index, transform) in transforms.iter().enumerate() {
+        let missing = match transform {
+            Transform::Bucketize {
+                boundaries: None, ..
+            } => Some("boundaries"),
+            Transform::Category {
+                vocabulary: None, ..
+            } => Some("vocabulary"),
+            _ => None,
+        };
+        if let Some(missing) = missing {
+            return Err(format!(
+                "Transform {} ({}) has no {}; fit it with --fit and set 'artifact_path'",
+                index,
+                transform.name(),
+                missing
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Fits each transform on the rows as the transforms before it leave them, filling in the
+/// statistics the params leave out. With the rows' schema, features can be addressed by name.
+pub fn fit_transforms(
+    transforms: &[Transform],
+    mut rows: Vec<Vec<f64>>,
+    schema: Option<&Schema>,
+) -> Result<Vec<Transform>, String> {
+    let schemas = schema
+        .map(|schema| schema_chain(transforms, schema))
+        .transpose()?;
+    let mut fitted = Vec::new();
+    for (index, transform) in transforms.iter().enumerate() {
+        let context = |e: String| format!("Transform {} ({}): {}", index, transform.name(), e);
+        let schema = schemas.as_ref().map(|schemas| &schemas[index]);
+        let transform = transform.fit(&rows, schema).map_err(context)?;
+        rows = rows
+            .into_iter()
+            .map(|row| transform.apply(row, schema))
+            .collect::<Result<_, _>>()
+            .map_err(context)?;
+        fitted.push(transform);
+    }
+    Ok(fitted)
+}
+
+/// The schema each transform sees for messages with the `input` schema, followed by the schema
+/// of the output. Fails if a transform addresses a feature the schema doesn't have.
+pub fn schema_chain(transforms: &[Transform], input: &Schema) -> Result<Vec<Schema>, String> {
+    let mut schemas = vec![input.clone()];
+    for (index, transform) in transforms.iter().enumerate() {
+        let output = transform
+            .output_schema(&schemas[index])
+            .map_err(|e| format!("Transform {} ({}): {}", index, transform.name(), e))?;
+        schemas.push(output);
+    }
+    Ok(schemas)
+}
+
+/// Runs every transform in order. `schemas` is the message's `schema_chain`, if it has a schema.
+pub fn apply_transforms(
+    transforms: &[Transform],
+    data: &[f64],
+    schemas: Option<&[Schema]>,
+) -> Result<Vec<f64>, String> {
+    transforms
+        .iter()
+        .enumerate()
+        .try_fold(data.to_vec(), |data, (index, transform)| {
+            transform
+                .apply(data, schemas.map(|schemas| &schemas[index]))
+                .map_err(|e| format!("Transform {} ({}): {}", index, transform.name(), e))
+        })
+}
+
+impl Transform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::MinMax { .. } => "min_max",
+            Transform::ZScore { .. } => "z_score",
+            Transform::Log1p { .. } => "log1p",
+            Transform::Power { .. } => "power",
+            Transform::Clip { .. } => "clip",
+            Transform::Impute { .. } => "impute",
+            Transform::Bucketize { .. } => "bucketize",
+            Transform::Category { .. } => "category",
+            Transform::Polynomial { .. } => "polynomial",
+            Transform::Select { .. } => "select",
+        }
+    }
+
+    /// Checks the params that don't depend on the data.
+    fn validate(&self) -> Result<(), String> {
+        // Per-feature lists must cover the `indices` they apply to
+        let (per_feature, indices): (Vec<&PerFeature>, &Option<Vec<Column>>) = match self {
+            Transform::MinMax { min, max, indices } => {
+                (min.iter().chain(max.iter()).collect(), indices)
+            }
+            Transform::ZScore { mean, std, indices } => {
+                (mean.iter().chain(std.iter()).collect(), indices)
+            }
+            Transform::Impute { value, indices } => (value.iter().collect(), indices),
+            _ => (vec![], &None),
+        };
+        for values in per_feature {
+            match (values, indices) {
+                (PerFeature::Many(values), _) if values.is_empty() => {
+                    return Err("per-feature lists must not be empty".to_string());
+                }
+                (PerFeature::Many(values), Some(indices)) if values.len() != indices.len() => {
+                    return Err(format!(
+                        "{} per-feature values for {} indices",
+                        values.len(),
+                        indices.len()
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        match self {
+            Transform::MinMax {
+                min: Some(min),
+                max: Some(max),
+                ..
+            } => {
+                let (min, max) = (min.values(), max.values());
+                if min.len() > 1 && max.len() > 1 && min.len() != max.len() {
+                    return Err(format!("{} mins but {} maxes", min.len(), max.len()));
+                }
+                let pairs = min.len().max(max.len());
+                for position in 0..pairs {
+                    let low = min[position.min(min.len() - 1)];
+                    let high = max[position.min(max.len() - 1)];
+                    if low >= high {
+                        return Err(format!("min {} is not below max {}", low, high));
+                    }
+                }
+                Ok(())
+            }
+            Transform::ZScore { std: Some(std), .. } => {
+                if std.values().iter().any(|&std| std <= 0.0) {
+                    return Err("std must be positive".to_string());
+                }
+                Ok(())
+            }
+            Transform::Clip { min, max, .. } => match (min, max) {
+                (None, None) => Err("needs a 'min' or a 'max'".to_string()),
+                (Some(min), Some(max)) if min > max => {
+                    Err(format!("min {} is above max {}", min, max))
+                }
+                _ => Ok(()),
+            },
+            Transform::Bucketize {
+                boundaries,
+                quantiles,
+                ..
+            } => {
+                match (boundaries, quantiles) {
+                    (None, None) => return Err("needs 'boundaries' or 'quantiles'".to_string()),
+                    (_, Some(quantiles)) if *quantiles < 2 => {
+                        return Err("needs at least 2 quantiles".to_string())
+                    }
+                    _ => {}
+                }
+                for boundaries in boundaries.iter().flat_map(|b| b.all()) {
+                    if boundaries.windows(2).any(|pair| pair[0] >= pair[1]) {
+                        return Err("boundaries must be strictly increasing".to_string());
+                    }
+                }
+                Ok(())
+            }
+            Transform::Category {
+                max_categories: Some(0),
+                ..
+            } => Err("max_categories must be at least 1".to_string()),
+            Transform::Polynomial { degree, .. } => {
+                if *degree < 2 {
+                    return Err(format!("degree must be at least 2, not {}", degree));
+                }
+                Ok(())
+            }
+            Transform::Select { indices } => {
+                if indices.is_empty() {
+                    return Err("needs at least one index".to_string());
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The schema of the features the transform outputs for the given input schema.
+    pub fn output_schema(&self, schema: &Schema) -> Result<Schema, String> {
+        let mut output = schema.clone();
+        match self {
+            Transform::MinMax { indices, .. }
+            | Transform::ZScore { indices, .. }
+            | Transform::Log1p { indices }
+            | Transform::Power { indices, .. } => {
+                for i in targets(indices, Some(schema), schema.width())? {
+                    output.set_type(i, FeatureType::Float);
+                }
+            }
+            Transform::Bucketize { indices, .. } | Transform::Category { indices, .. } => {
+                for i in targets(indices, Some(schema), schema.width())? {
+                    output.set_type(i, FeatureType::Int);
+                }
+            }
+            Transform::Clip { indices, .. } | Transform::Impute { indices, .. } => {
+                targets(indices, Some(schema), schema.width())?;
+            }
+            Transform::Polynomial {
+                degree,
+                interactions,
+            } => {
+                let names = schema.names();
+                let float = |name: String| Field {
+                    name,
+                    dtype: FeatureType::Float,
+                };
+                for power in 2..=*degree {
+                    output.extend(names.iter().map(|a| float(format!("{}^{}", a, power))))?;
+                }
+                if *interactions {
+                    for (i, a) in names.iter().enumerate() {
+                        output
+                            .extend(names[i + 1..].iter().map(|b| float(format!("{}*{}", a, b))))?;
+                    }
+                }
+            }
+            Transform::Select { indices } => {
+                let indices = indices
+                    .iter()
+                    .map(|column| select_index(column, Some(schema), schema.width()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                output = schema.select(&indices)?;
+            }
+        }
+        Ok(output)
+    }
+
+    /// Applies the transform to one message's features, given the schema they have (if any).
+    pub fn apply(&self, mut data: Vec<f64>, schema: Option<&Schema>) -> Result<Vec<f64>, String> {
+        match self {
+            Transform::MinMax { min, max, indices } => {
+                let targets = targets(indices, schema, data.len())?;
+                let values: Vec<f64> = targets.iter().map(|&i| data[i]).collect();
+                for (position, &i) in targets.iter().enumerate() {
+                    let low = match min {
+                        Some(min) => min.at(position)?,
+                        None => values.iter().cloned().fold(f64::INFINITY, f64::min),
+                    };
+                    let high = match max {
+                        Some(max) => max.at(position)?,
+                        None => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                    };
+                    // Like `--fit`, a range taken from the message is 1 where the values don't spread
+                    let range = match (min, max) {
+                        (Some(_), Some(_)) if high <= low => {
+                            return Err(format!("min {} is not below max {}", low, high));
+                        }
+                        _ if high <= low => 1.0,
+                        _ => high - low,
+                    };
+                    data[i] = (data[i] - low) / range;
+                }
+            }
+            Transform::ZScore { mean, std, indices } => {
+                let targets = targets(indices, schema, data.len())?;
+                let values: Vec<f64> = targets.iter().map(|&i| data[i]).collect();
+                let sample_mean = values.iter().sum::<f64>() / values.len().max(1) as f64;
+                let sample_std = (values
+                    .iter()
+                    .map(|x| (x - sample_mean).powi(2))
+                    .sum::<f64>()
+                    / values.len().max(1) as f64)
+                    .sqrt();
+                for (position, &i) in targets.iter().enumerate() {
+                    let mean = match mean {
+                        Some(mean) => mean.at(position)?,
+                        None => sample_mean,
+                    };
+                    let std = match std {
+                        Some(std) => std.at(position)?,
+                        // Like `--fit`, a std taken from the message is 1 where the values don't spread
+                        None if sample_std == 0.0 => 1.0,
+                        None => sample_std,
+                    };
+                    if std == 0.0 || std.is_nan() {
+                        return Err("standard deviation is zero or NaN".to_string());
+                    }
+                    data[i] = (data[i] - mean) / std;
+                }
+            }
+            Transform::Log1p { indices } => {
+                for i in targets(indices, schema, data.len())? {
+                    data[i] = data[i].ln_1p();
+                }
+            }
+            Transform::Power { exponent, indices } => {
+                for i in targets(indices, schema, data.len())? {
+                    data[i] = data[i].powf(*exponent);
+                }
+            }
+            Transform::Clip { min, max, indices } => {
+                for i in targets(indices, schema, data.len())? {
+                    data[i] = data[i]
+                        .max(min.unwrap_or(f64::NEG_INFINITY))
+                        .min(max.unwrap_or(f64::INFINITY));
+                }
+            }
+            Transform::Impute { value, indices } => {
+                for (position, i) in targets(indices, schema, data.len())?
+                    .into_iter()
+                    .enumerate()
+                {
+                    if data[i].is_nan() {
+                        data[i] = match value {
+                            Some(value) => value.at(position)?,
+                            None => 0.0,
+                        };
+                    }
+                }
+            }
+            Transform::Bucketize {
+                boundaries,
+                indices,
+                ..
+            } => {
+                let boundaries = boundaries
+                    .as_ref()
+                    .ok_or_else(|| "boundaries have not been fitted".to_string())?;
+                for (position, i) in targets(indices, schema, data.len())?
+                    .into_iter()
+                    .enumerate()
+                {
+                    let boundaries = boundaries.at(position)?;
+                    data[i] = boundaries.partition_point(|&boundary| boundary <= data[i]) as f64;
+                }
+            }
+            Transform::Category {
+                vocabulary,
+                unknown,
+                indices,
+                ..
+            } => {
+                let vocabulary = vocabulary
+                    .as_ref()
+                    .ok_or_else(|| "vocabulary has not been fitted".to_string())?;
+                for (position, i) in targets(indices, schema, data.len())?
+                    .into_iter()
+                    .enumerate()
+                {
+                    data[i] = vocabulary
+                        .at(position)?
+                        .iter()
+                        .position(|&value| value == data[i])
+                        .map(|index| index as f64)
+                        .unwrap_or(*unknown);
+                }
+            }
+            Transform::Polynomial {
+                degree,
+                interactions,
+            } => {
+                let features = data.clone();
+                for power in 2..=*degree as i32 {
+                    data.extend(features.iter().map(|x| x.powi(power)));
+                }
+                if *interactions {
+                    for (i, a) in features.iter().enumerate() {
+                        data.extend(features[i + 1..].iter().map(|b| a * b));
+                    }
+                }
+            }
+            Transform::Select { indices } => {
+                return indices
+                    .iter()
+                    .map(|column| Ok(data[select_index(column, schema, data.len())?]))
+                    .collect();
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl Transform {
+    /// Returns a copy with the statistics the params leave out computed over `rows`. NaN values
+    /// (missing cells) are left out of every statistic.
+    pub fn fit(&self, rows: &[Vec<f64>], schema: Option<&Schema>) -> Result<Transform, String> {
+        let width = rows.first().map(|row| row.len()).unwrap_or(0);
+        if rows.iter().any(|row| row.len() != width) {
+            return Err("rows have different numbers of features".to_string());
+        }
+        let columns = |indices: &Option<Vec<Column>>| -> Result<Vec<Vec<f64>>, String> {
+            if rows.is_empty() {
+                return Err("no rows to fit on".to_string());
+            }
+            targets(indices, schema, width)?
+                .into_iter()
+                .map(|i| {
+                    let mut column: Vec<f64> = rows
+                        .iter()
+                        .map(|row| row[i])
+                        .filter(|x| !x.is_nan())
+                        .collect();
+                    if column.is_empty() {
+                        return Err(format!("feature {} has no values", i));
+                    }
+                    column.sort_by(|a, b| a.total_cmp(b));
+                    Ok(column)
+                })
+                .collect()
+        };
+
+        let fitted = match self {
+            Transform::MinMax { min, max, indices } if min.is_none() || max.is_none() => {
+                let columns = columns(indices)?;
+                let features = targets(indices, schema, width)?;
+                Transform::MinMax {
+                    min: Some(min.clone().unwrap_or_else(|| {
+                        PerFeature::Many(columns.iter().map(|c| c[0]).collect())
+                    })),
+                    max: Some(max.clone().unwrap_or_else(|| {
+                        // A constant feature gets a range of 1 instead of dividing by zero
+                        PerFeature::Many(
+                            columns
+                                .iter()
+                                .zip(&features)
+                                .map(|(c, feature)| {
+                                    let (low, high) = (c[0], c[c.len() - 1]);
+                                    if low < high {
+                                        return high;
+                                    }
+                                    warn!("Feature {} is constant, fitting a range of 1", feature);
+                                    low + 1.0
+                                })
+                                .collect(),
+                        )
+                    })),
+                    indices: indices.clone(),
+                }
+            }
+            Transform::ZScore { mean, std, indices } if mean.is_none() || std.is_none() => {
+                let columns = columns(indices)?;
+                let means: Vec<f64> = columns
+                    .iter()
+                    .map(|c| c.iter().sum::<f64>() / c.len() as f64)
+                    .collect();
+                let features = targets(indices, schema, width)?;
+                let stds: Vec<f64> = columns
+                    .iter()
+                    .zip(&means)
+                    .zip(&features)
+                    .map(|((c, mean), feature)| {
+                        let std = (c.iter().map(|x| (x - mean).powi(2)).sum::<f64>()
+                            / c.len() as f64)
+                            .sqrt();
+                        if std > 0.0 {
+                            return std;
+                        }
+                        // A constant feature gets a std of 1 instead of dividing by zero
+                        warn!("Feature {} is constant, fitting a std of 1", feature);
+                        1.0
+                    })
+                    .collect();
+                Transform::ZScore {
+                    mean: Some(mean.clone().unwrap_or(PerFeature::Many(means))),
+                    std: Some(std.clone().unwrap_or(PerFeature::Many(stds))),
+                    indices: indices.clone(),
+                }
+            }
+            Transform::Bucketize {
+                boundaries: None,
+                quantiles: Some(quantiles),
+                indices,
+            } => {
+                let boundaries = columns(indices)?
+                    .iter()
+                    .map(|column| {
+                        let mut boundaries: Vec<f64> = (1..*quantiles)
+                            .map(|q| column[(q * column.len() / quantiles).min(column.len() - 1)])
+                            .collect();
+                        // Repeated values can make quantiles coincide
+                        boundaries.dedup();
+                        boundaries
+                    })
+                    .collect();
+                Transform::Bucketize {
+                    boundaries: Some(Lists::PerFeature(boundaries)),
+                    quantiles: Some(*quantiles),
+                    indices: indices.clone(),
+                }
+            }
+            Transform::Category {
+                vocabulary: None,
+                max_categories,
+                unknown,
+                indices,
+            } => {
+                let vocabulary = columns(indices)?
+                    .iter()
+                    .map(|column| {
+                        let mut counts: HashMap<u64, usize> = HashMap::new();
+                        for value in column {
+                            *counts.entry(value.to_bits()).or_default() += 1;
+                        }
+                        let mut values: Vec<(f64, usize)> = counts
+                            .into_iter()
+                            .map(|(bits, count)| (f64::from_bits(bits), count))
+                            .collect();
+                        // Most frequent first, ties in value order
+                        values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.total_cmp(&b.0)));
+                        values
+                            .into_iter()
+                            .take(max_categories.unwrap_or(usize::MAX))
+                            .map(|(value, _)| value)
+                            .collect()
+                    })
+                    .collect();
+                Transform::Category {
+                    vocabulary: Some(Lists::PerFeature(vocabulary)),
+                    max_categories: *max_categories,
+                    unknown: *unknown,
+                    indices: indices.clone(),
+                }
+            }
+            other => other.clone(),
+        };
+
+        fitted.validate()?;
+        Ok(fitted)
+    }
+}
+
+/// The feature positions an elementwise transform applies to.
+fn targets(
+    indices: &Option<Vec<Column>>,
+    schema: Option<&Schema>,
+    len: usize,
+) -> Result<Vec<usize>, String> {
+    match indices {
+        Some(indices) => indices
+            .iter()
+            .map(|column| select_index(column, schema, len))
+            .collect(),
+        None => Ok((0..len).collect()),
+    }
+}
+
+/// Resolves a column to a position within the `len` features.
+fn select_index(column: &Column, schema: Option<&Schema>, len: usize) -> Result<usize, String> {
+    let index = Schema::resolve(schema, column)?;
+    if index >= len {
+        return Err(out_of_range(index, len));
+    }
+    Ok(index)
+}
+
+fn out_of_range(index: usize, len: usize) -> String {
+    format!(
+        "feature index {} is out of range for {} features",
+        index, len
+    )
+}
@@ -0,0 +1,116 @@
+// tests/builtin_model.rs
+//
+// Evaluates the pure-Rust built-in models, which need no onnxruntime.
+
+mod common;
+
+use actor_poc::steps::builtin_model::BuiltinModel;
+use common::assert_close;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// Writes a model file to the temp dir and loads it.
+fn load(name: &str, model: Value) -> Result<BuiltinModel, String> {
+    let path: PathBuf = std::env::temp_dir().join(format!(
+        "builtin_model_{}_{}.json",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, model.to_string()).unwrap();
+    let loaded = BuiltinModel::load(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    loaded
+}
+
+#[test]
+fn linear_model_predicts_every_row() {
+    let model = load(
+        "linear",
+        json!({ "type": "linear", "coefficients": [1.0, 2.0, -1.0], "intercept": 0.5 }),
+    )
+    .unwrap();
+
+    assert_eq!(model.check_width(None).unwrap(), Some(3));
+    assert!(model.check_width(Some(4)).is_err());
+
+    let predictions = model.predict(&[1.0, 1.0, 1.0, 0.0, 2.0, 4.0], 2).unwrap();
+    assert_close(&predictions[0], &[2.5]);
+    assert_close(&predictions[1], &[0.5]);
+}
+
+#[test]
+fn logistic_model_applies_sigmoid_or_softmax() {
+    let binary = load(
+        "sigmoid",
+        json!({ "type": "logistic", "coefficients": [1.0, 1.0], "intercept": 0.0 }),
+    )
+    .unwrap();
+    assert_close(&binary.predict_row(&[0.0, 0.0]).unwrap(), &[0.5]);
+
+    let multiclass = load(
+        "softmax",
+        json!({
+            "type": "logistic",
+            "coefficients": [[1.0, 0.0], [0.0, 1.0], [0.0, 0.0]],
+            "intercept": [0.0, 0.0, 0.0]
+        }),
+    )
+    .unwrap();
+    let probabilities = multiclass.predict_row(&[1000.0, 1000.0]).unwrap();
+    assert_close(&probabilities, &[0.5, 0.5, 0.0]);
+}
+
+#[test]
+fn tree_and_forest_follow_sklearn_layout() {
+    let model = BuiltinModel::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/data/builtin_tree.json"
+    ))
+    .unwrap();
+    assert!(model.check_width(Some(3)).is_err());
+
+    // feature 2 <= 0.5, then feature 0 <= -1.25
+    assert_close(
+        &model.predict_row(&[-2.0, 0.0, 0.0, 0.0]).unwrap(),
+        &[20.0 / 21.0, 1.0 / 21.0],
+    );
+    // feature 2 > 0.5, then feature 3 > 1.0
+    assert_close(
+        &model.predict_row(&[0.0, 0.0, 1.0, 2.0]).unwrap(),
+        &[3.0 / 37.0, 34.0 / 37.0],
+    );
+
+    let stump = |threshold: f64, left: f64, right: f64| {
+        json!({
+            "children_left": [1, -1, -1],
+            "children_right": [2, -1, -1],
+            "feature": [0, -2, -2],
+            "threshold": [threshold, -2.0, -2.0],
+            "value": [[0.0], [left], [right]]
+        })
+    };
+    let forest = load(
+        "forest",
+        json!({ "type": "forest", "trees": [stump(0.0, 1.0, 2.0), stump(5.0, 10.0, 20.0)] }),
+    )
+    .unwrap();
+    assert_close(&forest.predict_row(&[1.0]).unwrap(), &[6.0]);
+}
+
+#[test]
+fn malformed_trees_are_rejected() {
+    let cyclic = json!({
+        "type": "tree",
+        "tree": {
+            "children_left": [0, -1],
+            "children_right": [1, -1],
+            "feature": [0, -2],
+            "threshold": [0.0, -2.0],
+            "value": [[0.0], [1.0]]
+        }
+    });
+    assert!(load("cyclic", cyclic).is_err());
+
+    let ragged = json!({ "type": "linear", "coefficients": [[1.0, 2.0], [1.0]] });
+    assert!(load("ragged", ragged).is_err());
+}
@@ -0,0 +1,47 @@
+// tests/common/mod.rs
+//
+// Helpers shared by the integration tests. Each test binary uses only some of them.
+#![allow(dead_code)]
+
+use actix_web::{web, App, HttpServer};
+use std::sync::mpsc;
+use std::thread;
+
+/// Asserts two feature vectors are equally long and equal up to rounding.
+pub fn assert_close(actual: &[f64], expected: &[f64]) {
+    assert_eq!(
+        actual.len(),
+        expected.len(),
+        "{:?} vs {:?}",
+        actual,
+        expected
+    );
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-9, "{:?} vs {:?}", actual, expected);
+    }
+}
+
+/// Starts a mock model server with the given routes on a free port and returns its base URL.
+/// The handlers get `state` as `web::Data`. The server runs on a thread of its own, so it keeps
+/// answering whether the test is async or blocks on a `Pipeline`.
+pub fn start_mock_server<T: Clone + Send + 'static>(
+    state: T,
+    routes: fn(&mut web::ServiceConfig),
+) -> String {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        actix::System::new().block_on(async move {
+            let server = HttpServer::new(move || {
+                App::new()
+                    .app_data(web::Data::new(state.clone()))
+                    .configure(routes)
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+            tx.send(server.addrs()[0]).unwrap();
+            server.run().await
+        })
+    });
+    format!("http://{}", rx.recv().unwrap())
+}
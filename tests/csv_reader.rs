@@ -0,0 +1,137 @@
+// tests/csv_reader.rs
+//
+// Reads CSV files the way a CsvReader's params say to.
+
+use actor_poc::steps::csv_reader::{read_rows, CsvFile, Row};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Writes a CSV file to the temp dir, returning its path.
+fn write_csv(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("csv_reader_{}_{}.csv", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Every row of the file, with malformed ones as their line and error.
+fn rows(path: &Path, params: Value) -> Vec<Result<Vec<f64>, (u64, String)>> {
+    let csv_file = CsvFile::open(path.to_str().unwrap(), &params).unwrap();
+    csv_file
+        .rows()
+        .unwrap()
+        .map(|row| match row.unwrap() {
+            Row::Features(features) => Ok(features),
+            Row::Malformed(row) => Err((row.line, row.error)),
+        })
+        .collect()
+}
+
+#[test]
+fn quoted_cells_and_other_delimiters_are_read() {
+    let path = write_csv(
+        "quoted",
+        "amount;\"items; total\";\"flagged\"\n\"1.5\";3;true\n\n2;\"4\";0\n",
+    );
+    let params = json!({
+        "header": true,
+        "delimiter": ";",
+        "schema": ["amount", { "name": "items; total", "type": "int" },
+                   { "name": "flagged", "type": "bool" }]
+    });
+    assert_eq!(
+        rows(&path, params),
+        vec![Ok(vec![1.5, 3.0, 1.0]), Ok(vec![2.0, 4.0, 0.0])]
+    );
+
+    let tabs = write_csv("tabs", "1\t2\n3\t\n");
+    let rows = rows(&tabs, json!({ "delimiter": "\t" }));
+    assert_eq!(rows[0], Ok(vec![1.0, 2.0]));
+    assert!(rows[1].as_ref().unwrap()[1].is_nan());
+
+    let error = CsvFile::open(path.to_str().unwrap(), &json!({ "delimiter": "::" }))
+        .err()
+        .unwrap();
+    assert!(error.contains("single ASCII character"), "{}", error);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&tabs).unwrap();
+}
+
+#[test]
+fn columns_are_picked_by_name_or_position() {
+    let path = write_csv("columns", "id,amount,items\n7,1.5,3\n8,2.5,4\n");
+    let csv_file = CsvFile::open(
+        path.to_str().unwrap(),
+        &json!({ "header": true, "columns": ["items", 1] }),
+    )
+    .unwrap();
+    assert_eq!(csv_file.schema().unwrap().names(), ["items", "amount"]);
+    let (_, picked) = read_rows(
+        path.to_str().unwrap(),
+        &json!({ "header": true, "columns": ["items", 1] }),
+    )
+    .unwrap();
+    assert_eq!(picked, vec![vec![3.0, 1.5], vec![4.0, 2.5]]);
+
+    for (columns, expected) in [
+        (json!(["nope"]), "no feature named 'nope'"),
+        (json!([3]), "out of range"),
+    ] {
+        let error = CsvFile::open(
+            path.to_str().unwrap(),
+            &json!({ "header": true, "columns": columns }),
+        )
+        .err()
+        .unwrap();
+        assert!(error.contains(expected), "{}", error);
+    }
+    // Without a header or schema, columns can only be positions
+    assert!(CsvFile::open(path.to_str().unwrap(), &json!({ "columns": ["id"] })).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn malformed_rows_are_reported_whole_instead_of_shifted() {
+    let path = write_csv("malformed", "1,2,3\n4,x,6\n7,8\n9,10,11\n");
+    assert_eq!(
+        rows(&path, json!({})),
+        vec![
+            Ok(vec![1.0, 2.0, 3.0]),
+            Err((2, "cell 1 is not a number: 'x'".to_string())),
+            Err((3, "2 cells but rows have 3".to_string())),
+            Ok(vec![9.0, 10.0, 11.0]),
+        ]
+    );
+
+    // Skipped by default, and fatal with `on_error: fail`
+    let (_, skipped) = read_rows(path.to_str().unwrap(), &json!({})).unwrap();
+    assert_eq!(skipped, vec![vec![1.0, 2.0, 3.0], vec![9.0, 10.0, 11.0]]);
+    let error = read_rows(path.to_str().unwrap(), &json!({ "on_error": "fail" }))
+        .err()
+        .unwrap();
+    assert!(error.starts_with("Line 2 of"), "{}", error);
+
+    assert!(CsvFile::open(path.to_str().unwrap(), &json!({ "on_error": "ignore" })).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn missing_files_and_bad_headers_fail_to_open() {
+    let error = CsvFile::open("/nonexistent/data.csv", &json!({}))
+        .err()
+        .unwrap();
+    assert!(error.contains("Failed to open CSV file"), "{}", error);
+
+    let path = write_csv("header", "a,b\n1,2\n");
+    let error = CsvFile::open(
+        path.to_str().unwrap(),
+        &json!({ "header": true, "schema": ["a", "c"] }),
+    )
+    .err()
+    .unwrap();
+    assert!(error.contains("but the schema names"), "{}", error);
+    std::fs::remove_file(&path).unwrap();
+
+    let empty = write_csv("empty", "");
+    assert!(CsvFile::open(empty.to_str().unwrap(), &json!({ "header": true })).is_err());
+    std::fs::remove_file(&empty).unwrap();
+}
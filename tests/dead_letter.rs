@@ -0,0 +1,241 @@
+// tests/dead_letter.rs
+//
+// Parks messages a step fails on in the dead-letter file, answers their HTTP requests with the
+// failure and replays the parked messages once the step works again. Also times out joins.
+
+mod common;
+
+use actix::Actor;
+use actix_web::{test, web, App, HttpResponse};
+use actor_poc::config::{adapt_for_requests, Config};
+use actor_poc::coordinator::Coordinator;
+use actor_poc::dead_letter::read_dead_letters;
+use actor_poc::http_input_handler::handle_http_request;
+use actor_poc::messages::{Initialize, ProcessMessage, Replay};
+use actor_poc::step::Step;
+use actor_poc::steps::step_join_point::StepJoinPoint;
+use common::start_mock_server;
+use dashmap::DashMap;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// What the mock model server saw: whether `/model` fails and the features `/audit` received.
+#[derive(Default)]
+struct Server {
+    failing: AtomicBool,
+    audited: Mutex<Vec<Value>>,
+}
+
+/// The mock server's routes: `/model` answers with the sum of the features, or a 500 while
+/// failing; `/audit` records the features and echoes them.
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route(
+            "/model",
+            web::post().to(
+                |body: web::Json<Value>, server: web::Data<Arc<Server>>| async move {
+                    if server.failing.load(Ordering::SeqCst) {
+                        return HttpResponse::InternalServerError().body("model is down");
+                    }
+                    let sum: f64 = body["features"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|v| v.as_f64().unwrap())
+                        .sum();
+                    HttpResponse::Ok().json(json!({ "processed_features": [sum] }))
+                },
+            ),
+        )
+        .route(
+            "/audit",
+            web::post().to(
+                |body: web::Json<Value>, server: web::Data<Arc<Server>>| async move {
+                    server
+                        .audited
+                        .lock()
+                        .unwrap()
+                        .push(body["features"].clone());
+                    HttpResponse::Ok().json(json!({ "processed_features": body["features"] }))
+                },
+            ),
+        );
+}
+
+/// A DAG scoring generated rows remotely, then passing the score to an audit endpoint.
+fn config_json(base_url: &str, dead_letter_path: &str) -> Value {
+    json!({
+        "dead_letter_path": dead_letter_path,
+        "steps": [
+            { "name": "gen", "type": "DataGenerator", "inputs": [], "outputs": ["raw"],
+              "params": { "limit": 1 } },
+            { "name": "model", "type": "MLModel", "inputs": ["raw"], "outputs": ["scored"],
+              "params": { "remote_endpoint": format!("{}/model", base_url) } },
+            { "name": "audit", "type": "MLModel", "inputs": ["scored"], "outputs": ["audited"],
+              "params": { "remote_endpoint": format!("{}/audit", base_url) } },
+            { "name": "printer", "type": "Printer", "inputs": ["audited"], "outputs": [] }
+        ]
+    })
+}
+
+/// Polls until `condition` holds, for up to five seconds.
+async fn eventually(condition: impl Fn() -> bool) -> bool {
+    for _ in 0..100 {
+        if condition() {
+            return true;
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+#[actix::test]
+async fn failed_messages_are_parked_answered_with_the_error_and_replayed() {
+    let server = Arc::new(Server::default());
+    server.failing.store(true, Ordering::SeqCst);
+    let base_url = start_mock_server(server.clone(), routes);
+    let dead_letter_path =
+        std::env::temp_dir().join(format!("dead_letter_{}.jsonl", std::process::id()));
+    let dead_letter_path = dead_letter_path.to_str().unwrap().to_string();
+    let _ = std::fs::remove_file(&dead_letter_path);
+
+    // Serve the DAG over HTTP, as `--http` does
+    let mut http_config = config_json(&base_url, &dead_letter_path);
+    adapt_for_requests(&mut http_config);
+    let mut config: Config = serde_json::from_value(http_config).unwrap();
+    config.http_mode = true;
+    let sender_map = Arc::new(DashMap::<u64, oneshot::Sender<ProcessMessage>>::new());
+    let coordinator = Coordinator::new(config.clone(), Some(sender_map.clone())).start();
+    coordinator
+        .send(Initialize { config })
+        .await
+        .unwrap()
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(coordinator.clone()))
+            .route(
+                "/process",
+                web::post().to(move |data, payload| {
+                    handle_http_request(data, payload, Some(sender_map.clone()))
+                }),
+            ),
+    )
+    .await;
+
+    // The request fails with the step and its error
+    let request = test::TestRequest::post()
+        .uri("/process")
+        .set_json(json!({ "features": [1.0, 2.0] }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 500);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["step"], "model");
+    let error = body["error"]["message"].as_str().unwrap();
+    assert!(error.contains("returned 500"), "{}", error);
+    assert!(error.contains("model is down"), "{}", error);
+
+    // The message is parked as it arrived at the failing step
+    let records = read_dead_letters(&dead_letter_path).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].step_name, "model");
+    assert!(
+        records[0].error.contains("model is down"),
+        "{}",
+        records[0].error
+    );
+    assert_eq!(records[0].message.data, vec![1.0, 2.0]);
+    assert!(server.audited.lock().unwrap().is_empty());
+
+    // A message without features fails the same way instead of leaving the request hanging
+    let request = test::TestRequest::post()
+        .uri("/process")
+        .set_json(json!({ "features": [] }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 500);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["step"], "model");
+    let error = body["error"]["message"].as_str().unwrap();
+    assert!(error.contains("empty feature data"), "{}", error);
+
+    // Once the model is back, replaying the record runs the message through the rest of the DAG
+    server.failing.store(false, Ordering::SeqCst);
+    let mut config: Config =
+        serde_json::from_value(config_json(&base_url, &dead_letter_path)).unwrap();
+    config.replay_mode = true;
+    let replayer = Coordinator::new(config.clone(), None).start();
+    replayer.send(Initialize { config }).await.unwrap().unwrap();
+    for record in records {
+        replayer.do_send(Replay {
+            step_name: "model".to_string(),
+            message: record.message,
+        });
+    }
+    assert!(eventually(|| !server.audited.lock().unwrap().is_empty()).await);
+    assert_eq!(*server.audited.lock().unwrap(), [json!([3.0])]);
+    assert_eq!(read_dead_letters(&dead_letter_path).unwrap().len(), 2);
+    std::fs::remove_file(&dead_letter_path).unwrap();
+}
+
+#[actix::test]
+async fn join_timeouts_count_from_the_first_branch() {
+    let dead_letter_path =
+        std::env::temp_dir().join(format!("dead_letter_join_{}.jsonl", std::process::id()));
+    let dead_letter_path = dead_letter_path.to_str().unwrap().to_string();
+    let _ = std::fs::remove_file(&dead_letter_path);
+
+    let config: Config = serde_json::from_value(json!({
+        "dead_letter_path": dead_letter_path,
+        "steps": []
+    }))
+    .unwrap();
+    let coordinator = Coordinator::new(config, None).start();
+    let join = StepJoinPoint::new_from_params(
+        "join".to_string(),
+        "joined".to_string(),
+        coordinator,
+        json!({ "expected_nodes": ["a", "b", "c"], "timeout_ms": 100 }),
+    )
+    .start();
+    let branch = |node_id: &str| ProcessMessage {
+        id: 1,
+        node_id: node_id.to_string(),
+        data: vec![1.0],
+        batch_id: None,
+        batch_total: None,
+        trace: Default::default(),
+        member_ids: vec![],
+        schema: None,
+    };
+
+    // `b` arrives within the timeout, but `c` doesn't
+    join.do_send(branch("a"));
+    actix::clock::sleep(Duration::from_millis(60)).await;
+    join.do_send(branch("b"));
+    assert!(eventually(|| read_dead_letters(&dead_letter_path).is_ok_and(|r| r.len() == 1)).await);
+    let records = read_dead_letters(&dead_letter_path).unwrap();
+    assert!(
+        records[0].error.contains(r#"["c"]"#),
+        "{}",
+        records[0].error
+    );
+
+    // Arriving late, `c` is parked on its own rather than starting the join over
+    join.do_send(branch("c"));
+    assert!(eventually(|| read_dead_letters(&dead_letter_path).is_ok_and(|r| r.len() == 2)).await);
+    let records = read_dead_letters(&dead_letter_path).unwrap();
+    assert_eq!(records[1].message.node_id, "c");
+    assert!(
+        records[1].error.contains("'c' arrived"),
+        "{}",
+        records[1].error
+    );
+    actix::clock::sleep(Duration::from_millis(300)).await;
+    assert_eq!(read_dead_letters(&dead_letter_path).unwrap().len(), 2);
+    std::fs::remove_file(&dead_letter_path).unwrap();
+}
@@ -0,0 +1,126 @@
+// tests/entity_aggregator.rs
+//
+// Runs events through the per-key windows of an EntityAggregator.
+
+mod common;
+
+use actor_poc::schema::Schema;
+use actor_poc::steps::entity_aggregator::EntityStore;
+use common::assert_close;
+use serde_json::json;
+
+#[test]
+fn aggregates_cover_the_last_events_of_each_key() {
+    let mut store = EntityStore::from_params(&json!({
+        "key": 0,
+        "window_events": 2,
+        "aggregates": [
+            { "type": "count" },
+            { "type": "sum", "column": 1 },
+            { "type": "mean", "column": 1 },
+            { "type": "max", "column": 1 },
+            { "type": "ewma", "column": 1, "alpha": 0.5 }
+        ]
+    }))
+    .unwrap();
+
+    let card = |card: f64, amount: f64| vec![card, amount];
+    assert_close(
+        &store.update(&card(7.0, 10.0), None).unwrap()[2..],
+        &[1.0, 10.0, 10.0, 10.0, 10.0],
+    );
+    assert_close(
+        &store.update(&card(9.0, 100.0), None).unwrap()[2..],
+        &[1.0, 100.0, 100.0, 100.0, 100.0],
+    );
+    assert_close(
+        &store.update(&card(7.0, 20.0), None).unwrap()[2..],
+        &[2.0, 30.0, 15.0, 20.0, 15.0],
+    );
+    // The window keeps two events, the EWMA keeps going
+    assert_close(
+        &store.update(&card(7.0, 6.0), None).unwrap()[2..],
+        &[2.0, 26.0, 13.0, 20.0, 10.5],
+    );
+    assert_eq!(store.len(), 2);
+}
+
+#[test]
+fn time_windows_and_idle_keys_follow_event_time() {
+    let schema: Schema = serde_json::from_value(json!(["ts", "user", "amount"])).unwrap();
+    let mut store = EntityStore::from_params(&json!({
+        "key": "user",
+        "time": "ts",
+        "window_secs": 60,
+        "idle_secs": 300,
+        "aggregates": [{ "type": "count" }, { "type": "min", "column": "amount", "name": "low" }]
+    }))
+    .unwrap();
+    assert_eq!(
+        store.output_schema(&schema).unwrap().names(),
+        ["ts", "user", "amount", "count", "low"]
+    );
+
+    let event = |store: &mut EntityStore, ts: f64, user: f64, amount: f64| {
+        store
+            .update(&[ts, user, amount], Some(&schema))
+            .unwrap()
+            .split_off(3)
+    };
+    assert_close(&event(&mut store, 0.0, 1.0, 5.0), &[1.0, 5.0]);
+    assert_close(&event(&mut store, 30.0, 1.0, 8.0), &[2.0, 5.0]);
+    // The first event is more than 60s old now
+    assert_close(&event(&mut store, 61.0, 1.0, 9.0), &[2.0, 8.0]);
+    assert_close(&event(&mut store, 100.0, 2.0, 1.0), &[1.0, 1.0]);
+
+    // User 1 was last seen at 61, so it has gone idle by 400
+    event(&mut store, 400.0, 2.0, 1.0);
+    assert_eq!(store.len(), 1);
+    assert_close(&event(&mut store, 401.0, 1.0, 3.0), &[1.0, 3.0]);
+}
+
+#[test]
+fn max_keys_evicts_the_least_recently_seen() {
+    let mut store = EntityStore::from_params(&json!({
+        "key": 0,
+        "window_events": 10,
+        "max_keys": 2,
+        "aggregates": [{ "type": "count" }]
+    }))
+    .unwrap();
+
+    for key in [1.0, 2.0, 1.0, 3.0] {
+        store.update(&[key], None).unwrap();
+    }
+    assert_eq!(store.len(), 2);
+    // Key 2 was evicted, key 1 kept its window
+    assert_close(&store.update(&[1.0], None).unwrap(), &[1.0, 3.0]);
+    assert_close(&store.update(&[2.0], None).unwrap(), &[2.0, 1.0]);
+}
+
+#[test]
+fn bad_params_and_events_are_rejected() {
+    for params in [
+        json!({ "window_events": 5, "aggregates": [{ "type": "count" }] }),
+        json!({ "key": 0, "aggregates": [{ "type": "count" }] }),
+        json!({ "key": 0, "window_events": 5, "aggregates": [] }),
+        json!({ "key": 0, "window_events": 5, "aggregates": [{ "type": "sum" }] }),
+        json!({ "key": 0, "window_events": 5, "aggregates": [{ "type": "ewma", "column": 1 }] }),
+        json!({ "key": 0, "window_events": 0, "aggregates": [{ "type": "count" }] }),
+        json!({ "key": 0, "window_events": 0.5, "aggregates": [{ "type": "count" }] }),
+        json!({ "key": 0, "window_events": 5, "max_keys": 0.5, "aggregates": [{ "type": "count" }] }),
+    ] {
+        assert!(EntityStore::from_params(&params).is_err(), "{}", params);
+    }
+
+    let mut store = EntityStore::from_params(&json!({
+        "key": "user",
+        "window_events": 5,
+        "aggregates": [{ "type": "count" }]
+    }))
+    .unwrap();
+    assert!(store.update(&[1.0], None).is_err());
+    let schema: Schema = serde_json::from_value(json!(["user"])).unwrap();
+    assert!(store.update(&[f64::NAN], Some(&schema)).is_err());
+    assert!(store.is_empty());
+}
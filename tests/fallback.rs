@@ -0,0 +1,162 @@
+// tests/fallback.rs
+//
+// Tries the backends of a fallback chain in order against an in-process mock model server.
+
+mod common;
+
+use actix_web::{web, HttpResponse};
+use actor_poc::pipeline::Pipeline;
+use actor_poc::steps::fallback::FallbackChain;
+use common::start_mock_server;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Received = Arc<Mutex<Vec<String>>>;
+
+/// Answers with the sum of the features, recording the request's path.
+async fn sum(
+    request: actix_web::HttpRequest,
+    body: web::Json<Value>,
+    received: web::Data<Received>,
+) -> HttpResponse {
+    received.lock().unwrap().push(request.path().to_string());
+    let sum: f64 = body["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_f64().unwrap())
+        .sum();
+    HttpResponse::Ok().json(json!({ "processed_features": [sum] }))
+}
+
+/// The mock server's routes: `/ok` answers with the sum, `/fail` with a 500 and `/slow` with the
+/// sum after half a second. Every request's path is recorded.
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route("/ok", web::post().to(sum))
+        .route(
+            "/fail",
+            web::post().to(
+                |request: actix_web::HttpRequest, received: web::Data<Received>| async move {
+                    received.lock().unwrap().push(request.path().to_string());
+                    HttpResponse::InternalServerError().json(json!({ "error": "model failed" }))
+                },
+            ),
+        )
+        .route(
+            "/slow",
+            web::post().to(
+                |request: actix_web::HttpRequest,
+                 body: web::Json<Value>,
+                 received: web::Data<Received>| async move {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    sum(request, body, received).await
+                },
+            ),
+        );
+}
+
+/// A chain of the given backends.
+fn chain_of(backends: Value) -> FallbackChain {
+    FallbackChain::from_params(&json!({ "backends": backends }), None).unwrap()
+}
+
+#[actix::test]
+async fn a_failing_primary_falls_back_to_the_next_backend() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+    let chain = chain_of(json!([
+        { "type": "remote", "name": "primary", "remote_endpoint": format!("{}/fail", base_url) },
+        { "type": "remote", "name": "secondary", "remote_endpoint": format!("{}/ok", base_url) },
+        { "type": "constant", "value": [0.0] }
+    ]));
+
+    let (backend, predictions) = chain.predict(vec![1.0, 2.0], 1).await.unwrap();
+    assert_eq!(backend, "secondary");
+    assert_eq!(predictions, vec![vec![3.0]]);
+    assert_eq!(*received.lock().unwrap(), ["/fail", "/ok"]);
+}
+
+#[actix::test]
+async fn a_primary_that_times_out_falls_back_to_the_next_backend() {
+    let base_url = start_mock_server(Received::default(), routes);
+    let chain = chain_of(json!([
+        { "type": "remote", "name": "primary", "remote_endpoint": format!("{}/slow", base_url),
+          "timeout_ms": 100 },
+        { "type": "constant", "value": [-1.0, 1.0] }
+    ]));
+
+    let started = std::time::Instant::now();
+    let (backend, predictions) = chain.predict(vec![1.0, 2.0, 3.0, 4.0], 2).await.unwrap();
+    assert!(started.elapsed() < Duration::from_millis(400));
+    assert_eq!(backend, "constant");
+    assert_eq!(predictions, vec![vec![-1.0, 1.0]; 2]);
+
+    // Without a timeout the slow answer is waited for
+    let chain = chain_of(json!([
+        { "type": "remote", "remote_endpoint": format!("{}/slow", base_url) },
+        { "type": "constant", "value": [0.0] }
+    ]));
+    let (backend, predictions) = chain.predict(vec![1.0, 2.0], 1).await.unwrap();
+    assert_eq!(backend, "remote");
+    assert_eq!(predictions, vec![vec![3.0]]);
+}
+
+#[actix::test]
+async fn every_backend_failing_reports_each_error() {
+    let base_url = start_mock_server(Received::default(), routes);
+    let chain = chain_of(json!([
+        { "type": "remote", "name": "primary", "remote_endpoint": format!("{}/fail", base_url) },
+        { "type": "remote", "name": "secondary", "remote_endpoint": format!("{}/slow", base_url),
+          "timeout_ms": 100 }
+    ]));
+
+    let error = chain.predict(vec![1.0], 1).await.err().unwrap();
+    assert!(error.starts_with("All backends failed"), "{}", error);
+    assert!(error.contains("primary: "), "{}", error);
+    assert!(
+        error.contains("secondary: timed out after 100ms"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn the_trace_records_the_backend_or_the_failure() {
+    let base_url = start_mock_server(Received::default(), routes);
+    let config = |backends: Value| {
+        json!({ "steps": [
+            { "name": "gen", "type": "DataGenerator", "inputs": [], "outputs": ["raw"],
+              "params": { "limit": 1 } },
+            { "name": "model", "type": "MLModel", "inputs": ["raw"], "outputs": ["scored"],
+              "params": { "backends": backends } },
+            { "name": "printer", "type": "Printer", "inputs": ["scored"], "outputs": [] }
+        ]})
+    };
+    let timeout = Duration::from_secs(10);
+
+    let pipeline = Pipeline::start(config(json!([
+        { "type": "remote", "remote_endpoint": format!("{}/fail", base_url) },
+        { "type": "constant", "name": "default", "value": [0.5] }
+    ])))
+    .unwrap();
+    let output = pipeline.process(vec![1.0, 2.0], timeout).unwrap();
+    assert!(output.trace.failure().is_none(), "{:?}", output.trace);
+    assert_eq!(output.data, vec![0.5]);
+    assert_eq!(output.trace.steps[0].backend.as_deref(), Some("default"));
+    pipeline.stop();
+
+    let pipeline = Pipeline::start(config(json!([
+        { "type": "remote", "remote_endpoint": format!("{}/fail", base_url) }
+    ])))
+    .unwrap();
+    let output = pipeline.process(vec![1.0, 2.0], timeout).unwrap();
+    let (step, error) = output.trace.failure().unwrap();
+    assert_eq!(step, "model");
+    assert!(
+        error.message.starts_with("All backends failed"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,135 @@
+// tests/feature_lookup.rs
+//
+// Loads keyed feature tables and looks entities up in them.
+
+use actor_poc::steps::feature_lookup::FeatureTable;
+use serde_json::json;
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Writes a table to the temp dir, returning its path.
+fn write_table(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("feature_lookup_{}_{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn csv_rows_are_found_by_key_and_missing_keys_get_defaults() {
+    let path = write_table(
+        "cards.csv",
+        "key,limit,risk\n7,1000,0.5\n-0,50,\n9,2000,0.1\n",
+    );
+    let table = FeatureTable::from_params(&json!({
+        "path": path,
+        "default": { "risk": 1.0 }
+    }))
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(table.features(), ["limit", "risk"]);
+    assert_eq!(table.len(), 3);
+    assert_eq!(table.lookup(7.0), (&[1000.0, 0.5][..], true));
+
+    let (zero, found) = table.lookup(0.0);
+    assert!(found && zero[0] == 50.0 && zero[1].is_nan());
+
+    let (missing, found) = table.lookup(3.0);
+    assert!(!found && missing[0].is_nan() && missing[1] == 1.0);
+}
+
+#[test]
+fn csv_tables_are_read_like_csv_files() {
+    // Quoted cells, another delimiter and a blank line
+    let path = write_table(
+        "merchants.csv",
+        "\"key\";\"name;city\";risk\n1;\"Shop; Ltd\";0.2\n\n2; ;0.7\n",
+    );
+    let table = FeatureTable::from_params(&json!({
+        "path": path,
+        "delimiter": ";",
+        "features": ["risk"]
+    }))
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(table.len(), 2);
+    assert_eq!(table.lookup(1.0), (&[0.2][..], true));
+    assert_eq!(table.lookup(2.0), (&[0.7][..], true));
+
+    assert!(FeatureTable::from_params(&json!({ "path": path, "delimiter": "::" })).is_err());
+}
+
+#[test]
+fn jsonl_tables_take_the_listed_features() {
+    let path = write_table(
+        "users.jsonl",
+        "{\"user\": 1, \"age\": 30, \"score\": 0.9}\n\n{\"user\": 2, \"age\": 41}\n",
+    );
+    let table = FeatureTable::from_params(&json!({
+        "path": path,
+        "table_key": "user",
+        "features": ["score", "age"],
+        "default": 0
+    }))
+    .unwrap();
+
+    assert_eq!(table.lookup(1.0), (&[0.9, 30.0][..], true));
+    assert!(table.lookup(2.0).0[0].is_nan());
+    assert_eq!(table.lookup(5.0), (&[0.0, 0.0][..], false));
+
+    // JSONL has no header to take the feature names from
+    let unlisted = FeatureTable::from_params(&json!({ "path": path, "table_key": "user" }));
+    std::fs::remove_file(&path).unwrap();
+    assert!(unlisted.is_err());
+}
+
+#[test]
+fn bad_tables_are_rejected() {
+    let path = write_table("bad.csv", "key,limit\n7,1000\n8,lots\n");
+    let bad_cell = FeatureTable::from_params(&json!({ "path": path }))
+        .err()
+        .unwrap();
+    assert!(bad_cell.contains("line 3"), "{}", bad_cell);
+
+    let no_column = FeatureTable::from_params(&json!({ "path": path, "features": ["risk"] }));
+    assert!(no_column.is_err());
+    let unknown_default = FeatureTable::from_params(&json!({
+        "path": path,
+        "default": { "risk": 0 }
+    }));
+    std::fs::remove_file(&path).unwrap();
+    assert!(unknown_default.is_err());
+
+    assert!(FeatureTable::from_params(&json!({ "path": "/nonexistent/table.csv" })).is_err());
+}
+
+#[test]
+fn changed_files_are_reloaded_and_bad_reloads_keep_the_old_rows() {
+    let path = write_table("reload.csv", "key,limit\n7,1000\n");
+    let mut table = FeatureTable::from_params(&json!({ "path": path })).unwrap();
+    assert!(!table.reload_if_changed().unwrap());
+
+    // Bump the modification time explicitly; back-to-back writes can share a timestamp
+    let touch = |seconds: u64| {
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(seconds))
+            .unwrap();
+    };
+
+    std::fs::write(&path, "key,limit\n7,1500\n8,20\n").unwrap();
+    touch(10);
+    assert!(table.reload_if_changed().unwrap());
+    assert_eq!(table.lookup(7.0), (&[1500.0][..], true));
+    assert_eq!(table.len(), 2);
+
+    std::fs::write(&path, "key,limit\n7,broken\n").unwrap();
+    touch(20);
+    assert!(table.reload_if_changed().is_err());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(table.lookup(8.0), (&[20.0][..], true));
+}
@@ -0,0 +1,36 @@
+// tests/model_inspector.rs
+//
+// Reads what `--inspect-model` prints from the ONNX files in data/.
+
+use actor_poc::model_inspector::{print_model_summary, read_opset_imports};
+
+const MODEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data/decision_tree.onnx");
+
+#[test]
+fn opsets_are_read_from_the_model_file() {
+    let opsets: Vec<(String, i64)> = read_opset_imports(MODEL)
+        .unwrap()
+        .into_iter()
+        .map(|opset| (opset.domain, opset.version))
+        .collect();
+    // The empty domain is the default operator set
+    assert_eq!(
+        opsets,
+        [("ai.onnx.ml".to_string(), 1), ("ai.onnx".to_string(), 9)]
+    );
+
+    let error = read_opset_imports(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("ONNX file"), "{}", error);
+}
+
+#[test]
+fn the_summary_needs_an_onnx_runtime() {
+    let summary = print_model_summary(MODEL);
+    if cfg!(any(feature = "onnxruntime", feature = "tract")) {
+        summary.unwrap();
+    } else {
+        assert!(summary.unwrap_err().to_string().contains("feature"));
+    }
+}
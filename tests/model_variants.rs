@@ -0,0 +1,147 @@
+// tests/model_variants.rs
+//
+// Splits traffic between the variants of an MLModel and copies it to a shadow variant.
+
+mod common;
+
+use actix_web::{web, HttpResponse};
+use actor_poc::pipeline::Pipeline;
+use actor_poc::steps::model_variants::{parse_shadow, parse_variants, pick_variant};
+use common::start_mock_server;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+type Received = Arc<Mutex<Vec<Value>>>;
+
+/// Writes a built-in linear model adding `intercept` to its one feature, returning its path.
+fn write_model(name: &str, intercept: f64) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "model_variants_{}_{}.json",
+        name,
+        std::process::id()
+    ));
+    let model = json!({ "type": "linear", "coefficients": [1.0], "intercept": intercept });
+    std::fs::write(&path, model.to_string()).unwrap();
+    path
+}
+
+/// A variant running the built-in model at `path`.
+fn variant(name: &str, percent: f64, path: &Path) -> Value {
+    json!({ "name": name, "percent": percent, "params": { "builtin_model_path": path } })
+}
+
+/// The mock server's routes: `/record` records the features it was sent and answers with them.
+fn routes(config: &mut web::ServiceConfig) {
+    config.route(
+        "/record",
+        web::post().to(
+            |body: web::Json<Value>, received: web::Data<Received>| async move {
+                received.lock().unwrap().push(body["features"].clone());
+                HttpResponse::Ok().json(json!({ "processed_features": body["features"] }))
+            },
+        ),
+    );
+}
+
+#[test]
+fn the_same_id_always_picks_the_same_variant() {
+    let percents = [50.0, 30.0, 20.0];
+    for id in 0..1_000 {
+        let picked = pick_variant(id, &percents);
+        assert!(picked < percents.len());
+        assert_eq!(pick_variant(id, &percents), picked);
+    }
+    // A variant without traffic is never picked
+    assert!((0..1_000).all(|id| pick_variant(id, &[100.0, 0.0]) == 0));
+}
+
+#[test]
+fn traffic_splits_roughly_by_the_percentages() {
+    let percents = [70.0, 20.0, 10.0];
+    let mut counts = [0usize; 3];
+    for id in 0..100_000 {
+        counts[pick_variant(id, &percents)] += 1;
+    }
+    for (count, percent) in counts.iter().zip(percents) {
+        let share = *count as f64 / 1_000.0;
+        assert!((share - percent).abs() < 1.0, "{:?}", counts);
+    }
+}
+
+#[test]
+fn variant_params_are_merged_over_the_step_params() {
+    let params = json!({
+        "feature_width": 4,
+        "variants": [
+            { "name": "a", "percent": 60, "params": { "remote_endpoint": "http://a" } },
+            { "name": "b", "percent": 40, "params": { "feature_width": 2 } }
+        ],
+        "shadow": { "name": "s" }
+    });
+    let variants = parse_variants(&params).unwrap();
+    assert_eq!(variants[0].params["feature_width"], 4);
+    assert_eq!(variants[0].params["remote_endpoint"], "http://a");
+    assert_eq!(variants[1].params["feature_width"], 2);
+    assert!(variants[1].params.get("variants").is_none());
+    let shadow = parse_shadow(&params).unwrap().unwrap();
+    assert_eq!(shadow.name, "s");
+    assert!(shadow.params.get("shadow").is_none());
+
+    let uneven = json!({ "variants": [{ "name": "a", "percent": 60 }] });
+    let error = parse_variants(&uneven).err().unwrap();
+    assert!(error.contains("add up to 60"), "{}", error);
+}
+
+#[test]
+fn shadow_predictions_never_reach_the_next_step() {
+    let received = Received::default();
+    let endpoint = format!("{}/record", start_mock_server(received.clone(), routes));
+    let models = [
+        write_model("a", 100.0),
+        write_model("b", 200.0),
+        write_model("shadow", 300.0),
+    ];
+    let pipeline = Pipeline::start(json!({ "steps": [
+        { "name": "gen", "type": "DataGenerator", "inputs": [], "outputs": ["raw"],
+          "params": { "limit": 1 } },
+        { "name": "model", "type": "MLModel", "inputs": ["raw"], "outputs": ["scored"],
+          "params": {
+              "variants": [variant("a", 50.0, &models[0]), variant("b", 50.0, &models[1])],
+              "shadow": { "name": "shadow", "params": { "builtin_model_path": models[2] } }
+          } },
+        // Records every message that gets past the model
+        { "name": "next", "type": "MLModel", "inputs": ["scored"], "outputs": ["out"],
+          "params": { "remote_endpoint": endpoint } },
+        { "name": "printer", "type": "Printer", "inputs": ["out"], "outputs": [] }
+    ]}))
+    .unwrap();
+    for model in &models {
+        std::fs::remove_file(model).unwrap();
+    }
+
+    let receivers: Vec<_> = (0..20).map(|i| pipeline.submit(vec![i as f64])).collect();
+    for (i, receiver) in receivers.into_iter().enumerate() {
+        let output = pipeline.wait(receiver, TIMEOUT).unwrap();
+        assert!(output.trace.failure().is_none(), "{:?}", output.trace);
+        let (name, intercept) = match pick_variant(output.id, &[50.0, 50.0]) {
+            0 => ("a", 100.0),
+            _ => ("b", 200.0),
+        };
+        assert_eq!(output.trace.steps[0].variant.as_deref(), Some(name));
+        assert_eq!(output.data, vec![i as f64 + intercept]);
+    }
+
+    // Give a late shadow prediction the chance to show up
+    thread::sleep(Duration::from_millis(200));
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 20);
+    for features in received.iter() {
+        let value = features[0].as_f64().unwrap();
+        assert!(value < 300.0, "{:?}", received);
+    }
+}
@@ -0,0 +1,189 @@
+// tests/onnx_backends.rs
+//
+// Runs `data/decision_tree.onnx` through each ONNX backend compiled in and checks they all give
+// the same labels and probabilities, and that tract labels a binary classifier like onnxruntime
+// and compiles a dynamic-width model for each width it is fed.
+#![cfg(any(feature = "onnxruntime", feature = "tract"))]
+
+use serde_json::{json, Value};
+
+const MODEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data/decision_tree.onnx");
+
+/// One row for each leaf of the tree.
+const ROWS: [[f64; 4]; 5] = [
+    [0.0, 0.0, 0.0, -1.0],
+    [0.0, -1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0, 1.0],
+    [3.0, 0.0, 0.0, 1.0],
+];
+
+/// The label followed by both class probabilities, per row.
+const EXPECTED: [[f64; 3]; 5] = [
+    [0.0, 1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0],
+];
+
+/// Rows on, around and between every threshold of the tree.
+fn grid() -> Vec<f64> {
+    let f0 = [-1.0, 0.0, 2.3465967, 2.35, 3.0];
+    let f1 = [-1.0, 0.0, 0.016586296, 0.02, 1.0];
+    let f3 = [-1.0, -0.30099997, -0.3, 0.0, 0.2028601, 0.21, 1.0];
+    let mut rows = Vec::new();
+    for a in f0 {
+        for b in f1 {
+            for d in f3 {
+                rows.extend([a, b, 0.5, d]);
+            }
+        }
+    }
+    rows
+}
+
+fn params() -> Value {
+    json!({ "outputs": [{ "name": "label" }, { "name": "probabilities" }] })
+}
+
+fn check(feature_width: Option<usize>, predictions: Vec<Vec<f64>>) {
+    assert_eq!(feature_width, Some(4));
+    assert_eq!(predictions.len(), ROWS.len());
+    for (row, expected) in predictions.iter().zip(EXPECTED) {
+        assert_eq!(row.as_slice(), expected.as_slice());
+    }
+}
+
+/// Checks each label is the class with the higher probability, as onnxruntime picks it.
+fn check_labels(predictions: &[Vec<f64>]) {
+    for row in predictions {
+        let [label, first, second] = row[..] else {
+            panic!("{:?}", row);
+        };
+        assert_eq!(label, if second > first { 1.0 } else { 0.0 }, "{:?}", row);
+    }
+    assert!(predictions.iter().any(|row| row[0] == 1.0));
+}
+
+/// Picks feature 7, past the end of the rows, with no `feature_width` to catch it at load.
+fn narrow_params() -> Value {
+    json!({
+        "inputs": [{ "name": "input", "indices": [0, 1, 2, 7] }],
+        "outputs": [{ "name": "label" }]
+    })
+}
+
+/// The prediction fails, so the message goes to the dead letters, instead of the actor panicking.
+fn check_narrow(error: String) {
+    assert!(
+        error.contains("feature index 7 but rows only have 4 features"),
+        "{}",
+        error
+    );
+}
+
+#[cfg(feature = "onnxruntime")]
+#[test]
+fn onnxruntime_backend() {
+    use actor_poc::steps::onnx::OnnxModel;
+
+    let model = OnnxModel::load(MODEL, &params(), None).unwrap();
+    check(
+        model.feature_width,
+        model.predict(&ROWS.concat(), ROWS.len()).unwrap(),
+    );
+
+    let rows = grid();
+    check_labels(&model.predict(&rows, rows.len() / 4).unwrap());
+
+    let narrow = OnnxModel::load(MODEL, &narrow_params(), None).unwrap();
+    check_narrow(narrow.predict(&ROWS[0], 1).unwrap_err());
+}
+
+#[cfg(feature = "tract")]
+#[test]
+fn tract_backend() {
+    use actor_poc::steps::onnx_tract::OnnxModel;
+
+    let model = OnnxModel::load(MODEL, &params(), None).unwrap();
+    check(
+        model.feature_width,
+        model.predict(&ROWS.concat(), ROWS.len()).unwrap(),
+    );
+
+    // Rows run one at a time give the same answers as a batch
+    let single = model.predict(&ROWS[1], 1).unwrap();
+    assert_eq!(single[0].as_slice(), EXPECTED[1].as_slice());
+
+    let narrow = OnnxModel::load(MODEL, &narrow_params(), None).unwrap();
+    check_narrow(narrow.predict(&ROWS[0], 1).unwrap_err());
+}
+
+#[cfg(feature = "tract")]
+#[test]
+fn tract_plans_for_a_dynamic_width_are_not_shared_across_widths() {
+    use actor_poc::steps::onnx_tract::OnnxModel;
+
+    // The same tree, taking any number of features
+    let model = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/data/decision_tree_dynamic.onnx"
+    );
+    let wide = OnnxModel::load(model, &params(), Some(5)).unwrap();
+    let exact = OnnxModel::load(model, &params(), Some(4)).unwrap();
+    let unset = OnnxModel::load(model, &params(), None).unwrap();
+
+    let padded: Vec<f64> = ROWS
+        .iter()
+        .flat_map(|row| row.iter().chain([&9.0]))
+        .copied()
+        .collect();
+    for (model, data) in [
+        (wide, padded),
+        (exact, ROWS.concat()),
+        (unset, ROWS.concat()),
+    ] {
+        let predictions = model.predict(&data, ROWS.len()).unwrap();
+        for (row, expected) in predictions.iter().zip(EXPECTED) {
+            assert_eq!(row.as_slice(), expected.as_slice());
+        }
+    }
+}
+
+#[cfg(all(feature = "onnxruntime", feature = "tract"))]
+#[test]
+fn backends_agree() {
+    use actor_poc::steps::{onnx, onnx_tract};
+
+    let ort = onnx::OnnxModel::load(MODEL, &params(), None).unwrap();
+    let tract = onnx_tract::OnnxModel::load(MODEL, &params(), None).unwrap();
+    let rows = grid();
+    let count = rows.len() / 4;
+    assert_eq!(
+        tract.predict(&rows, count).unwrap(),
+        ort.predict(&rows, count).unwrap()
+    );
+}
+
+#[cfg(feature = "tract")]
+#[test]
+fn tract_labels_a_binary_classifier_by_the_likelier_class() {
+    use actor_poc::steps::onnx_tract::OnnxModel;
+
+    // Every leaf of the tree weighs the first class only, the layout tract labels wrongly
+    let rows = grid();
+    let count = rows.len() / 4;
+    let model = OnnxModel::load(MODEL, &params(), None).unwrap();
+    let predictions = model.predict(&rows, count).unwrap();
+    check_labels(&predictions);
+
+    // The label is fixed up from the probabilities even when they aren't selected
+    let label_only = json!({ "outputs": [{ "name": "label" }] });
+    let model = OnnxModel::load(MODEL, &label_only, None).unwrap();
+    let labels = model.predict(&rows, count).unwrap();
+    assert!(labels
+        .iter()
+        .zip(&predictions)
+        .all(|(label, row)| label[..] == row[..1]));
+}
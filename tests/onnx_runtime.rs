@@ -0,0 +1,145 @@
+// tests/onnx_runtime.rs
+//
+// Runs ONNX models through onnxruntime: the session the MLModel actors share, pooled batches and
+// the conversion of int64, string and map outputs to numbers.
+// With both ONNX
+// features on MLModel uses tract instead, so these only run without it.
+#![cfg(all(feature = "onnxruntime", not(feature = "tract")))]
+
+use actix::Actor;
+use actor_poc::config::Config;
+use actor_poc::coordinator::Coordinator;
+use actor_poc::pipeline::Pipeline;
+use actor_poc::step::Step;
+use actor_poc::steps::ml_model::MLModel;
+use actor_poc::steps::onnx::{load_session, OnnxModel};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+const MODEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data/decision_tree.onnx");
+// The same tree with "no"/"yes" labels, and with its probabilities also zipped into maps
+const STRINGS_MODEL: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/data/decision_tree_strings.onnx"
+);
+const ZIPMAP_MODEL: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/data/decision_tree_zipmap.onnx"
+);
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One row for each leaf of the tree.
+const ROWS: [[f64; 4]; 5] = [
+    [0.0, 0.0, 0.0, -1.0],
+    [0.0, -1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0, 1.0],
+    [3.0, 0.0, 0.0, 1.0],
+];
+
+/// The label followed by both class probabilities, per row.
+const EXPECTED: [[f64; 3]; 5] = [
+    [0.0, 1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0],
+];
+
+#[actix::test]
+async fn actors_for_the_same_model_share_one_session() {
+    // A copy of its own, so no other test holds on to the session
+    let path = std::env::temp_dir().join(format!("onnx_runtime_{}.onnx", std::process::id()));
+    std::fs::copy(MODEL, &path).unwrap();
+    let path = path.to_str().unwrap();
+
+    let config: Config = serde_json::from_value(json!({ "steps": [] })).unwrap();
+    let coordinator = Coordinator::new(config, None).start();
+    let _actors: Vec<_> = ["first", "second"]
+        .into_iter()
+        .map(|name| {
+            let mut model = MLModel::new_from_params(
+                name.to_string(),
+                format!("{}_output", name),
+                coordinator.clone(),
+                json!({ "onnx_model_path": path }),
+            );
+            model.load_model().unwrap();
+            model.start()
+        })
+        .collect();
+
+    // Held by the cache, both actors and this handle
+    let session = load_session(path).unwrap();
+    assert_eq!(Arc::strong_count(&session), 4);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn pooled_rows_come_back_under_the_ids_they_were_pooled_with() {
+    let pipeline = Pipeline::start(json!({ "steps": [
+        { "name": "gen", "type": "DataGenerator", "inputs": [], "outputs": ["raw"],
+          "params": { "limit": 1 } },
+        { "name": "pool", "type": "BatchPooler", "inputs": ["raw"], "outputs": ["pooled"],
+          "params": { "window_size": ROWS.len() } },
+        { "name": "model", "type": "MLModel", "inputs": ["pooled"], "outputs": ["scored"],
+          "params": {
+              "onnx_model_path": MODEL,
+              "outputs": [{ "name": "label" }, { "name": "probabilities" }],
+              "split_batch": true
+          } },
+        { "name": "printer", "type": "Printer", "inputs": ["scored"], "outputs": [] }
+    ]}))
+    .unwrap();
+
+    // Rows of both classes, out of order, go through the model as one batch
+    let order = [3, 0, 4, 1, 2];
+    let receivers: Vec<_> = order
+        .iter()
+        .map(|&row| pipeline.submit(ROWS[row].to_vec()))
+        .collect();
+    for (row, receiver) in order.into_iter().zip(receivers) {
+        let output = pipeline.wait(receiver, TIMEOUT).unwrap();
+        assert!(output.trace.failure().is_none(), "{:?}", output.trace);
+        assert_eq!(output.data, EXPECTED[row]);
+    }
+}
+
+#[test]
+fn int64_string_and_map_outputs_become_numbers() {
+    // int64 labels and maps keyed by int64 (in key order) or by string (in `classes` order)
+    let model = OnnxModel::load(
+        ZIPMAP_MODEL,
+        &json!({ "outputs": [
+            { "name": "label" },
+            { "name": "probability_map" },
+            { "name": "class_map", "classes": ["yes", "no"] }
+        ] }),
+        None,
+    )
+    .unwrap();
+    let predictions = model.predict(&ROWS.concat(), ROWS.len()).unwrap();
+    for (row, expected) in predictions.iter().zip(EXPECTED) {
+        let [label, no, yes] = expected;
+        assert_eq!(row.as_slice(), [label, no, yes, yes, no]);
+    }
+
+    // String labels become their position in `classes`
+    let model = OnnxModel::load(
+        STRINGS_MODEL,
+        &json!({ "outputs": [{ "name": "label", "classes": ["no", "yes"] }] }),
+        None,
+    )
+    .unwrap();
+    let predictions = model.predict(&ROWS.concat(), ROWS.len()).unwrap();
+    for (row, expected) in predictions.iter().zip(EXPECTED) {
+        assert_eq!(row.as_slice(), [expected[0]]);
+    }
+
+    // and can't be turned into numbers without them
+    let model = OnnxModel::load(STRINGS_MODEL, &json!({}), None).unwrap();
+    let error = model.predict(&ROWS[0], 1).unwrap_err();
+    assert!(error.contains("'no' is not numeric"), "{}", error);
+}
@@ -0,0 +1,131 @@
+// tests/pipeline.rs
+//
+// Runs DAGs in-process through `Pipeline`, without the HTTP server.
+
+use actor_poc::pipeline::Pipeline;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A CLI-style config: a generator, a clipping step, an optional extra step and a printer.
+fn config(extra: Option<Value>) -> Value {
+    let mut steps = vec![
+        json!({ "name": "gen", "type": "DataGenerator", "inputs": [], "outputs": ["raw"],
+                "params": { "limit": 1 } }),
+        json!({ "name": "clip", "type": "FeatureProcessor", "inputs": ["raw"], "outputs": ["clipped"],
+                "params": { "transforms": [{ "type": "clip", "min": -1.0, "max": 1.0 }] } }),
+    ];
+    let last = match extra {
+        Some(step) => {
+            steps.push(step);
+            "out"
+        }
+        None => "clipped",
+    };
+    steps.push(json!({ "name": "printer", "type": "Printer", "inputs": [last], "outputs": [] }));
+    json!({ "steps": steps })
+}
+
+#[test]
+fn vectors_fed_in_come_out_of_the_final_step_with_their_trace() {
+    let pipeline = Pipeline::start(config(None)).unwrap();
+
+    let output = pipeline.process(vec![-3.0, 0.5, 2.0], TIMEOUT).unwrap();
+    assert_eq!(output.data, vec![-1.0, 0.5, 1.0]);
+    let steps: Vec<&str> = output
+        .trace
+        .steps
+        .iter()
+        .map(|s| s.node_id.as_str())
+        .collect();
+    assert_eq!(steps, ["clip"]);
+
+    // Several in flight at once each get their own answer
+    let receivers: Vec<_> = (0..20)
+        .map(|i| pipeline.submit(vec![i as f64 / 10.0]))
+        .collect();
+    for (i, receiver) in receivers.into_iter().enumerate() {
+        let output = pipeline.wait(receiver, TIMEOUT).unwrap();
+        assert_eq!(output.data, vec![(i as f64 / 10.0).min(1.0)]);
+    }
+
+    pipeline.stop();
+    assert!(pipeline.process(vec![0.0], TIMEOUT).is_err());
+}
+
+#[test]
+fn failed_messages_come_out_with_the_failure_in_their_trace() {
+    let script = std::env::temp_dir().join(format!("pipeline_{}.py", std::process::id()));
+    std::fs::write(
+        &script,
+        "def process(features):\n    raise ValueError('no thanks')\n",
+    )
+    .unwrap();
+    let pipeline = Pipeline::start(config(Some(json!({
+        "name": "py", "type": "PyFeatureProcessor", "inputs": ["clipped"], "outputs": ["out"],
+        "params": { "python_script": script }
+    }))))
+    .unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    let output = pipeline.process(vec![0.0], TIMEOUT).unwrap();
+    let (step, error) = output.trace.failure().unwrap();
+    assert_eq!(step, "py");
+    assert!(error.message.contains("ValueError: no thanks"), "{}", error);
+}
+
+#[test]
+fn a_message_given_up_on_is_no_longer_tracked() {
+    // A worker process, so the actors keep running while the script sleeps
+    let script = std::env::temp_dir().join(format!("pipeline_slow_{}.py", std::process::id()));
+    std::fs::write(
+        &script,
+        "import time\n\ndef process(features):\n    time.sleep(0.5)\n    return features\n",
+    )
+    .unwrap();
+    let pipeline = Pipeline::start(config(Some(json!({
+        "name": "py", "type": "PyFeatureProcessor", "inputs": ["clipped"], "outputs": ["out"],
+        "params": { "python_script": script, "workers": 1 }
+    }))))
+    .unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    let error = pipeline
+        .process(vec![0.0], Duration::from_millis(100))
+        .unwrap_err();
+    assert!(error.contains("No output after"), "{}", error);
+    assert_eq!(pipeline.in_flight(), 0);
+}
+
+#[test]
+fn invalid_configs_and_steps_that_fail_to_load_are_reported() {
+    let errors = Pipeline::start(json!({ "steps": [] })).err().unwrap();
+    assert!(
+        errors.iter().any(|e| e.contains("exactly one sink")),
+        "{:?}",
+        errors
+    );
+
+    let errors = Pipeline::start(config(Some(json!({
+        "name": "py", "type": "PyFeatureProcessor", "inputs": ["clipped"], "outputs": ["out"],
+        "params": { "python_script": "/nonexistent/script.py" }
+    }))))
+    .err()
+    .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0].starts_with("PyFeatureProcessor 'py'"),
+        "{:?}",
+        errors
+    );
+
+    let errors = Pipeline::start(config(Some(json!({
+        "name": "join", "type": "StepJoinPoint", "inputs": ["clipped"], "outputs": ["out"],
+        "params": { "columns": "amount" }
+    }))))
+    .err()
+    .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("Invalid 'columns'"), "{:?}", errors);
+}
@@ -0,0 +1,142 @@
+// tests/py_script.rs
+//
+// Imports Python scripts once and calls their functions per message.
+
+use actor_poc::steps::py_feature_processor::PyScript;
+use std::path::PathBuf;
+
+/// Writes a script to the temp dir, returning its path.
+fn write_script(name: &str, code: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("py_script_{}_{}.py", std::process::id(), name));
+    std::fs::write(&path, code).unwrap();
+    path
+}
+
+#[test]
+fn module_state_survives_between_calls_and_prints_are_captured() {
+    let path = write_script(
+        "counter",
+        "print('importing')\n\
+         calls = 0\n\
+         \n\
+         def process(features):\n\
+         \x20   global calls\n\
+         \x20   calls += 1\n\
+         \x20   print('call', calls)\n\
+         \x20   return [x + calls for x in features]\n",
+    );
+    let script = PyScript::load(path.to_str().unwrap(), "process", false).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        script.call(&[1.0, 2.0], 0).unwrap(),
+        (vec![2.0, 3.0], "call 1\n".to_string())
+    );
+    assert_eq!(
+        script.call(&[1.0], 0).unwrap(),
+        (vec![3.0], "call 2\n".to_string())
+    );
+}
+
+#[test]
+fn scripts_without_the_function_or_that_fail_are_errors() {
+    let path = write_script("other", "def transform(features):\n    return features\n");
+    let missing = PyScript::load(path.to_str().unwrap(), "process", false)
+        .err()
+        .unwrap();
+    assert!(missing.contains("no function 'process'"), "{}", missing);
+
+    let script = PyScript::load(path.to_str().unwrap(), "transform", false).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(script.call(&[4.0], 0).unwrap().0, vec![4.0]);
+
+    let path = write_script("broken", "print('loading')\nimport no_such_module\n");
+    let broken = PyScript::load(path.to_str().unwrap(), "process", false)
+        .err()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(broken.contains("ModuleNotFoundError"), "{}", broken);
+    assert!(broken.contains("line 2"), "{}", broken);
+    assert!(broken.contains("loading"), "{}", broken);
+
+    assert!(PyScript::load("/nonexistent/script.py", "process", false).is_err());
+}
+
+#[test]
+fn failures_carry_the_traceback_and_output() {
+    let path = write_script(
+        "raises",
+        "import sys\n\
+         \n\
+         def divide(x):\n\
+         \x20   return x / 0\n\
+         \n\
+         def process(features):\n\
+         \x20   print('before')\n\
+         \x20   print('warning', file=sys.stderr)\n\
+         \x20   return [divide(x) for x in features]\n\
+         \n\
+         def no_output(features):\n\
+         \x20   print('nothing to return')\n",
+    );
+    let script = PyScript::load(path.to_str().unwrap(), "process", false).unwrap();
+    let error = script.call(&[1.0], 0).unwrap_err();
+    assert!(
+        error.message.contains("ZeroDivisionError"),
+        "{}",
+        error.message
+    );
+    let traceback = error.traceback.unwrap();
+    assert!(traceback.contains("in divide"), "{}", traceback);
+    assert!(traceback.ends_with("ZeroDivisionError: float division by zero"));
+    assert_eq!(error.output.as_deref(), Some("before\nwarning\n"));
+
+    let script = PyScript::load(path.to_str().unwrap(), "no_output", false).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let error = script.call(&[1.0], 0).unwrap_err();
+    assert!(error.message.contains("array or list of numbers"));
+    assert_eq!(error.traceback, None);
+    assert_eq!(error.output.as_deref(), Some("nothing to return\n"));
+}
+
+#[test]
+fn features_are_lent_as_buffers_and_arrays_come_back_checked() {
+    // Stands in for NumPy, which views the same buffer
+    let path = write_script(
+        "buffers",
+        "import array, sys, types\n\
+         fake = types.ModuleType('numpy')\n\
+         fake.asarray = memoryview\n\
+         sys.modules['numpy'] = fake\n\
+         \n\
+         def process(features):\n\
+         \x20   print(features.readonly, features.format, features.shape)\n\
+         \x20   doubled = array.array('d', [x * 2 for x in features.cast('B').cast('d')])\n\
+         \x20   return memoryview(doubled).cast('B').cast('d', features.shape)\n\
+         \n\
+         def flat(features):\n\
+         \x20   return array.array('d', [sum(features.cast('B').cast('d'))])\n\
+         \n\
+         def too_many_rows(features):\n\
+         \x20   return memoryview(array.array('d', [0.0] * 3)).cast('B').cast('d', [3, 1])\n\
+         \n\
+         def words(features):\n\
+         \x20   return ['a', 'b']\n",
+    );
+    let load = |function| PyScript::load(path.to_str().unwrap(), function, true).unwrap();
+
+    let (single, printed) = load("process").call(&[1.0, 2.0, 3.0], 0).unwrap();
+    assert_eq!(single, vec![2.0, 4.0, 6.0]);
+    assert_eq!(printed, "True d (3,)\n");
+
+    let (batch, printed) = load("process").call(&[1.0, 2.0, 3.0, 4.0], 2).unwrap();
+    assert_eq!(batch, vec![2.0, 4.0, 6.0, 8.0]);
+    assert_eq!(printed, "True d (2, 2)\n");
+
+    assert_eq!(load("flat").call(&[1.0, 2.0], 2).unwrap().0, vec![3.0]);
+
+    let rows = load("too_many_rows").call(&[1.0, 2.0], 2).unwrap_err();
+    assert!(rows.message.contains("3 rows for 2 input rows"), "{}", rows);
+    assert!(load("words").call(&[1.0], 0).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
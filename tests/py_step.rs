@@ -0,0 +1,155 @@
+// tests/py_step.rs
+//
+// Runs step types written as Python classes.
+
+use actor_poc::config::PythonStepType;
+use actor_poc::steps::py_step::PyStepObject;
+use serde_json::json;
+use std::path::PathBuf;
+
+/// Writes a script to the temp dir, returning its step type.
+fn step_type(name: &str, class: &str, code: &str) -> PythonStepType {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("py_step_{}_{}.py", std::process::id(), name));
+    std::fs::write(&path, code).unwrap();
+    PythonStepType {
+        python_script: path.to_str().unwrap().to_string(),
+        class: class.to_string(),
+        numpy: false,
+    }
+}
+
+#[test]
+fn classes_keep_state_and_run_their_hooks() {
+    let log = std::env::temp_dir().join(format!("py_step_{}_hooks.log", std::process::id()));
+    let step = step_type(
+        "scaler",
+        "Scaler",
+        "class Scaler:\n\
+         \x20   def __init__(self, params):\n\
+         \x20       self.factor = params['factor']\n\
+         \x20       self.log = params['log']\n\
+         \x20       self.seen = 0\n\
+         \n\
+         \x20   def start(self):\n\
+         \x20       print('starting')\n\
+         \n\
+         \x20   def process(self, features):\n\
+         \x20       self.seen += 1\n\
+         \x20       print('seen', self.seen)\n\
+         \x20       return [x * self.factor for x in features]\n\
+         \n\
+         \x20   def process_batch(self, rows):\n\
+         \x20       return [[sum(row)] for row in rows]\n\
+         \n\
+         \x20   def stop(self):\n\
+         \x20       open(self.log, 'w').write(str(self.seen))\n",
+    );
+    let mut object =
+        PyStepObject::load(&step, &json!({ "factor": 2, "log": log.to_str().unwrap() })).unwrap();
+    std::fs::remove_file(&step.python_script).unwrap();
+
+    assert_eq!(
+        object.process(&[1.0, 2.0], 0).unwrap(),
+        (vec![2.0, 4.0], "seen 1\n".to_string())
+    );
+    assert_eq!(
+        object.process(&[3.0], 0).unwrap(),
+        (vec![6.0], "seen 2\n".to_string())
+    );
+    // Pooled batches go to `process_batch`, one list per row
+    assert_eq!(
+        object.process(&[1.0, 2.0, 3.0, 4.0], 2).unwrap().0,
+        vec![3.0, 7.0]
+    );
+
+    object.stop().unwrap();
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "2");
+    std::fs::remove_file(&log).unwrap();
+}
+
+#[test]
+fn a_missing_method_is_covered_by_the_other() {
+    let rows_only = step_type(
+        "rows_only",
+        "Negate",
+        "class Negate:\n\
+         \x20   def __init__(self, params):\n\
+         \x20       pass\n\
+         \n\
+         \x20   def process(self, features):\n\
+         \x20       return [-x for x in features]\n",
+    );
+    let object = PyStepObject::load(&rows_only, &json!(null)).unwrap();
+    std::fs::remove_file(&rows_only.python_script).unwrap();
+    assert_eq!(
+        object.process(&[1.0, 2.0, 3.0, 4.0], 2).unwrap().0,
+        vec![-1.0, -2.0, -3.0, -4.0]
+    );
+
+    let batch_only = step_type(
+        "batch_only",
+        "Total",
+        "class Total:\n\
+         \x20   def __init__(self, params):\n\
+         \x20       pass\n\
+         \n\
+         \x20   def process_batch(self, rows):\n\
+         \x20       return [[sum(row), len(rows)] for row in rows]\n",
+    );
+    let object = PyStepObject::load(&batch_only, &json!({})).unwrap();
+    std::fs::remove_file(&batch_only.python_script).unwrap();
+    assert_eq!(object.process(&[1.0, 2.0], 0).unwrap().0, vec![3.0, 1.0]);
+}
+
+#[test]
+fn bad_classes_and_failing_calls_are_reported() {
+    let broken = step_type(
+        "broken",
+        "Broken",
+        "class Broken:\n\
+         \x20   def __init__(self, params):\n\
+         \x20       self.model = params['model']\n\
+         \n\
+         \x20   def process(self, features):\n\
+         \x20       return [1 / x for x in features]\n\
+         \n\
+         \x20   def process_batch(self, rows):\n\
+         \x20       return [[0.0]]\n\
+         \n\
+         class Empty:\n\
+         \x20   pass\n",
+    );
+
+    let missing_param = PyStepObject::load(&broken, &json!({})).err().unwrap();
+    assert!(missing_param.contains("KeyError"), "{}", missing_param);
+
+    let empty = PythonStepType {
+        class: "Empty".to_string(),
+        ..broken.clone()
+    };
+    assert!(PyStepObject::load(&empty, &json!({})).is_err());
+    let unknown = PythonStepType {
+        class: "Unknown".to_string(),
+        ..broken.clone()
+    };
+    assert!(PyStepObject::load(&unknown, &json!({})).is_err());
+
+    let object = PyStepObject::load(&broken, &json!({ "model": "m" })).unwrap();
+    std::fs::remove_file(&broken.python_script).unwrap();
+
+    let error = object.process(&[0.0], 0).err().unwrap();
+    assert!(
+        error.message.contains("'Broken.process' failed"),
+        "{}",
+        error
+    );
+    assert!(error.traceback.unwrap().contains("ZeroDivisionError"));
+
+    let wrong_rows = object.process(&[1.0, 2.0], 2).err().unwrap();
+    assert!(
+        wrong_rows.message.contains("1 rows for 2"),
+        "{}",
+        wrong_rows
+    );
+}
@@ -0,0 +1,143 @@
+// tests/py_worker.rs
+//
+// Runs Python scripts in a pool of worker processes.
+
+use actor_poc::steps::py_worker::{PyWorkerPool, WorkerCommand};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Writes a script to the temp dir, returning its path.
+fn write_script(name: &str, code: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("py_worker_{}_{}.py", std::process::id(), name));
+    std::fs::write(&path, code).unwrap();
+    path
+}
+
+fn start(
+    path: &Path,
+    function: &str,
+    size: usize,
+    timeout_ms: Option<u64>,
+    memory_limit_mb: Option<u64>,
+) -> Result<PyWorkerPool, String> {
+    let command = WorkerCommand {
+        python: "python3".to_string(),
+        script: path.to_str().unwrap().to_string(),
+        function: function.to_string(),
+        numpy: false,
+        memory_limit_mb,
+        env: vec![],
+    };
+    PyWorkerPool::start(command, size, timeout_ms.map(Duration::from_millis))
+}
+
+const SCRIPT: &str = "import os, sys, time\n\
+                      \n\
+                      def process(features):\n\
+                      \x20   print('got', len(features))\n\
+                      \x20   print('to stderr', file=sys.stderr)\n\
+                      \x20   return [x * 2 for x in features]\n\
+                      \n\
+                      def raises(features):\n\
+                      \x20   print('before')\n\
+                      \x20   raise ValueError('bad feature')\n\
+                      \n\
+                      def crashes(features):\n\
+                      \x20   if features[0] < 0:\n\
+                      \x20       os._exit(3)\n\
+                      \x20   return features\n\
+                      \n\
+                      def sleeps(features):\n\
+                      \x20   time.sleep(features[0])\n\
+                      \x20   return features\n\
+                      \n\
+                      def words(features):\n\
+                      \x20   return ['a']\n\
+                      \n\
+                      def allocates(features):\n\
+                      \x20   return [len(bytearray(int(features[0]) * 1024 * 1024))]\n";
+
+#[actix::test]
+async fn workers_answer_with_output_and_errors() {
+    let path = write_script("answers", SCRIPT);
+    let pool = start(&path, "process", 2, None, None).unwrap();
+
+    let (output, printed) = pool.call(vec![1.0, f64::NAN, -3.5], 0).await.unwrap();
+    assert_eq!(output[0], 2.0);
+    assert!(output[1].is_nan());
+    assert_eq!(output[2], -7.0);
+    assert_eq!(printed, "got 3\nto stderr\n");
+
+    // More calls than workers wait for one to come free
+    let calls = (0..6).map(|i| pool.call(vec![i as f64], 0));
+    let answers = futures::future::join_all(calls).await;
+    for (i, answer) in answers.into_iter().enumerate() {
+        assert_eq!(answer.unwrap().0, vec![i as f64 * 2.0]);
+    }
+
+    let raises = start(&path, "raises", 1, None, None).unwrap();
+    let error = raises.call(vec![1.0], 0).await.unwrap_err();
+    assert_eq!(
+        error.message,
+        "Python function failed: ValueError: bad feature"
+    );
+    assert!(error.traceback.unwrap().contains("in raises"));
+    assert_eq!(error.output.as_deref(), Some("before\n"));
+
+    let words = start(&path, "words", 1, None, None).unwrap();
+    let error = words.call(vec![1.0], 0).await.unwrap_err();
+    assert!(
+        error.message.contains("array or list of numbers"),
+        "{}",
+        error.message
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[actix::test]
+async fn crashed_and_timed_out_workers_are_replaced() {
+    let path = write_script("restarts", SCRIPT);
+
+    let crashes = start(&path, "crashes", 1, None, None).unwrap();
+    let error = crashes.call(vec![-1.0], 0).await.unwrap_err();
+    assert!(error.message.contains("Python worker"), "{}", error.message);
+    assert_eq!(crashes.call(vec![1.0], 0).await.unwrap().0, vec![1.0]);
+
+    let sleeps = start(&path, "sleeps", 1, Some(300), None).unwrap();
+    let error = sleeps.call(vec![30.0], 0).await.unwrap_err();
+    assert!(error.message.contains("timed out"), "{}", error.message);
+    assert_eq!(error.limit.as_deref(), Some("timeout"));
+    assert_eq!(sleeps.call(vec![0.0], 0).await.unwrap().0, vec![0.0]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[actix::test]
+async fn allocations_past_the_memory_limit_fail_the_call() {
+    let path = write_script("memory", SCRIPT);
+    let allocates = start(&path, "allocates", 1, None, Some(512)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        allocates.call(vec![16.0], 0).await.unwrap().0,
+        vec![16.0 * 1024.0 * 1024.0]
+    );
+    let error = allocates.call(vec![4096.0], 0).await.unwrap_err();
+    assert!(error.message.contains("MemoryError"), "{}", error.message);
+    assert_eq!(error.limit.as_deref(), Some("memory"));
+    // The worker frees what it had allocated and keeps going
+    assert!(allocates.call(vec![16.0], 0).await.is_ok());
+}
+
+#[test]
+fn workers_that_cannot_import_the_script_fail_to_start() {
+    let path = write_script("broken", "print('loading')\nimport no_such_module\n");
+    let error = start(&path, "process", 2, None, None).err().unwrap();
+    assert!(error.contains("ModuleNotFoundError"), "{}", error);
+    assert!(error.contains("loading"), "{}", error);
+
+    let path_ok = write_script("missing", SCRIPT);
+    let missing = start(&path_ok, "nope", 1, None, None).err().unwrap();
+    assert!(missing.contains("nope"), "{}", missing);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&path_ok).unwrap();
+}
@@ -0,0 +1,191 @@
+// tests/remote.rs
+//
+// Builds requests with the remote MLModel client's json protocol params and sends them to an
+// in-process mock server that records what it received.
+
+mod common;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use actor_poc::steps::remote::RemoteModel;
+use common::start_mock_server;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A request as the mock server saw it.
+#[derive(Debug, Clone)]
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Value,
+}
+
+type Received = Arc<Mutex<Vec<Request>>>;
+
+/// Records the request and answers with two rows of scores under `result.scores`, after half a
+/// second on `/slow`.
+async fn record(
+    request: HttpRequest,
+    body: web::Json<Value>,
+    received: web::Data<Received>,
+) -> HttpResponse {
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+        .collect();
+    received.lock().unwrap().push(Request {
+        method: request.method().to_string(),
+        path: request.path().to_string(),
+        headers,
+        body: body.0.clone(),
+    });
+    if request.path() == "/slow" {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    HttpResponse::Ok().json(json!({ "result": { "scores": [[1.0, 2.0], [3.0]], "model": "m" } }))
+}
+
+/// The mock server's routes: every request is recorded.
+fn routes(config: &mut web::ServiceConfig) {
+    config.default_service(web::to(record));
+}
+
+/// The value of a (lowercase) header of a recorded request.
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[actix::test]
+async fn field_paths_nest_the_request_and_pick_the_response() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+    let model = RemoteModel::from_params(
+        &format!("{}/score", base_url),
+        &json!({
+            "method": "put",
+            "request_field": "inputs.data",
+            "response_field": "result.scores.1"
+        }),
+    )
+    .unwrap();
+
+    let predictions = model.predict(&[1.0, 2.5], 1).await.unwrap();
+    assert_eq!(predictions, vec![vec![3.0]]);
+    let request = received.lock().unwrap()[0].clone();
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.path, "/score");
+    assert_eq!(request.body, json!({ "inputs": { "data": [1.0, 2.5] } }));
+
+    // Batched rows go under the same path, one array per row, and come back one entry per row
+    let model = RemoteModel::from_params(
+        &format!("{}/score", base_url),
+        &json!({ "request_field": "inputs.data", "response_field": "result.scores" }),
+    )
+    .unwrap();
+    let predictions = model.predict_rows(&[&[1.0], &[2.0, 3.0]]).await.unwrap();
+    assert_eq!(predictions, vec![vec![1.0, 2.0], vec![3.0]]);
+    assert_eq!(
+        received.lock().unwrap()[1].body,
+        json!({ "inputs": { "data": [[1.0], [2.0, 3.0]] } })
+    );
+
+    let model = RemoteModel::from_params(
+        &format!("{}/score", base_url),
+        &json!({ "response_field": "result.missing" }),
+    )
+    .unwrap();
+    let error = model.predict(&[1.0], 1).await.err().unwrap();
+    assert!(error.contains("no 'result.missing' field"), "{}", error);
+}
+
+#[actix::test]
+async fn static_and_environment_headers_are_sent() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+    std::env::set_var("REMOTE_TEST_TOKEN", "Bearer secret");
+    let model = RemoteModel::from_params(
+        &base_url,
+        &json!({
+            "headers": { "X-Model": "churn" },
+            "env_headers": { "Authorization": "REMOTE_TEST_TOKEN" },
+            "response_field": "result.scores.0"
+        }),
+    )
+    .unwrap();
+
+    assert_eq!(
+        model.predict(&[0.0], 1).await.unwrap(),
+        vec![vec![1.0, 2.0]]
+    );
+    let request = received.lock().unwrap()[0].clone();
+    assert_eq!(header(&request, "x-model"), Some("churn"));
+    assert_eq!(header(&request, "authorization"), Some("Bearer secret"));
+    assert_eq!(request.body, json!({ "features": [0.0] }));
+}
+
+#[test]
+fn a_missing_environment_variable_is_a_clear_error() {
+    let error = RemoteModel::from_params(
+        "http://localhost:1",
+        &json!({ "env_headers": { "Authorization": "REMOTE_TEST_UNSET_TOKEN" } }),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        error,
+        "Environment variable 'REMOTE_TEST_UNSET_TOKEN' for header 'Authorization' is not set"
+    );
+
+    for params in [
+        json!({ "headers": { "X-Model": 1 } }),
+        json!({ "headers": { "bad header": "x" } }),
+        json!({ "env_headers": { "Authorization": 1 } }),
+    ] {
+        assert!(
+            RemoteModel::from_params("http://localhost:1", &params).is_err(),
+            "{}",
+            params
+        );
+    }
+}
+
+#[actix::test]
+async fn slow_answers_and_connections_time_out() {
+    let base_url = start_mock_server(Received::default(), routes);
+    let model = RemoteModel::from_params(
+        &format!("{}/slow", base_url),
+        &json!({ "read_timeout_ms": 100, "response_field": "result.scores.0" }),
+    )
+    .unwrap();
+    let started = Instant::now();
+    let error = model.predict(&[1.0], 1).await.err().unwrap();
+    assert!(started.elapsed() < Duration::from_millis(400), "{}", error);
+    assert!(error.starts_with("Request to"), "{}", error);
+
+    // Without a read timeout the default of 30s waits for the answer
+    let model = RemoteModel::from_params(
+        &format!("{}/slow", base_url),
+        &json!({ "response_field": "result.scores.0" }),
+    )
+    .unwrap();
+    assert_eq!(
+        model.predict(&[1.0], 1).await.unwrap(),
+        vec![vec![1.0, 2.0]]
+    );
+
+    // A non-routable address never answers the connection
+    let model = RemoteModel::from_params(
+        "http://10.255.255.1:81",
+        &json!({ "connect_timeout_ms": 100 }),
+    )
+    .unwrap();
+    let started = Instant::now();
+    let error = model.predict(&[1.0], 1).await.err().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2), "{}", error);
+}
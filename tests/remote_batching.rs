@@ -0,0 +1,180 @@
+// tests/remote_batching.rs
+//
+// Batches messages to a remote MLModel speaking the json protocol and checks each message gets
+// the predictions for its own rows.
+
+mod common;
+
+use actix_web::{web, HttpResponse};
+use actor_poc::pipeline::Pipeline;
+use common::start_mock_server;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+type Received = Arc<Mutex<Vec<Value>>>;
+
+/// Answers each row with its sum and width, so rows of different widths give different answers.
+/// Like a real batch endpoint it only takes an array of rows.
+fn answer(features: &Value) -> Result<Value, String> {
+    let rows = features
+        .as_array()
+        .filter(|rows| rows.iter().all(Value::is_array))
+        .ok_or_else(|| format!("expected an array of rows, got {}", features))?;
+    Ok(json!(rows
+        .iter()
+        .map(|row| {
+            let row = row.as_array().unwrap();
+            let sum: f64 = row.iter().map(|v| v.as_f64().unwrap()).sum();
+            json!([sum, row.len()])
+        })
+        .collect::<Vec<_>>()))
+}
+
+/// The mock server's routes: `/predict` answers every row, `/short` only the first.
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route(
+            "/predict",
+            web::post().to(
+                |body: web::Json<Value>, received: web::Data<Received>| async move {
+                    received.lock().unwrap().push(body.0.clone());
+                    match answer(&body["features"]) {
+                        Ok(answer) => {
+                            HttpResponse::Ok().json(json!({ "processed_features": answer }))
+                        }
+                        Err(e) => HttpResponse::BadRequest().body(e),
+                    }
+                },
+            ),
+        )
+        .route(
+            "/short",
+            web::post().to(|body: web::Json<Value>| async move {
+                let first = answer(&body["features"]).unwrap()[0].clone();
+                HttpResponse::Ok().json(json!({ "processed_features": [first] }))
+            }),
+        );
+}
+
+/// A DAG sending every message to the remote model, in batches of up to three.
+fn config(endpoint: String, max_batch_wait_ms: u64) -> Value {
+    json!({ "steps": [
+        { "name": "gen", "type": "DataGenerator", "inputs": [], "outputs": ["raw"],
+          "params": { "limit": 1 } },
+        { "name": "model", "type": "MLModel", "inputs": ["raw"], "outputs": ["scored"],
+          "params": {
+              "remote_endpoint": endpoint,
+              "max_batch_size": 3,
+              "max_batch_wait_ms": max_batch_wait_ms
+          } },
+        { "name": "printer", "type": "Printer", "inputs": ["scored"], "outputs": [] }
+    ]})
+}
+
+#[test]
+fn each_message_of_a_batch_gets_its_own_prediction() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+    let pipeline = Pipeline::start(config(format!("{}/predict", base_url), 5000)).unwrap();
+
+    // Rows of different widths, as without a `feature_width`
+    let rows = [vec![1.0, 2.0], vec![10.0, 20.0, 30.0], vec![100.0]];
+    let receivers: Vec<_> = rows
+        .iter()
+        .map(|row| pipeline.submit(row.clone()))
+        .collect();
+    for (row, receiver) in rows.iter().zip(receivers) {
+        let output = pipeline.wait(receiver, TIMEOUT).unwrap();
+        assert!(output.trace.failure().is_none(), "{:?}", output.trace);
+        assert_eq!(output.data, vec![row.iter().sum(), row.len() as f64]);
+        let batch = output.trace.steps[0].batch.as_ref().unwrap();
+        assert_eq!(batch.size, 3);
+    }
+
+    // One request, with an array per row
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(
+        received[0],
+        json!({ "features": [[1.0, 2.0], [10.0, 20.0, 30.0], [100.0]] })
+    );
+}
+
+#[test]
+fn a_message_alone_when_the_wait_ends_is_still_sent_as_rows() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+    let pipeline = Pipeline::start(config(format!("{}/predict", base_url), 50)).unwrap();
+
+    let output = pipeline.process(vec![1.0, 2.0], TIMEOUT).unwrap();
+    assert!(output.trace.failure().is_none(), "{:?}", output.trace);
+    assert_eq!(output.data, vec![3.0, 2.0]);
+    let batch = output.trace.steps[0].batch.as_ref().unwrap();
+    assert_eq!(batch.size, 1);
+
+    let received = received.lock().unwrap();
+    assert_eq!(*received, [json!({ "features": [[1.0, 2.0]] })]);
+}
+
+#[test]
+fn a_response_without_a_row_for_every_row_fails_the_batch() {
+    let base_url = start_mock_server(Received::default(), routes);
+    let pipeline = Pipeline::start(config(format!("{}/short", base_url), 5000)).unwrap();
+
+    let receivers: Vec<_> = (0..3).map(|i| pipeline.submit(vec![i as f64])).collect();
+    for receiver in receivers {
+        let output = pipeline.wait(receiver, TIMEOUT).unwrap();
+        let (step, error) = output.trace.failure().unwrap();
+        assert_eq!(step, "model");
+        assert!(
+            error
+                .message
+                .contains("predictions for 1 rows but 3 were sent"),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn pooled_rows_split_back_to_the_messages_they_were_pooled_from() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+    let pipeline = Pipeline::start(json!({ "steps": [
+        { "name": "gen", "type": "DataGenerator", "inputs": [], "outputs": ["raw"],
+          "params": { "limit": 1 } },
+        { "name": "pool", "type": "BatchPooler", "inputs": ["raw"], "outputs": ["pooled"],
+          "params": { "window_size": 3 } },
+        { "name": "model", "type": "MLModel", "inputs": ["pooled"], "outputs": ["scored"],
+          "params": {
+              "remote_endpoint": format!("{}/predict", base_url),
+              "max_batch_size": 3,
+              "max_batch_wait_ms": 50,
+              "feature_width": 2,
+              "split_batch": true
+          } },
+        { "name": "printer", "type": "Printer", "inputs": ["scored"], "outputs": [] }
+    ]}))
+    .unwrap();
+
+    let rows = [vec![1.0, 2.0], vec![10.0, 20.0], vec![100.0, 200.0]];
+    let receivers: Vec<_> = rows
+        .iter()
+        .map(|row| pipeline.submit(row.clone()))
+        .collect();
+    for (row, receiver) in rows.iter().zip(receivers) {
+        let output = pipeline.wait(receiver, TIMEOUT).unwrap();
+        assert!(output.trace.failure().is_none(), "{:?}", output.trace);
+        assert_eq!(output.data, vec![row.iter().sum(), 2.0]);
+    }
+
+    // The pooled message went out as one request with a row per member
+    let received = received.lock().unwrap();
+    assert_eq!(
+        *received,
+        [json!({ "features": [[1.0, 2.0], [10.0, 20.0], [100.0, 200.0]] })]
+    );
+}
@@ -0,0 +1,152 @@
+// tests/remote_v2.rs
+//
+// Runs the remote MLModel client against an in-process mock of a KServe v2 model server.
+
+mod common;
+
+use actix_web::{web, HttpResponse};
+use actor_poc::steps::remote::RemoteModel;
+use common::start_mock_server;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+type Received = Arc<Mutex<Vec<(String, Value)>>>;
+
+/// Answers v2 inference requests for a two-class model: an INT64 `label` (1 when the row sums
+/// to more than 10), a BYTES `name` and FP32 `probabilities` per row.
+async fn infer(
+    request: actix_web::HttpRequest,
+    body: web::Json<Value>,
+    received: web::Data<Received>,
+) -> HttpResponse {
+    received
+        .lock()
+        .unwrap()
+        .push((request.path().to_string(), body.0.clone()));
+
+    let input = &body["inputs"][0];
+    let rows = input["shape"][0].as_u64().unwrap() as usize;
+    let width = input["shape"][1].as_u64().unwrap() as usize;
+    let data: Vec<f64> = input["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_f64().unwrap())
+        .collect();
+
+    let sums: Vec<f64> = data.chunks(width).map(|row| row.iter().sum()).collect();
+    let labels: Vec<i64> = sums.iter().map(|&sum| (sum > 10.0) as i64).collect();
+    let names: Vec<&str> = labels
+        .iter()
+        .map(|&label| if label == 1 { "yes" } else { "no" })
+        .collect();
+    let probabilities: Vec<f64> = labels
+        .iter()
+        .flat_map(|&label| if label == 1 { [0.2, 0.8] } else { [0.9, 0.1] })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "model_name": "churn",
+        "outputs": [
+            { "name": "label", "shape": [rows], "datatype": "INT64", "data": labels },
+            { "name": "name", "shape": [rows], "datatype": "BYTES", "data": names },
+            { "name": "probabilities", "shape": [rows, 2], "datatype": "FP32", "data": probabilities }
+        ]
+    }))
+}
+
+/// The mock server's routes: v2 inference for any model and version, and a 500 for `broken`.
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route(
+            "/v2/models/{name}/versions/{version}/infer",
+            web::post().to(infer),
+        )
+        .route(
+            "/v2/models/broken/infer",
+            web::post().to(|| async {
+                HttpResponse::InternalServerError().json(json!({ "error": "model failed" }))
+            }),
+        )
+        .route("/v2/models/{name}/infer", web::post().to(infer));
+}
+
+#[actix::test]
+async fn sends_typed_inputs_and_reads_typed_outputs() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+
+    let params = json!({
+        "protocol": "v2",
+        "model_name": "churn",
+        "model_version": 3,
+        "inputs": [{ "name": "features", "dtype": "float64", "indices": [0, 2] }],
+        "outputs": [
+            { "name": "probabilities" },
+            { "name": "name", "classes": ["no", "yes"] }
+        ]
+    });
+    let remote = RemoteModel::from_params(&base_url, &params).unwrap();
+
+    // Two rows of three features; only features 0 and 2 are sent
+    let predictions = remote
+        .predict(&[1.0, 100.0, 2.0, 5.0, 100.0, 6.0], 2)
+        .await
+        .unwrap();
+    assert_eq!(predictions, vec![vec![0.9, 0.1, 0.0], vec![0.2, 0.8, 1.0]]);
+
+    let received = received.lock().unwrap();
+    let (path, body) = &received[0];
+    assert_eq!(path, "/v2/models/churn/versions/3/infer");
+    assert_eq!(
+        body["inputs"][0],
+        json!({
+            "name": "features",
+            "shape": [2, 2],
+            "datatype": "FP64",
+            "data": [1.0, 2.0, 5.0, 6.0]
+        })
+    );
+    assert_eq!(
+        body["outputs"],
+        json!([{ "name": "probabilities" }, { "name": "name" }])
+    );
+}
+
+#[actix::test]
+async fn defaults_to_one_fp32_input_and_every_output() {
+    let received = Received::default();
+    let base_url = start_mock_server(received.clone(), routes);
+
+    let params = json!({ "protocol": "v2", "model_name": "churn" });
+    let remote = RemoteModel::from_params(&base_url, &params).unwrap();
+
+    // Without classes the BYTES output can't be converted
+    let error = remote.predict(&[20.0, 1.0], 1).await.unwrap_err();
+    assert!(error.contains("Label 'yes'"), "{}", error);
+
+    let received = received.lock().unwrap();
+    let (path, body) = &received[0];
+    assert_eq!(path, "/v2/models/churn/infer");
+    assert_eq!(body["inputs"][0]["name"], "input-0");
+    assert_eq!(body["inputs"][0]["datatype"], "FP32");
+    assert!(body.get("outputs").is_none());
+}
+
+#[actix::test]
+async fn non_success_status_is_an_error() {
+    let base_url = start_mock_server(Received::default(), routes);
+
+    let params = json!({ "protocol": "v2", "model_name": "broken" });
+    let remote = RemoteModel::from_params(&base_url, &params).unwrap();
+
+    let error = remote.predict(&[1.0], 1).await.unwrap_err();
+    assert!(error.contains("500"), "{}", error);
+    assert!(error.contains("model failed"), "{}", error);
+}
+
+#[test]
+fn v2_requires_a_model_name() {
+    let params = json!({ "protocol": "v2" });
+    assert!(RemoteModel::from_params("http://localhost:8000", &params).is_err());
+}
@@ -0,0 +1,65 @@
+// tests/schema.rs
+//
+// Parses records against a feature schema and checks message widths.
+
+use actor_poc::messages::ProcessMessage;
+use actor_poc::schema::{check_width, Schema};
+use serde_json::json;
+use std::sync::Arc;
+
+fn schema() -> Schema {
+    serde_json::from_value(json!([
+        "amount",
+        { "name": "count", "type": "int" },
+        { "name": "flagged", "type": "bool" }
+    ]))
+    .unwrap()
+}
+
+#[test]
+fn rows_parse_by_type_without_shifting() {
+    let schema = schema();
+    assert_eq!(
+        schema.parse_row(&["1.5", " 3", "true"]).unwrap(),
+        vec![1.5, 3.0, 1.0]
+    );
+
+    let missing = schema.parse_row(&["", "3", "0"]).unwrap();
+    assert!(missing[0].is_nan());
+    assert_eq!(&missing[1..], &[3.0, 0.0]);
+
+    // A bad cell rejects the row instead of dropping the cell and shifting the rest
+    assert!(schema.parse_row(&["abc", "3", "true"]).is_err());
+    assert!(schema.parse_row(&["1.5", "3.5", "true"]).is_err());
+    assert!(schema.parse_row(&["1.5", "3"]).is_err());
+}
+
+#[test]
+fn names_must_be_unique() {
+    assert!(serde_json::from_value::<Schema>(json!(["a", "b", "a"])).is_err());
+    assert!(Schema::from_names(["a", "", "b"]).is_err());
+    assert_eq!(Schema::from_names(["a", " b"]).unwrap().names(), ["a", "b"]);
+}
+
+#[test]
+fn messages_must_be_as_wide_as_their_schema() {
+    let mut msg = ProcessMessage {
+        id: 1,
+        node_id: "raw".to_string(),
+        data: vec![1.0, 2.0, 0.0],
+        batch_id: None,
+        batch_total: None,
+        trace: Default::default(),
+        member_ids: vec![],
+        schema: Some(Arc::new(schema())),
+    };
+    assert!(check_width(&msg).is_ok());
+
+    msg.data.push(4.0);
+    assert!(check_width(&msg).is_err());
+
+    // A pooled batch has one row per member
+    msg.data.extend([5.0, 6.0]);
+    msg.member_ids = vec![1, 2];
+    assert!(check_width(&msg).is_ok());
+}
@@ -0,0 +1,253 @@
+// tests/transforms.rs
+//
+// Runs FeatureProcessor transform lists over single messages.
+
+mod common;
+
+use actor_poc::schema::Schema;
+use actor_poc::steps::transforms::{
+    apply_transforms, check_fitted, fit_transforms, load_artifact, parse_transforms, schema_chain,
+    TransformArtifact,
+};
+use common::assert_close;
+use serde_json::json;
+
+fn run(transforms: serde_json::Value, data: &[f64]) -> Result<Vec<f64>, String> {
+    let transforms = parse_transforms(&json!({ "transforms": transforms }))?;
+    apply_transforms(&transforms, data, None)
+}
+
+#[test]
+fn no_transforms_passes_data_through() {
+    let transforms = parse_transforms(&json!({})).unwrap();
+    assert_eq!(
+        apply_transforms(&transforms, &[1.0, 2.0], None).unwrap(),
+        vec![1.0, 2.0]
+    );
+}
+
+#[test]
+fn scaling_uses_given_or_message_statistics() {
+    let scaled = run(json!([{ "type": "min_max", "min": 0.0 }]), &[1.0, 2.0, 4.0]).unwrap();
+    assert_close(&scaled, &[0.25, 0.5, 1.0]);
+
+    let scaled = run(
+        json!([{ "type": "min_max", "min": [0.0, 10.0], "max": [2.0, 20.0], "indices": [2, 0] }]),
+        &[15.0, 7.0, 1.0],
+    )
+    .unwrap();
+    assert_close(&scaled, &[0.5, 7.0, 0.5]);
+
+    let scaled = run(json!([{ "type": "z_score" }]), &[1.0, 3.0]).unwrap();
+    assert_close(&scaled, &[-1.0, 1.0]);
+
+    let scaled = run(
+        json!([{ "type": "z_score", "mean": 1.0, "std": 2.0 }]),
+        &[5.0],
+    )
+    .unwrap();
+    assert_close(&scaled, &[2.0]);
+}
+
+#[test]
+fn statistics_from_a_message_without_spread_scale_by_one() {
+    // One target, all-equal targets and values all below a given `min`
+    let scaled = run(json!([{ "type": "min_max", "indices": [1] }]), &[5.0, 3.0]).unwrap();
+    assert_close(&scaled, &[5.0, 0.0]);
+    let scaled = run(json!([{ "type": "min_max" }]), &[2.0, 2.0]).unwrap();
+    assert_close(&scaled, &[0.0, 0.0]);
+    let scaled = run(json!([{ "type": "min_max", "min": 0.0 }]), &[-2.0, 0.0]).unwrap();
+    assert_close(&scaled, &[-2.0, 0.0]);
+    let scaled = run(json!([{ "type": "z_score" }]), &[4.0, 4.0]).unwrap();
+    assert_close(&scaled, &[0.0, 0.0]);
+}
+
+#[test]
+fn transforms_run_in_order() {
+    let transformed = run(
+        json!([
+            { "type": "impute", "value": -1.0 },
+            { "type": "clip", "min": 0.0 },
+            { "type": "log1p", "indices": [1] },
+            { "type": "bucketize", "boundaries": [0.5, 2.0], "indices": [2] },
+            { "type": "select", "indices": [2, 1, 2] }
+        ]),
+        &[f64::NAN, std::f64::consts::E - 1.0, 3.0],
+    )
+    .unwrap();
+    assert_close(&transformed, &[2.0, 1.0, 2.0]);
+}
+
+#[test]
+fn polynomial_appends_powers_and_interactions() {
+    let expanded = run(json!([{ "type": "polynomial", "degree": 3 }]), &[2.0, 3.0]).unwrap();
+    assert_close(&expanded, &[2.0, 3.0, 4.0, 9.0, 8.0, 27.0]);
+
+    let expanded = run(
+        json!([{ "type": "polynomial", "degree": 2, "interactions": true }]),
+        &[2.0, 3.0, 5.0],
+    )
+    .unwrap();
+    assert_close(&expanded, &[2.0, 3.0, 5.0, 4.0, 9.0, 25.0, 6.0, 10.0, 15.0]);
+}
+
+#[test]
+fn power_raises_features_in_place() {
+    let squared = run(
+        json!([{ "type": "power", "exponent": 2 }]),
+        &[2.0, -3.0, 0.5],
+    )
+    .unwrap();
+    assert_close(&squared, &[4.0, 9.0, 0.25]);
+
+    let rooted = run(
+        json!([{ "type": "power", "exponent": 0.5, "indices": [1] }]),
+        &[4.0, 9.0],
+    )
+    .unwrap();
+    assert_close(&rooted, &[4.0, 3.0]);
+}
+
+#[test]
+fn bad_lists_are_rejected_at_load() {
+    for transforms in [
+        json!([{ "type": "unknown" }]),
+        json!([{ "type": "clip" }]),
+        json!([{ "type": "clip", "min": 0.0, "mx": 1.0 }]),
+        json!([{ "type": "min_max", "min": 1.0, "max": 1.0 }]),
+        json!([{ "type": "z_score", "std": 0.0 }]),
+        json!([{ "type": "bucketize", "boundaries": [2.0, 1.0] }]),
+        json!([{ "type": "bucketize" }]),
+        json!([{ "type": "category", "max_categories": 0 }]),
+        json!([{ "type": "polynomial", "degree": 1 }]),
+        json!([{ "type": "select", "indices": [] }]),
+        json!([{ "type": "impute", "value": [1.0, 2.0], "indices": [0] }]),
+    ] {
+        assert!(
+            parse_transforms(&json!({ "transforms": transforms })).is_err(),
+            "{}",
+            transforms
+        );
+    }
+}
+
+#[test]
+fn out_of_range_indices_fail_the_message() {
+    assert!(run(json!([{ "type": "select", "indices": [3] }]), &[1.0, 2.0]).is_err());
+    assert!(run(json!([{ "type": "log1p", "indices": [2] }]), &[1.0, 2.0]).is_err());
+}
+
+#[test]
+fn fitting_fills_in_statistics_from_rows() {
+    let transforms = parse_transforms(&json!({ "transforms": [
+        { "type": "z_score", "indices": [0] },
+        { "type": "min_max", "max": 10.0, "indices": [1] },
+        { "type": "bucketize", "quantiles": 2, "indices": [2] },
+        { "type": "category", "max_categories": 2, "indices": [3] }
+    ]}))
+    .unwrap();
+    assert!(check_fitted(&transforms).is_err());
+
+    let rows = vec![
+        vec![1.0, 2.0, 1.0, 7.0],
+        vec![3.0, 4.0, 2.0, 5.0],
+        vec![f64::NAN, 6.0, 3.0, 5.0],
+        vec![5.0, 8.0, 4.0, 9.0],
+    ];
+    let fitted = fit_transforms(&transforms, rows, None).unwrap();
+    check_fitted(&fitted).unwrap();
+
+    // The NaN is left out of the mean, min comes from the data, max from the params,
+    // the median splits the buckets and the vocabulary is ordered by frequency
+    let transformed = apply_transforms(&fitted, &[3.0, 6.0, 2.5, 7.0], None).unwrap();
+    let z = 1.0 / (8.0_f64 / 3.0).sqrt();
+    assert_close(&transformed[..2], &[0.0, 0.5]);
+    assert_close(&transformed[2..], &[0.0, 1.0]);
+    let transformed = apply_transforms(&fitted, &[5.0, 2.0, 3.0, 9.0], None).unwrap();
+    assert_close(&transformed, &[z * 2.0, 0.0, 1.0, -1.0]);
+
+    assert!(fit_transforms(&transforms, vec![vec![f64::NAN, 1.0, 1.0, 1.0]; 3], None).is_err());
+}
+
+#[test]
+fn constant_features_fit_a_unit_spread() {
+    let transforms = parse_transforms(&json!({ "transforms": [
+        { "type": "min_max", "indices": [0, 1] },
+        { "type": "z_score", "indices": [0, 1] }
+    ]}))
+    .unwrap();
+    let rows = vec![vec![2.0, 1.0], vec![2.0, 3.0], vec![f64::NAN, 5.0]];
+    let fitted = fit_transforms(&transforms, rows, None).unwrap();
+    check_fitted(&fitted).unwrap();
+
+    // The constant feature scales to 0 with a range of 1, then keeps its value with a std of 1
+    let transformed = apply_transforms(&fitted, &[2.0, 3.0], None).unwrap();
+    assert_close(&transformed, &[0.0, 0.0]);
+    let transformed = apply_transforms(&fitted, &[3.0, 5.0], None).unwrap();
+    let z = 0.5 / (1.0_f64 / 6.0).sqrt();
+    assert_close(&transformed, &[1.0, z]);
+}
+
+#[test]
+fn artifacts_must_match_the_step() {
+    let params = json!({ "transforms": [{ "type": "category" }] });
+    let transforms = parse_transforms(&params).unwrap();
+    let artifact = TransformArtifact {
+        step: "normalize".to_string(),
+        source: "train.csv".to_string(),
+        rows: 2,
+        transforms: fit_transforms(&transforms, vec![vec![4.0], vec![2.0]], None).unwrap(),
+    };
+    let path = std::env::temp_dir().join(format!("transforms_{}.json", std::process::id()));
+    std::fs::write(&path, serde_json::to_string(&artifact).unwrap()).unwrap();
+    let path = path.to_str().unwrap();
+
+    let loaded = load_artifact(path, &transforms).unwrap();
+    assert_close(&apply_transforms(&loaded, &[4.0], None).unwrap(), &[1.0]);
+    assert!(load_artifact(path, &[]).is_ok());
+
+    let other = parse_transforms(&json!({ "transforms": [{ "type": "log1p" }] })).unwrap();
+    assert!(load_artifact(path, &other).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn features_can_be_addressed_by_name() {
+    let schema: Schema = serde_json::from_value(json!(["amount", "age", "score"])).unwrap();
+    let transforms = parse_transforms(&json!({ "transforms": [
+        { "type": "clip", "max": 100.0, "indices": ["amount"] },
+        { "type": "select", "indices": ["score", "amount"] },
+        { "type": "polynomial", "degree": 2, "interactions": true }
+    ]}))
+    .unwrap();
+
+    let chain = schema_chain(&transforms, &schema).unwrap();
+    assert_eq!(
+        chain.last().unwrap().names(),
+        ["score", "amount", "score^2", "amount^2", "score*amount"]
+    );
+    let transformed = apply_transforms(&transforms, &[250.0, 40.0, 0.5], Some(&chain)).unwrap();
+    assert_close(&transformed, &[0.5, 100.0, 0.25, 10000.0, 50.0]);
+
+    // Names need a schema, and one that has them
+    assert!(apply_transforms(&transforms, &[250.0, 40.0, 0.5], None).is_err());
+    let other: Schema = serde_json::from_value(json!(["amount", "age"])).unwrap();
+    assert!(schema_chain(&transforms, &other).is_err());
+}
+
+#[test]
+fn a_feature_selected_twice_is_named_by_its_pick() {
+    let schema: Schema = serde_json::from_value(json!(["amount", "age"])).unwrap();
+    let transforms = parse_transforms(&json!({ "transforms": [
+        { "type": "select", "indices": ["age", "amount", 1, "age"] }
+    ]}))
+    .unwrap();
+
+    let chain = schema_chain(&transforms, &schema).unwrap();
+    assert_eq!(
+        chain.last().unwrap().names(),
+        ["age", "amount", "age#2", "age#3"]
+    );
+    let selected = apply_transforms(&transforms, &[250.0, 40.0], Some(&chain)).unwrap();
+    assert_close(&selected, &[40.0, 250.0, 40.0, 40.0]);
+}